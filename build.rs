@@ -1,7 +1,41 @@
+use std::env;
+use std::fs;
 use std::io::Result;
+use std::path::PathBuf;
 
 extern crate prost_build;
+
+const PROTOCOL: &str = "src/communication-protocol.proto";
+
 fn main() -> Result<()> {
-    prost_build::compile_protos(&["src/communication-protocol.proto"], &["src/"])?;
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", PROTOCOL);
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let protocol = fs::read_to_string(PROTOCOL)?
+        .lines()
+        .map(enable_feature_line)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let generated = out_dir.join("communication-protocol.proto");
+    fs::write(&generated, protocol)?;
+    prost_build::compile_protos(&[&generated], &[&out_dir])?;
     Ok(())
-}
\ No newline at end of file
+}
+
+// Protocol lines written as `// @feature(name) ...` only exist when the crate feature `name` is enabled,
+// so that the default build stays byte-compatible with the reference hub
+fn enable_feature_line(line: &str) -> String {
+    let content = line.trim_start();
+    let Some((feature, gated)) = content.strip_prefix("// @feature(").and_then(|rest| rest.split_once(')')) else {
+        return line.to_string();
+    };
+
+    let variable = format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+    if env::var_os(variable).is_none() {
+        return line.to_string();
+    }
+    let indent = &line[..line.len() - content.len()];
+    format!("{}{}", indent, gated.trim_start())
+}
@@ -0,0 +1,114 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use hmac::{Hmac, Mac};
+use prost::Message;
+use sha2::Sha256;
+use crate::Envelope;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Keys shared by pairs of processes, keyed by their listening ports. Messages between the two processes of
+/// a pair travel over an authenticated perfect link: the sender signs each NetworkMessage with their key, and
+/// the receiver drops the ones whose MAC does not match, so a process cannot pass itself off as another.
+/// Pairs without a key, like a process and the hub, stay plain perfect links.
+#[derive(Clone)]
+pub struct LinkKeys {
+    keys: HashMap<(i32, i32), Vec<u8>>,
+    /// Next sequence number to sign with, shared by the clones. It starts at the current time so that it keeps
+    /// increasing when a process restarts.
+    next_sequence_number: Arc<AtomicU64>,
+}
+
+impl Default for LinkKeys {
+    fn default() -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        LinkKeys {
+            keys: HashMap::new(),
+            next_sequence_number: Arc::new(AtomicU64::new(now.as_nanos() as u64)),
+        }
+    }
+}
+
+impl LinkKeys {
+    /// Share `key` between the processes listening on `first_port` and `second_port`
+    pub fn insert(&mut self, first_port: u16, second_port: u16, key: impl Into<Vec<u8>>) {
+        self.keys.insert(pair(first_port as i32, second_port as i32), key.into());
+    }
+
+    pub fn key(&self, first_port: i32, second_port: i32) -> Option<&[u8]> {
+        self.keys.get(&pair(first_port, second_port)).map(Vec::as_slice)
+    }
+
+    /// Set the sequence number and the MAC of a NetworkMessage wrapper going to `destination_port`, if its sender
+    /// shares a key with it
+    pub fn sign(&self, wrapper: &mut Envelope, destination_port: i32) {
+        let Some(network_message) = wrapper.network_message.as_mut() else { return };
+        let Some(key) = self.key(network_message.sender_listening_port, destination_port) else { return };
+
+        network_message.sequence_number = self.next_sequence_number.fetch_add(1, Ordering::SeqCst);
+        let mac = unsigned_hmac(key, wrapper).finalize().into_bytes().to_vec();
+        wrapper.network_message.as_mut().unwrap().mac = mac;
+    }
+
+    /// Whether a NetworkMessage wrapper received on `own_port` may be delivered: either its sender shares no
+    /// key with this process, or its MAC matches
+    pub fn verify(&self, wrapper: &Envelope, own_port: i32) -> bool {
+        let Some(network_message) = wrapper.network_message.as_ref() else { return false };
+        let Some(key) = self.key(network_message.sender_listening_port, own_port) else { return true };
+
+        unsigned_hmac(key, wrapper).verify_slice(&network_message.mac).is_ok()
+    }
+}
+
+// Only the pairs, so that logging a ClientState does not give the keys away
+impl fmt::Debug for LinkKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.keys.keys()).finish()
+    }
+}
+
+/// Sequence numbers of the authenticated messages delivered lately, per sender, so that a replayed message is not
+/// delivered twice. Only the last `SIZE` sequence numbers of a sender are remembered; anything older is dropped,
+/// which keeps the memory bounded while still letting messages overtake each other a little.
+#[derive(Debug, Default)]
+pub struct ReplayWindow {
+    /// Keyed by the sender's listening port
+    senders: HashMap<i32, BTreeSet<u64>>,
+}
+
+impl ReplayWindow {
+    pub const SIZE: usize = 1024;
+
+    /// Whether the message `sequence_number` from `sender_port` is new, remembering it if so
+    pub fn accept(&mut self, sender_port: i32, sequence_number: u64) -> bool {
+        let seen = self.senders.entry(sender_port).or_default();
+        if seen.len() == Self::SIZE && seen.first().is_some_and(|oldest| sequence_number < *oldest) {
+            return false;
+        }
+        if !seen.insert(sequence_number) {
+            return false;
+        }
+        if seen.len() > Self::SIZE {
+            seen.pop_first();
+        }
+        true
+    }
+}
+
+fn pair(first_port: i32, second_port: i32) -> (i32, i32) {
+    (first_port.min(second_port), first_port.max(second_port))
+}
+
+/// HMAC-SHA256 fed with `wrapper` marshalled with an empty MAC
+fn unsigned_hmac(key: &[u8], wrapper: &Envelope) -> HmacSha256 {
+    let mut unsigned = wrapper.clone();
+    if let Some(network_message) = unsigned.network_message.as_mut() {
+        network_message.mac.clear();
+    }
+    let mut hmac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    hmac.update(&unsigned.encode_to_vec());
+    hmac
+}
@@ -1,8 +1,82 @@
 use crate::{protobuf, Envelope};
 use crate::network_service::NetworkService;
 use crate::protobuf::message::Type;
+use std::str::FromStr;
 use std::sync::mpsc::Sender;
 use crate::protobuf::ProcessId;
+use crate::client::ClientState;
+use crate::{byzantine_broadcast_manager, causal_broadcast_manager, fifo_broadcast_manager, reliable_broadcast_manager};
+use crate::{total_order_broadcast_manager, uniform_reliable_broadcast_manager};
+
+/// Which broadcast abstraction carries the app-level broadcasts. Every process of a system must agree on it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BroadcastGuarantee {
+    /// Best-effort broadcast, as the reference processes do
+    #[default]
+    BestEffort,
+    /// Eager reliable broadcast
+    Reliable,
+    /// All-Ack uniform reliable broadcast; relies on the perfect failure detector
+    UniformAllAck,
+    /// Majority-Ack uniform reliable broadcast; delivers nothing unless a majority of the processes is correct
+    UniformMajorityAck,
+    /// FIFO reliable broadcast
+    Fifo,
+    /// Causal order broadcast
+    Causal,
+    /// Consensus-based total order broadcast; relies on the perfect failure detector
+    TotalOrder,
+    /// Byzantine consistent broadcast; needs authenticated links between every two processes
+    ByzantineConsistent,
+    /// Byzantine reliable broadcast; needs authenticated links between every two processes
+    ByzantineReliable,
+}
+
+impl BroadcastGuarantee {
+    /// Abstraction app-level broadcasts are handed to, `None` meaning a plain best-effort broadcast
+    pub fn abstraction_id(&self) -> Option<&'static str> {
+        match self {
+            BroadcastGuarantee::BestEffort => None,
+            BroadcastGuarantee::Reliable => Some(reliable_broadcast_manager::EAGER_ABSTRACTION_ID),
+            BroadcastGuarantee::UniformAllAck => Some(uniform_reliable_broadcast_manager::ALL_ACK_ABSTRACTION_ID),
+            BroadcastGuarantee::UniformMajorityAck => Some(uniform_reliable_broadcast_manager::MAJORITY_ACK_ABSTRACTION_ID),
+            BroadcastGuarantee::Fifo => Some(fifo_broadcast_manager::ABSTRACTION_ID),
+            BroadcastGuarantee::Causal => Some(causal_broadcast_manager::ABSTRACTION_ID),
+            BroadcastGuarantee::TotalOrder => Some(total_order_broadcast_manager::ABSTRACTION_ID),
+            BroadcastGuarantee::ByzantineConsistent => Some(byzantine_broadcast_manager::CONSISTENT_ABSTRACTION_ID),
+            BroadcastGuarantee::ByzantineReliable => Some(byzantine_broadcast_manager::RELIABLE_ABSTRACTION_ID),
+        }
+    }
+
+    /// Whether the values of each sender are delivered in the order it broadcast them
+    pub fn is_fifo(&self) -> bool {
+        matches!(self, BroadcastGuarantee::Fifo | BroadcastGuarantee::Causal)
+    }
+
+    /// Whether a value delivered by any correct process is delivered by all of them
+    pub fn is_total(&self) -> bool {
+        !matches!(self, BroadcastGuarantee::BestEffort | BroadcastGuarantee::ByzantineConsistent)
+    }
+}
+
+impl FromStr for BroadcastGuarantee {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "beb" => Ok(BroadcastGuarantee::BestEffort),
+            "rb" => Ok(BroadcastGuarantee::Reliable),
+            "aurb" => Ok(BroadcastGuarantee::UniformAllAck),
+            "murb" => Ok(BroadcastGuarantee::UniformMajorityAck),
+            "fifo" => Ok(BroadcastGuarantee::Fifo),
+            "causal" => Ok(BroadcastGuarantee::Causal),
+            "tob" => Ok(BroadcastGuarantee::TotalOrder),
+            "bcb" => Ok(BroadcastGuarantee::ByzantineConsistent),
+            "brb" => Ok(BroadcastGuarantee::ByzantineReliable),
+            _ => Err(format!("Unknown broadcast guarantee '{}', expected beb, rb, aurb, murb, fifo, causal, tob, bcb or brb", s)),
+        }
+    }
+}
 
 pub struct BroadcastManager {
 }
@@ -14,18 +88,56 @@ impl BroadcastManager {
         tx.send(inner).unwrap();
     }
 
-    pub fn do_beb_broadcast(message: Envelope, tx: &Sender<Envelope>, nodes: &Vec<ProcessId>, system_id: &str) {
-        for node in nodes {
-            let mut pl_send_msg = protobuf::PlSend::default();
-            pl_send_msg.message = NetworkService::wrap_envelope_contents(message.clone());
-            pl_send_msg.destination = Option::from(node.clone());
+    /// Send `message` to every process of the system. Unless `beb_loopback` is set, this process's own
+    /// copy skips the network and is queued right away as a BebDeliver sent by itself.
+    pub fn do_beb_broadcast(message: Envelope, tx: &Sender<Envelope>, client_state: &ClientState) {
+        for node in &client_state.nodes {
+            if node.port == client_state.own_port as i32 && !client_state.beb_loopback {
+                Self::deliver_locally(message.clone(), node.clone(), tx, &client_state.system_id);
+                continue;
+            }
+
+            let pl_send_msg = protobuf::PlSend {
+                message: NetworkService::wrap_envelope_contents(message.clone()),
+                destination: Option::from(node.clone()),
+            };
 
             let mut wrapped_pl_send = Envelope::with_shipping_label(Type::PlSend);
             wrapped_pl_send.pl_send = NetworkService::wrap_envelope_contents(pl_send_msg);
-            wrapped_pl_send.system_id = system_id.to_string();
+            wrapped_pl_send.system_id = client_state.system_id.to_string();
             wrapped_pl_send.to_abstraction_id = format!("{}.beb", message.to_abstraction_id);
 
             tx.send(wrapped_pl_send).unwrap()
         }
     }
-}
\ No newline at end of file
+
+    /// Sender and contents of a message that reached its abstraction through a perfect link, or through
+    /// a best-effort broadcast when it is this process's own copy
+    pub fn unwrap_delivery(message: Envelope) -> Option<(ProcessId, Envelope)> {
+        match message.r#type() {
+            Type::PlDeliver => {
+                let pl_deliver = message.pl_deliver?;
+                Some((pl_deliver.sender.unwrap_or_default(), *pl_deliver.message?))
+            },
+            Type::BebDeliver => {
+                let beb_deliver = message.beb_deliver?;
+                Some((beb_deliver.sender.unwrap_or_default(), *beb_deliver.message?))
+            },
+            _ => None,
+        }
+    }
+
+    fn deliver_locally(message: Envelope, own_process: ProcessId, tx: &Sender<Envelope>, system_id: &str) {
+        let beb_deliver = protobuf::BebDeliver {
+            sender: Option::from(own_process),
+            message: NetworkService::wrap_envelope_contents(message.clone()),
+        };
+
+        let mut wrapper = Envelope::with_shipping_label(Type::BebDeliver);
+        wrapper.beb_deliver = NetworkService::wrap_envelope_contents(beb_deliver);
+        wrapper.system_id = system_id.to_string();
+        wrapper.from_abstraction_id = format!("{}.beb", message.to_abstraction_id);
+        wrapper.to_abstraction_id = message.to_abstraction_id;
+        tx.send(wrapper).unwrap();
+    }
+}
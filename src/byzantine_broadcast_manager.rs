@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+use crate::{debug, protobuf, warn, Envelope};
+use crate::broadcast_manager::BroadcastManager;
+use crate::client::ClientState;
+use crate::network_service::NetworkService;
+use crate::perfect_link_manager::PerfectLinkManager;
+use crate::protobuf::message::Type;
+use crate::protobuf::ProcessId;
+use crate::reliable_broadcast_manager::deliver_to_parent;
+
+pub const CONSISTENT_ABSTRACTION_ID: &str = "app.bcb";
+pub const RELIABLE_ABSTRACTION_ID: &str = "app.brb";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Algorithm {
+    /// Delivers once more than (N+f)/2 processes echoed the same message
+    AuthenticatedEcho,
+    /// Echoes, then delivers once more than 2f processes are ready for the same message
+    DoubleEcho,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+    Send,
+    Echo,
+    Ready,
+}
+
+/// A SEND, ECHO or READY message, which all carry the same fields
+struct Part {
+    phase: Phase,
+    message_id: String,
+    source: ProcessId,
+    message: Envelope,
+}
+
+/// What this process saw of one message of one source
+#[derive(Default)]
+struct Instance {
+    /// Whether this process echoed a message, which it only does for the first SEND of the source
+    echoed: bool,
+    /// Whether this process said it is ready for a message, which it only does once
+    ready: bool,
+    delivered: bool,
+    /// Message echoed by each process, keyed by its port; later echoes of the same process are ignored
+    echoes: HashMap<i32, Envelope>,
+    /// Message each process is ready for, keyed by its port
+    readies: HashMap<i32, Envelope>,
+}
+
+/// Byzantine broadcast: every correct process delivers the same message for a given source and message id,
+/// even if up to f of the N > 3f processes, the source included, behave arbitrarily. The consistent variant
+/// only promises that; the reliable one also promises that if a correct process delivers, all of them do.
+/// Both count on authenticated perfect links between every two processes, so that nobody can echo on
+/// behalf of another.
+pub struct ByzantineBroadcastManager {
+    abstraction_id: &'static str,
+    algorithm: Algorithm,
+    /// Keyed by the source's port and the message id it chose
+    instances: HashMap<(i32, String), Instance>,
+    tx: Sender<Envelope>,
+}
+
+impl ByzantineBroadcastManager {
+    /// Byzantine Consistent Broadcast, Authenticated Echo Broadcast, under `app.bcb`
+    pub fn consistent(tx: Sender<Envelope>) -> Self {
+        ByzantineBroadcastManager {
+            abstraction_id: CONSISTENT_ABSTRACTION_ID,
+            algorithm: Algorithm::AuthenticatedEcho,
+            instances: HashMap::new(),
+            tx,
+        }
+    }
+
+    /// Byzantine Reliable Broadcast, Authenticated Double-Echo Broadcast (Bracha), under `app.brb`
+    pub fn reliable(tx: Sender<Envelope>) -> Self {
+        ByzantineBroadcastManager {
+            abstraction_id: RELIABLE_ABSTRACTION_ID,
+            algorithm: Algorithm::DoubleEcho,
+            ..Self::consistent(tx)
+        }
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        match message.r#type() {
+            Type::RbBroadcast => self.broadcast(message, client_state),
+            Type::PlDeliver | Type::BebDeliver => {
+                let (sender, inner) = BroadcastManager::unwrap_delivery(message).unwrap();
+                match Part::from_envelope(&inner) {
+                    Some(part) => self.handle_part(part, sender, client_state),
+                    None => warn!("{} got an unknown message type: {:?}", self.abstraction_id, inner.r#type()),
+                }
+            },
+            Type::PlSend => PerfectLinkManager::handle_pl_send(message, &client_state.system_id, client_state.own_port, &client_state.link_keys),
+            _ => warn!("{} got an unknown message type: {:?}", self.abstraction_id, message.r#type()),
+        }
+    }
+
+    fn broadcast(&mut self, message: Envelope, client_state: &ClientState) {
+        let Some(source) = client_state.own_process().cloned() else {
+            warn!("{} cannot broadcast outside of a system", self.abstraction_id);
+            return;
+        };
+        let inner = *message.rb_broadcast.unwrap().message.unwrap();
+
+        let part = Part { phase: Phase::Send, message_id: inner.message_uuid.clone(), source, message: inner };
+        send_to_all(self.abstraction_id, part, &self.tx, client_state);
+    }
+
+    fn handle_part(&mut self, part: Part, sender: ProcessId, client_state: &ClientState) {
+        let Some(source) = client_state.nodes.iter().find(|node| node.port == part.source.port).cloned() else {
+            warn!("{} got message {} from a source that is not in the system", self.abstraction_id, part.message_id);
+            return;
+        };
+        if !client_state.nodes.iter().any(|node| node.port == sender.port) {
+            warn!("{} got a {:?} from port {}, which is not in the system", self.abstraction_id, part.phase, sender.port);
+            return;
+        }
+
+        let key = (source.port, part.message_id.clone());
+        let instance = self.instances.entry(key.clone()).or_default();
+        match part.phase {
+            Phase::Send => {
+                if sender.port != source.port {
+                    warn!("{} ignored a SEND of message {} from port {} on behalf of {}-{}",
+                          self.abstraction_id, part.message_id, sender.port, source.owner, source.index);
+                    return;
+                }
+                if instance.echoed {
+                    debug!("{} ignored another SEND of message {} from {}-{}",
+                           self.abstraction_id, part.message_id, source.owner, source.index);
+                    return;
+                }
+                instance.echoed = true;
+                let echo = Part { phase: Phase::Echo, source, ..part };
+                send_to_all(self.abstraction_id, echo, &self.tx, client_state);
+                return;
+            },
+            Phase::Echo | Phase::Ready => {
+                let replies = if part.phase == Phase::Echo { &mut instance.echoes } else { &mut instance.readies };
+                if replies.contains_key(&sender.port) {
+                    debug!("{} ignored a second {:?} of message {} from port {}",
+                           self.abstraction_id, part.phase, part.message_id, sender.port);
+                    return;
+                }
+                replies.insert(sender.port, part.message.clone());
+            },
+        }
+        self.advance(&key, Part { source, ..part }, client_state);
+    }
+
+    /// Say this process is ready for, or deliver, the message `part` carries once enough processes back it
+    fn advance(&mut self, key: &(i32, String), part: Part, client_state: &ClientState) {
+        let system_size = client_state.nodes.len();
+        let faulty = tolerated_faults(system_size);
+        let instance = self.instances.get_mut(key).unwrap();
+        let echoes = instance.echoes.values().filter(|message| **message == part.message).count();
+        let readies = instance.readies.values().filter(|message| **message == part.message).count();
+        let byzantine_quorum = echoes > (system_size + faulty) / 2;
+
+        let deliver = match self.algorithm {
+            Algorithm::AuthenticatedEcho => byzantine_quorum,
+            Algorithm::DoubleEcho => {
+                if !instance.ready && (byzantine_quorum || readies > faulty) {
+                    instance.ready = true;
+                    let ready = Part { phase: Phase::Ready, message_id: part.message_id.clone(),
+                        source: part.source.clone(), message: part.message.clone() };
+                    send_to_all(self.abstraction_id, ready, &self.tx, client_state);
+                }
+                readies > 2 * faulty
+            },
+        };
+        if deliver && !instance.delivered {
+            instance.delivered = true;
+            deliver_to_parent(self.abstraction_id, part.message, part.source, &self.tx);
+        }
+    }
+}
+
+/// Largest f such that a system of `system_size` processes has more than 3f of them
+pub fn tolerated_faults(system_size: usize) -> usize {
+    system_size.saturating_sub(1) / 3
+}
+
+fn send_to_all(abstraction_id: &str, part: Part, tx: &Sender<Envelope>, client_state: &ClientState) {
+    let mut wrapper = part.into_envelope();
+    wrapper.from_abstraction_id = abstraction_id.to_string();
+    wrapper.to_abstraction_id = abstraction_id.to_string();
+    BroadcastManager::do_beb_broadcast(wrapper, tx, client_state);
+}
+
+impl Part {
+    fn from_envelope(envelope: &Envelope) -> Option<Self> {
+        let (phase, message_id, source, message) = match (&envelope.byz_internal_send, &envelope.byz_internal_echo,
+                                                          &envelope.byz_internal_ready) {
+            (Some(send), _, _) => (Phase::Send, &send.message_id, &send.source, &send.message),
+            (_, Some(echo), _) => (Phase::Echo, &echo.message_id, &echo.source, &echo.message),
+            (_, _, Some(ready)) => (Phase::Ready, &ready.message_id, &ready.source, &ready.message),
+            _ => return None,
+        };
+        Some(Part {
+            phase,
+            message_id: message_id.clone(),
+            source: source.clone()?,
+            message: *message.clone()?,
+        })
+    }
+
+    fn into_envelope(self) -> Envelope {
+        let message = NetworkService::wrap_envelope_contents(self.message);
+        let source = Option::from(self.source);
+        match self.phase {
+            Phase::Send => {
+                let mut wrapper = Envelope::with_shipping_label(Type::ByzInternalSend);
+                wrapper.byz_internal_send = NetworkService::wrap_envelope_contents(
+                    protobuf::ByzInternalSend { message_id: self.message_id, source, message });
+                wrapper
+            },
+            Phase::Echo => {
+                let mut wrapper = Envelope::with_shipping_label(Type::ByzInternalEcho);
+                wrapper.byz_internal_echo = NetworkService::wrap_envelope_contents(
+                    protobuf::ByzInternalEcho { message_id: self.message_id, source, message });
+                wrapper
+            },
+            Phase::Ready => {
+                let mut wrapper = Envelope::with_shipping_label(Type::ByzInternalReady);
+                wrapper.byz_internal_ready = NetworkService::wrap_envelope_contents(
+                    protobuf::ByzInternalReady { message_id: self.message_id, source, message });
+                wrapper
+            },
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::mpsc::Sender;
+use crate::{protobuf, warn, Envelope};
+use crate::client::ClientState;
+use crate::network_service::NetworkService;
+use crate::protobuf::message::Type;
+use crate::protobuf::ProcessId;
+use crate::reliable_broadcast_manager::{deliver_to_parent, request_broadcast, ReliableBroadcastManager};
+
+pub const ABSTRACTION_ID: &str = "app.crb";
+
+/// Causal order broadcast, Waiting Causal Broadcast: a message is only delivered after every message
+/// delivered by its sender before broadcasting it. Each message carries the vector clock of its
+/// sender, with one entry per process ordered by rank; it waits in `pending` until this process's
+/// clock has caught up with it.
+pub struct CausalBroadcastManager {
+    rb: ReliableBroadcastManager,
+    /// Messages delivered from each process, by rank order; empty until the system is known
+    vector_clock: Vec<i64>,
+    /// Messages broadcast by this process so far
+    last_sequence_number: i64,
+    /// Messages delivered by the eager reliable broadcast but not yet by this abstraction
+    pending: Vec<(ProcessId, Vec<i64>, Envelope)>,
+    tx: Sender<Envelope>,
+}
+
+impl CausalBroadcastManager {
+    pub fn new(tx: Sender<Envelope>) -> Self {
+        CausalBroadcastManager {
+            rb: ReliableBroadcastManager::eager(ABSTRACTION_ID, tx.clone()),
+            vector_clock: Vec::new(),
+            last_sequence_number: 0,
+            pending: Vec::new(),
+            tx,
+        }
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        if message.to_abstraction_id.starts_with(self.rb.abstraction_id()) {
+            self.rb.handle_message(message, client_state);
+            return;
+        }
+        if self.vector_clock.is_empty() {
+            self.vector_clock = vec![0; client_state.nodes.len()];
+        }
+
+        match message.r#type() {
+            Type::RbBroadcast => self.broadcast(message, client_state),
+            Type::RbDeliver => {
+                let rb_deliver = message.rb_deliver.unwrap();
+                let sender = rb_deliver.sender.unwrap_or_default();
+                match rb_deliver.message.and_then(|inner| inner.crb_internal_data) {
+                    Some(data) => self.handle_data(*data, sender, client_state),
+                    None => warn!("{} got a delivery it cannot read", ABSTRACTION_ID),
+                }
+            },
+            _ => warn!("{} got an unknown message type: {:?}", ABSTRACTION_ID, message.r#type()),
+        }
+    }
+
+    fn broadcast(&mut self, message: Envelope, client_state: &ClientState) {
+        let Some(own_slot) = slot(client_state, client_state.rank) else {
+            warn!("{} cannot broadcast outside of a system", ABSTRACTION_ID);
+            return;
+        };
+        let inner = *message.rb_broadcast.unwrap().message.unwrap();
+
+        let mut data = protobuf::CrbInternalData {
+            vector_clock: self.vector_clock.clone(),
+            ..Default::default()
+        };
+        data.vector_clock[own_slot] = self.last_sequence_number;
+        data.message = NetworkService::wrap_envelope_contents(inner);
+        self.last_sequence_number += 1;
+
+        let mut wrapper = Envelope::with_shipping_label(Type::CrbInternalData);
+        wrapper.crb_internal_data = NetworkService::wrap_envelope_contents(data);
+        request_broadcast(self.rb.abstraction_id(), wrapper, &self.tx);
+    }
+
+    fn handle_data(&mut self, data: protobuf::CrbInternalData, sender: ProcessId, client_state: &ClientState) {
+        let Some(inner) = data.message else {
+            warn!("{} got an empty message from {}-{}", ABSTRACTION_ID, sender.owner, sender.index);
+            return;
+        };
+        if data.vector_clock.len() != self.vector_clock.len() || slot(client_state, sender.rank).is_none() {
+            warn!("{} dropped a message of {}-{} with a vector clock of {} entries",
+                  ABSTRACTION_ID, sender.owner, sender.index, data.vector_clock.len());
+            return;
+        }
+        self.pending.push((sender, data.vector_clock, *inner));
+
+        while let Some(position) = self.pending.iter().position(|(_, clock, _)| {
+            clock.iter().zip(&self.vector_clock).all(|(theirs, ours)| theirs <= ours)
+        }) {
+            let (sender, _, message) = self.pending.remove(position);
+            self.vector_clock[slot(client_state, sender.rank).unwrap()] += 1;
+            deliver_to_parent(ABSTRACTION_ID, message, sender, &self.tx);
+        }
+    }
+}
+
+/// Entry of the process of rank `rank` in a vector clock
+fn slot(client_state: &ClientState, rank: i32) -> Option<usize> {
+    let mut ranks = client_state.nodes.iter().map(|node| node.rank).collect::<Vec<_>>();
+    ranks.sort_unstable();
+    ranks.binary_search(&rank).ok()
+}
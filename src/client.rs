@@ -1,13 +1,37 @@
-use crate::network_service::NetworkService;
 use crate::protobuf::message::Type;
 use crate::protobuf::ProcessId;
-use crate::{protobuf, Envelope};
+use crate::{debug, info, protobuf, trace, warn, Envelope};
+use std::collections::HashMap;
+use std::fmt;
 use std::net::{SocketAddr};
-use std::sync::mpsc::{Receiver, Sender};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use uuid::Uuid;
-use crate::broadcast_manager::BroadcastManager;
+use crate::authenticated_link::LinkKeys;
+use crate::broadcast_manager::{BroadcastGuarantee, BroadcastManager};
 use crate::perfect_link_manager::PerfectLinkManager;
-use crate::register_manager::RegisterManager;
+use crate::failure_detector::{self, PerfectFailureDetector};
+use crate::register_log::RegisterLog;
+use crate::node::NodeConfig;
+use crate::register_manager::{register_name, ReadMetrics, RegisterKind, RegisterManager, RegisterSummary};
+use crate::regular_register_manager::RegularRegisterManager;
+use crate::reliable_broadcast_manager::{self, request_broadcast, ReliableBroadcastManager};
+use crate::uniform_reliable_broadcast_manager::{self, UniformReliableBroadcastManager};
+use crate::fifo_broadcast_manager::{self, FifoBroadcastManager};
+use crate::causal_broadcast_manager::{self, CausalBroadcastManager};
+use crate::total_order_broadcast_manager::{self, TotalOrderBroadcastManager};
+use crate::consensus_manager::{self, ConsensusManager};
+use crate::probabilistic_broadcast_manager::{self, GossipMetrics, ProbabilisticBroadcastManager};
+use crate::byzantine_broadcast_manager::{self, ByzantineBroadcastManager};
+use crate::terminating_reliable_broadcast_manager::{self, TerminatingReliableBroadcastManager};
+#[cfg(feature = "nbac")]
+use crate::non_blocking_atomic_commit_manager::{self, NonBlockingAtomicCommitManager};
+use crate::single_writer_register_manager::SingleWriterRegisterManager;
+use crate::value_codec::{I32Codec, RegisterCodec, ValueCodec};
+#[cfg(feature = "value-payload")]
+use crate::value_codec::{BytesCodec, StringCodec};
 
 pub struct Client {
     rx: Receiver<Envelope>,
@@ -17,32 +41,177 @@ pub struct Client {
     nodes: Vec<ProcessId>,
     system_id: String,
     rank: i32,
-    register_manager: RegisterManager,
+    registers: Registers<I32Codec>,
+    #[cfg(feature = "value-payload")]
+    bytes_registers: Registers<BytesCodec>,
+    #[cfg(feature = "value-payload")]
+    string_registers: Registers<StringCodec>,
+    register_log: Option<Arc<Mutex<RegisterLog>>>,
+    fast_reads: bool,
+    register_kinds: HashMap<String, RegisterKind>,
+    register_codecs: HashMap<String, RegisterCodec>,
+    failure_detector: PerfectFailureDetector,
+    eager_reliable_broadcast: ReliableBroadcastManager,
+    lazy_reliable_broadcast: ReliableBroadcastManager,
+    all_ack_uniform_broadcast: UniformReliableBroadcastManager,
+    majority_ack_uniform_broadcast: UniformReliableBroadcastManager,
+    fifo_broadcast: FifoBroadcastManager,
+    causal_broadcast: CausalBroadcastManager,
+    total_order_broadcast: TotalOrderBroadcastManager,
+    consensus: ConsensusManager,
+    probabilistic_broadcast: ProbabilisticBroadcastManager,
+    byzantine_consistent_broadcast: ByzantineBroadcastManager,
+    byzantine_reliable_broadcast: ByzantineBroadcastManager,
+    terminating_reliable_broadcast: TerminatingReliableBroadcastManager,
+    #[cfg(feature = "nbac")]
+    non_blocking_atomic_commit: NonBlockingAtomicCommitManager,
+    gossip_fanout: usize,
+    gossip_rounds: i32,
+    broadcast_guarantee: BroadcastGuarantee,
+    beb_loopback: bool,
+    link_keys: Arc<LinkKeys>,
+    /// App-level values this process broadcast in the current system
+    app_broadcasts: i64,
+    /// App-level values delivered from each process in the current system, keyed by its port
+    app_delivered: HashMap<i32, i64>,
+    shutdown: Arc<AtomicBool>,
+    status: Arc<Mutex<ProcessStatus>>,
 }
 
+/// The register managers of a client for the registers whose values go through `C`
+struct Registers<C: ValueCodec> {
+    atomic: RegisterManager<C>,
+    regular: RegularRegisterManager<C>,
+    single_writer: SingleWriterRegisterManager<C>,
+}
+
+/// How long the worker waits for a message before checking whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Upper bound on the messages handled while draining, in case handling keeps producing new ones
+const DRAIN_LIMIT: usize = 10_000;
+
+#[derive(Clone, Debug)]
 pub struct ClientState {
     pub own_port: u16,
     pub hub_socket: SocketAddr,
     pub nodes: Vec<ProcessId>,
     pub system_id: String,
     pub rank: i32,
+    /// Whether best-effort broadcasts send this process's own copy over the network instead of queueing it
+    pub beb_loopback: bool,
+    /// Keys shared with other processes, for the authenticated perfect links to them
+    pub link_keys: Arc<LinkKeys>,
+}
+
+/// Copy of a client's state that other threads can look at, refreshed after every message
+#[derive(Clone, Debug)]
+pub struct ProcessStatus {
+    pub state: ClientState,
+    pub registers: Vec<RegisterSummary>,
+    /// Reads of (N,N) atomic registers since the process joined its system
+    pub read_metrics: ReadMetrics,
+    /// Probabilistic broadcast traffic since the process joined its system
+    pub gossip_metrics: GossipMetrics,
+}
+
+impl ClientState {
+    /// This process, as listed by the hub when the system was initialized
+    pub fn own_process(&self) -> Option<&ProcessId> {
+        self.nodes.iter().find(|node| node.port == self.own_port as i32)
+    }
+
+    /// Name used in log lines, e.g. `sys-1/abc-2`
+    pub fn label(&self) -> String {
+        match self.own_process() {
+            Some(process) => format!("{}/{}-{}", self.system_id, process.owner, process.index),
+            None => format!("port-{}", self.own_port),
+        }
+    }
 }
 
 impl Client {
-    pub fn new(rx: Receiver<Envelope>, tx: Sender<Envelope>, own_port: u16, hub_socket: SocketAddr) -> Self {
+    pub fn new(rx: Receiver<Envelope>, tx: Sender<Envelope>, own_port: u16, config: &NodeConfig,
+               register_log: Option<RegisterLog>, shutdown: Arc<AtomicBool>) -> Self {
         let system_id = String::new();
+        let hub_socket = config.hub_address;
+        let register_log = register_log.map(|log| Arc::new(Mutex::new(log)));
+        let link_keys = Arc::new(config.link_keys.clone());
         Client { rx, tx: tx.clone(), own_port, nodes: vec![], system_id, hub_socket, rank: -1,
-            register_manager: RegisterManager::new(tx.clone()),
+            registers: Registers::new(&tx, &register_log, config.fast_reads, &config.register_kinds),
+            #[cfg(feature = "value-payload")]
+            bytes_registers: Registers::new(&tx, &register_log, config.fast_reads, &config.register_kinds),
+            #[cfg(feature = "value-payload")]
+            string_registers: Registers::new(&tx, &register_log, config.fast_reads, &config.register_kinds),
+            register_log,
+            fast_reads: config.fast_reads,
+            register_kinds: config.register_kinds.clone(),
+            register_codecs: config.register_codecs.clone(),
+            failure_detector: PerfectFailureDetector::default(),
+            eager_reliable_broadcast: ReliableBroadcastManager::eager("app", tx.clone()),
+            lazy_reliable_broadcast: ReliableBroadcastManager::lazy("app", tx.clone()),
+            all_ack_uniform_broadcast: UniformReliableBroadcastManager::all_ack(tx.clone()),
+            majority_ack_uniform_broadcast: UniformReliableBroadcastManager::majority_ack(tx.clone()),
+            fifo_broadcast: FifoBroadcastManager::new(tx.clone()),
+            causal_broadcast: CausalBroadcastManager::new(tx.clone()),
+            total_order_broadcast: TotalOrderBroadcastManager::new(tx.clone()),
+            consensus: ConsensusManager::new(tx.clone()),
+            probabilistic_broadcast: ProbabilisticBroadcastManager::new(config.gossip_fanout, config.gossip_rounds, tx.clone()),
+            byzantine_consistent_broadcast: ByzantineBroadcastManager::consistent(tx.clone()),
+            byzantine_reliable_broadcast: ByzantineBroadcastManager::reliable(tx.clone()),
+            terminating_reliable_broadcast: TerminatingReliableBroadcastManager::new(tx.clone()),
+            #[cfg(feature = "nbac")]
+            non_blocking_atomic_commit: NonBlockingAtomicCommitManager::new(tx.clone()),
+            gossip_fanout: config.gossip_fanout,
+            gossip_rounds: config.gossip_rounds,
+            broadcast_guarantee: config.broadcast_guarantee,
+            beb_loopback: config.beb_loopback,
+            link_keys: link_keys.clone(),
+            app_broadcasts: 0,
+            app_delivered: HashMap::new(),
+            shutdown,
+            status: Arc::new(Mutex::new(ProcessStatus {
+                state: ClientState {
+                    own_port, hub_socket, nodes: vec![], system_id: String::new(), rank: -1,
+                    beb_loopback: config.beb_loopback, link_keys,
+                },
+                registers: vec![],
+                read_metrics: ReadMetrics::default(),
+                gossip_metrics: GossipMetrics::default(),
+            })),
         }
     }
 
-    pub fn start_worker(&mut self) {
-        loop {
-            match self.rx.recv() {
-                Ok(msg) => Self::handle_message(self, msg),
+    /// Handle through which other threads can follow this client's state
+    pub fn status(&self) -> Arc<Mutex<ProcessStatus>> {
+        self.status.clone()
+    }
+
+    /// Handle messages until a shutdown is requested, then drain the queue.
+    /// Returns the number of register operations that had to be abandoned.
+    pub fn start_worker(&mut self) -> usize {
+        while !self.shutdown.load(Ordering::SeqCst) {
+            match self.rx.recv_timeout(POLL_INTERVAL) {
+                Ok(msg) => {
+                    Self::handle_message(self, msg);
+                    self.handle_timeouts();
+                    self.publish_status();
+                },
+                Err(RecvTimeoutError::Timeout) => self.handle_timeouts(),
                 Err(err) => panic!("{}", err)
             }
         };
+
+        let mut drained = 0;
+        while let Ok(msg) = self.rx.try_recv() {
+            self.handle_message(msg);
+            drained += 1;
+            if drained == DRAIN_LIMIT {
+                warn!("[Port {}] Gave up draining the queue after {} messages", self.own_port, drained);
+                break;
+            }
+        }
+
+        self.fail_pending_operations()
     }
     
     pub fn clone_state(&self) -> ClientState {
@@ -51,13 +220,134 @@ impl Client {
             hub_socket: self.hub_socket,
             nodes: self.nodes.clone(),
             system_id: self.system_id.clone(),
-            rank: self.rank
+            rank: self.rank,
+            beb_loopback: self.beb_loopback,
+            link_keys: self.link_keys.clone(),
+        }
+    }
+
+    fn publish_status(&self) {
+        let mut status = self.status.lock().unwrap();
+        status.state = self.clone_state();
+        status.registers = self.registers.summaries();
+        status.read_metrics = self.registers.atomic.read_metrics();
+        #[cfg(feature = "value-payload")]
+        {
+            status.registers.extend(self.bytes_registers.summaries());
+            status.registers.extend(self.string_registers.summaries());
+            let (bytes, string) = (self.bytes_registers.atomic.read_metrics(), self.string_registers.atomic.read_metrics());
+            status.read_metrics.fast += bytes.fast + string.fast;
+            status.read_metrics.slow += bytes.slow + string.slow;
+        }
+        status.gossip_metrics = self.probabilistic_broadcast.metrics();
+    }
+
+    fn fail_pending_operations(&mut self) -> usize {
+        let failed = self.registers.fail_pending_operations();
+        #[cfg(feature = "value-payload")]
+        let failed = failed + self.bytes_registers.fail_pending_operations() + self.string_registers.fail_pending_operations();
+        failed
+    }
+
+    fn handle_timeouts(&mut self) {
+        let state = self.clone_state();
+        for process in self.failure_detector.handle_timeout(&state) {
+            self.registers.regular.handle_crash(&process, state.clone());
+            #[cfg(feature = "value-payload")]
+            {
+                self.bytes_registers.regular.handle_crash(&process, state.clone());
+                self.string_registers.regular.handle_crash(&process, state.clone());
+            }
+            self.lazy_reliable_broadcast.handle_crash(&process, &state);
+            self.all_ack_uniform_broadcast.handle_crash(&process, &state);
+            self.total_order_broadcast.handle_crash(&process, &state);
+            self.consensus.handle_crash(&process, &state);
+            self.terminating_reliable_broadcast.handle_crash(&process, &state);
+            #[cfg(feature = "nbac")]
+            self.non_blocking_atomic_commit.handle_crash(&process, &state);
         }
     }
 
     fn handle_message(&mut self, message: Envelope) {
-        if message.to_abstraction_id.starts_with("app.nnar") {
-            self.register_manager.handle_message(message, self.clone_state());
+        trace!("[Port {}] Handling {:?}", self.own_port, message);
+
+        if ["app.nnar", "app.onrr", "app.onar"].iter().any(|prefix| message.to_abstraction_id.starts_with(prefix)) {
+            let state = self.clone_state();
+            match self.register_codec(register_name(&message.to_abstraction_id).unwrap_or_default()) {
+                RegisterCodec::I32 => self.registers.handle_message(message, state),
+                #[cfg(feature = "value-payload")]
+                RegisterCodec::Bytes => self.bytes_registers.handle_message(message, state),
+                #[cfg(feature = "value-payload")]
+                RegisterCodec::String => self.string_registers.handle_message(message, state),
+            }
+            return
+        }
+        if message.to_abstraction_id.starts_with(failure_detector::ABSTRACTION_ID) {
+            self.failure_detector.handle_message(message, &self.clone_state());
+            return
+        }
+        if message.to_abstraction_id.starts_with(reliable_broadcast_manager::EAGER_ABSTRACTION_ID) {
+            self.eager_reliable_broadcast.handle_message(message, &self.clone_state());
+            return
+        }
+        if message.to_abstraction_id.starts_with(reliable_broadcast_manager::LAZY_ABSTRACTION_ID) {
+            // Lazy relaying depends on crashes being detected
+            self.failure_detector.start(&self.clone_state());
+            self.lazy_reliable_broadcast.handle_message(message, &self.clone_state());
+            return
+        }
+        if message.to_abstraction_id.starts_with(uniform_reliable_broadcast_manager::ALL_ACK_ABSTRACTION_ID) {
+            self.failure_detector.start(&self.clone_state());
+            self.all_ack_uniform_broadcast.handle_message(message, &self.clone_state());
+            return
+        }
+        if message.to_abstraction_id.starts_with(uniform_reliable_broadcast_manager::MAJORITY_ACK_ABSTRACTION_ID) {
+            self.majority_ack_uniform_broadcast.handle_message(message, &self.clone_state());
+            return
+        }
+        if message.to_abstraction_id.starts_with(fifo_broadcast_manager::ABSTRACTION_ID) {
+            self.fifo_broadcast.handle_message(message, &self.clone_state());
+            return
+        }
+        if message.to_abstraction_id.starts_with(causal_broadcast_manager::ABSTRACTION_ID) {
+            self.causal_broadcast.handle_message(message, &self.clone_state());
+            return
+        }
+        if message.to_abstraction_id.starts_with(total_order_broadcast_manager::ABSTRACTION_ID) {
+            // Its consensus moves past leaders once they are detected as crashed
+            self.failure_detector.start(&self.clone_state());
+            self.total_order_broadcast.handle_message(message, &self.clone_state());
+            return
+        }
+        if message.to_abstraction_id.starts_with(consensus_manager::ABSTRACTION_ID) {
+            // Rounds move past leaders once they are detected as crashed
+            self.failure_detector.start(&self.clone_state());
+            self.consensus.handle_message(message, &self.clone_state());
+            return
+        }
+        if message.to_abstraction_id.starts_with(probabilistic_broadcast_manager::ABSTRACTION_ID) {
+            self.probabilistic_broadcast.handle_message(message, &self.clone_state());
+            return
+        }
+        if message.to_abstraction_id.starts_with(byzantine_broadcast_manager::CONSISTENT_ABSTRACTION_ID) {
+            self.byzantine_consistent_broadcast.handle_message(message, &self.clone_state());
+            return
+        }
+        if message.to_abstraction_id.starts_with(byzantine_broadcast_manager::RELIABLE_ABSTRACTION_ID) {
+            self.byzantine_reliable_broadcast.handle_message(message, &self.clone_state());
+            return
+        }
+        if message.to_abstraction_id.starts_with(terminating_reliable_broadcast_manager::ABSTRACTION_ID) {
+            // Instances deliver a failed value once the sender is detected as crashed
+            self.failure_detector.start(&self.clone_state());
+            self.terminating_reliable_broadcast.handle_message(message, &self.clone_state());
+            return
+        }
+        #[cfg(feature = "nbac")]
+        if message.to_abstraction_id.starts_with(non_blocking_atomic_commit_manager::ABSTRACTION_ID) {
+            // Instances abort once any process is detected as crashed
+            self.failure_detector.start(&self.clone_state());
+            self.non_blocking_atomic_commit.handle_message(message, &self.clone_state());
             return
         }
         
@@ -66,18 +356,56 @@ impl Client {
                 let res = PerfectLinkManager::handle_pl_deliver(message);
                 self.handle_message(res);
             },
-            Type::PlSend => PerfectLinkManager::handle_pl_send(message, &self.system_id, self.own_port),
+            Type::PlSend => PerfectLinkManager::handle_pl_send(message, &self.system_id, self.own_port, &self.link_keys),
             
             Type::ProcInitializeSystem => self.handle_proc_initialize_system(message),
-            Type::BebBroadcast => BroadcastManager::do_beb_broadcast(message, &self.tx, &self.nodes, &self.system_id),
+            Type::ProcDestroySystem => self.handle_proc_destroy_system(message),
+            Type::BebBroadcast => BroadcastManager::do_beb_broadcast(message, &self.tx, &self.clone_state()),
             Type::BebDeliver => BroadcastManager::handle_beb_deliver(message, &self.tx),
+            Type::RbDeliver => {
+                let inner = *message.rb_deliver.unwrap().message.unwrap();
+                self.handle_message(inner);
+            },
             Type::AppBroadcast => self.handle_app_broadcast(message),
             Type::AppValue => self.handle_app_broadcast_value(message),
             Type::AppRead => self.handle_app_read(message),
             Type::AppWrite => self.handle_app_write(message),
-            
+            Type::AppTrbBroadcast => {
+                self.failure_detector.start(&self.clone_state());
+                self.terminating_reliable_broadcast.handle_message(message, &self.clone_state());
+            },
+            Type::AppTrbDeliver => self.handle_app_trb_deliver(message),
+            #[cfg(feature = "nbac")]
+            Type::AppNbacVote => {
+                self.failure_detector.start(&self.clone_state());
+                self.non_blocking_atomic_commit.handle_message(message, &self.clone_state());
+            },
+            #[cfg(feature = "nbac")]
+            Type::AppNbacDecide => {
+                if let Some(decision) = message.app_nbac_decide.as_ref() {
+                    info!("{} decided to {} '{}'", self.clone_state().label(),
+                          if decision.commit { "commit" } else { "abort" }, decision.transaction);
+                }
+                PerfectLinkManager::send_to_hub(message, &self.hub_socket, &self.tx);
+            },
+            Type::AppPropose => {
+                self.failure_detector.start(&self.clone_state());
+                self.consensus.handle_message(message, &self.clone_state());
+            },
+            // App-level consensus instances decide the AppDecide they were proposed
+            Type::HcDecide => match message.hc_decide.and_then(|decide| decide.value) {
+                Some(inner) => self.handle_message(*inner),
+                None => warn!("[Port {}] Got a consensus decision without a value", self.own_port),
+            },
+            Type::AppDecide => {
+                if let Some(value) = message.app_decide.as_ref().and_then(|decide| decide.value.as_ref()) {
+                    info!("{} decided {}", self.clone_state().label(), value);
+                }
+                PerfectLinkManager::send_to_hub(message, &self.hub_socket, &self.tx);
+            },
+
             _ => {
-                println!("Unknown message type received: {:?}", message)
+                warn!("Unknown message type received: {:?}", message)
             }
         }
     }
@@ -93,59 +421,211 @@ impl Client {
             .next()
             .expect("One of the nodes should be the one with this client's listening port");
 
-        println!("[Port {}] Got a list of the system's nodes: ", self.own_port);
+        info!("Starting system {} of process {}", self.system_id, self.clone_state().label());
         for process in &self.nodes {
-            println!("[{}] {:?}", self.own_port, process);
+            debug!("[{}] {:?}", self.own_port, process);
+        }
+
+        if self.register_kinds.values().any(|kind| *kind == RegisterKind::ReadOneWriteAll) {
+            self.failure_detector.start(&self.clone_state());
         }
     }
 
-    fn handle_app_broadcast(&self, message: Envelope) {
-        let value = message.app_broadcast.unwrap().value.unwrap();
-        let value = Option::from(value);
-        let value = protobuf::AppValue { value };
+    fn handle_proc_destroy_system(&mut self, message: Envelope) {
+        if message.system_id != self.system_id {
+            warn!("[Port {}] Ignoring the destruction of unknown system '{}'", self.own_port, message.system_id);
+            return;
+        }
+
+        let failed = self.fail_pending_operations();
+        self.registers = Registers::new(&self.tx, &self.register_log, self.fast_reads, &self.register_kinds);
+        #[cfg(feature = "value-payload")]
+        {
+            self.bytes_registers = Registers::new(&self.tx, &self.register_log, self.fast_reads, &self.register_kinds);
+            self.string_registers = Registers::new(&self.tx, &self.register_log, self.fast_reads, &self.register_kinds);
+        }
+        self.failure_detector = PerfectFailureDetector::default();
+        self.eager_reliable_broadcast = ReliableBroadcastManager::eager("app", self.tx.clone());
+        self.lazy_reliable_broadcast = ReliableBroadcastManager::lazy("app", self.tx.clone());
+        self.all_ack_uniform_broadcast = UniformReliableBroadcastManager::all_ack(self.tx.clone());
+        self.majority_ack_uniform_broadcast = UniformReliableBroadcastManager::majority_ack(self.tx.clone());
+        self.fifo_broadcast = FifoBroadcastManager::new(self.tx.clone());
+        self.causal_broadcast = CausalBroadcastManager::new(self.tx.clone());
+        self.total_order_broadcast = TotalOrderBroadcastManager::new(self.tx.clone());
+        self.consensus = ConsensusManager::new(self.tx.clone());
+        self.probabilistic_broadcast = ProbabilisticBroadcastManager::new(self.gossip_fanout, self.gossip_rounds, self.tx.clone());
+        self.byzantine_consistent_broadcast = ByzantineBroadcastManager::consistent(self.tx.clone());
+        self.byzantine_reliable_broadcast = ByzantineBroadcastManager::reliable(self.tx.clone());
+        self.terminating_reliable_broadcast = TerminatingReliableBroadcastManager::new(self.tx.clone());
+        #[cfg(feature = "nbac")]
+        {
+            self.non_blocking_atomic_commit = NonBlockingAtomicCommitManager::new(self.tx.clone());
+        }
+        self.app_broadcasts = 0;
+        self.app_delivered.clear();
+        self.nodes.clear();
+        self.system_id = String::new();
+        self.rank = -1;
+
+        info!("[Port {}] Destroyed system '{}', abandoning {} register operation(s)",
+                 self.own_port, message.system_id, failed);
+    }
+
+    fn handle_app_broadcast(&mut self, message: Envelope) {
+        let Some(sender) = self.clone_state().own_process().cloned() else {
+            warn!("[Port {}] Cannot broadcast outside of a system", self.own_port);
+            return;
+        };
+        self.app_broadcasts += 1;
+
+        let mut value = protobuf::AppValue {
+            value: message.app_broadcast.unwrap().value,
+            sender: Option::from(sender),
+            sequence_number: self.app_broadcasts,
+            ..Default::default()
+        };
+        if self.broadcast_guarantee == BroadcastGuarantee::Causal {
+            value.vector_clock = self.app_vector_clock();
+        }
 
         let mut app_value_wrapper = Envelope::with_shipping_label(Type::AppValue);
         app_value_wrapper.app_value = Option::from(value);
         app_value_wrapper.to_abstraction_id = "app".to_string();
 
-        BroadcastManager::do_beb_broadcast(app_value_wrapper, &self.tx, &self.nodes, &self.system_id);
+        match self.broadcast_guarantee.abstraction_id() {
+            Some(abstraction_id) => request_broadcast(abstraction_id, app_value_wrapper, &self.tx),
+            None => BroadcastManager::do_beb_broadcast(app_value_wrapper, &self.tx, &self.clone_state()),
+        }
+    }
+
+    /// App-level values delivered from each process by rank order, counting the earlier broadcasts of this one
+    fn app_vector_clock(&self) -> Vec<i64> {
+        let mut nodes = self.nodes.clone();
+        nodes.sort_by_key(|node| node.rank);
+        nodes.iter()
+            .map(|node| if node.port == self.own_port as i32 {
+                self.app_broadcasts - 1
+            } else {
+                self.app_delivered.get(&node.port).copied().unwrap_or(0)
+            })
+            .collect()
     }
 
     fn handle_app_broadcast_value(&mut self, message: Envelope) {
-        let mut pl_send = protobuf::PlSend::default();
-        pl_send.message = NetworkService::wrap_envelope_contents(message);
-        pl_send.destination = Option::from(ProcessId {
-            host: self.hub_socket.ip().to_string(),
-            port: self.hub_socket.port() as i32,
-            ..Default::default()
-        });
-        let mut pl_send_wrapper = Envelope::with_shipping_label(Type::PlSend);
-        pl_send_wrapper.pl_send = NetworkService::wrap_envelope_contents(pl_send);
+        if let Some(app_value) = message.app_value.as_ref() {
+            if let Some(sender) = &app_value.sender {
+                *self.app_delivered.entry(sender.port).or_default() += 1;
+            }
+            match (&app_value.value, &app_value.sender) {
+                (Some(value), Some(sender)) => info!("{} delivered {} (#{} of {}-{})", self.clone_state().label(),
+                                                     value, app_value.sequence_number, sender.owner, sender.index),
+                (Some(value), None) => info!("{} delivered {}", self.clone_state().label(), value),
+                (None, _) => {},
+            }
+        }
 
-        self.tx.send(pl_send_wrapper).unwrap();
+        PerfectLinkManager::send_to_hub(message, &self.hub_socket, &self.tx);
     }
     
+    fn handle_app_trb_deliver(&self, message: Envelope) {
+        if let Some(trb_deliver) = message.app_trb_deliver.as_ref() {
+            let sender = trb_deliver.sender.clone().unwrap_or_default();
+            match (&trb_deliver.value, trb_deliver.failed) {
+                (_, true) => info!("{} delivered that {}-{} failed on '{}'", self.clone_state().label(),
+                                   sender.owner, sender.index, trb_deliver.topic),
+                (Some(value), false) => info!("{} delivered {} from {}-{} on '{}'", self.clone_state().label(),
+                                              value, sender.owner, sender.index, trb_deliver.topic),
+                (None, false) => {},
+            }
+        }
+
+        PerfectLinkManager::send_to_hub(message, &self.hub_socket, &self.tx);
+    }
+
     fn handle_app_read(&self, message: Envelope) {
         let app_read = message.app_read.unwrap();
         let nnar_read = protobuf::NnarRead::default();
         let mut nnar_wrapper = Envelope::with_shipping_label(Type::NnarRead);
         nnar_wrapper.nnar_read = Option::from(nnar_read);
-        nnar_wrapper.to_abstraction_id = format!("app.nnar[{}]", app_read.register);
+        nnar_wrapper.to_abstraction_id = self.register_kind(&app_read.register).abstraction_id(&app_read.register);
         
         self.tx.send(nnar_wrapper).unwrap()
     }
 
     fn handle_app_write(&self, message: Envelope) {
         let app_write = message.app_write.unwrap();
-        let mut nnar_write = protobuf::NnarWrite::default();
-        nnar_write.value = app_write.value;
+        let nnar_write = protobuf::NnarWrite {
+            value: app_write.value,
+        };
 
         let mut nnar_wrapper = Envelope::with_shipping_label(Type::NnarWrite);
         nnar_wrapper.nnar_write = Option::from(nnar_write);
-        nnar_wrapper.to_abstraction_id = format!("app.nnar[{}]", app_write.register);
+        nnar_wrapper.to_abstraction_id = self.register_kind(&app_write.register).abstraction_id(&app_write.register);
 
         self.tx.send(nnar_wrapper).unwrap();
     }
+
+    fn register_kind(&self, register: &str) -> RegisterKind {
+        self.register_kinds.get(register).copied().unwrap_or_default()
+    }
+
+    fn register_codec(&self, register: &str) -> RegisterCodec {
+        self.register_codecs.get(register).copied().unwrap_or_default()
+    }
+}
+
+impl<C: ValueCodec> Registers<C> {
+    fn new(tx: &Sender<Envelope>, log: &Option<Arc<Mutex<RegisterLog>>>, fast_reads: bool,
+           kinds: &HashMap<String, RegisterKind>) -> Self {
+        let atomic = match log {
+            Some(log) => RegisterManager::with_log(tx.clone(), log.clone()),
+            None => RegisterManager::new(tx.clone()),
+        };
+        Registers {
+            atomic: atomic.with_fast_reads(fast_reads),
+            regular: RegularRegisterManager::new(tx.clone(), kinds.clone()),
+            single_writer: SingleWriterRegisterManager::new(tx.clone()),
+        }
+    }
+
+    fn handle_message(&mut self, message: Envelope, client_state: ClientState) {
+        if message.to_abstraction_id.starts_with("app.nnar") {
+            self.atomic.handle_message(message, client_state);
+        } else if message.to_abstraction_id.starts_with("app.onrr") {
+            self.regular.handle_message(message, client_state);
+        } else {
+            self.single_writer.handle_message(message, client_state);
+        }
+    }
+
+    fn summaries(&self) -> Vec<RegisterSummary> {
+        let mut summaries = self.atomic.summaries();
+        summaries.extend(self.regular.summaries());
+        summaries.extend(self.single_writer.summaries());
+        summaries
+    }
+
+    fn fail_pending_operations(&mut self) -> usize {
+        self.atomic.fail_pending_operations()
+            + self.regular.fail_pending_operations()
+            + self.single_writer.fail_pending_operations()
+    }
+}
+
+impl fmt::Display for protobuf::Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.defined {
+            return write!(f, "undefined");
+        }
+        #[cfg(feature = "value-payload")]
+        if !self.payload.is_empty() {
+            return match std::str::from_utf8(&self.payload) {
+                Ok(text) => write!(f, "{:?}", text),
+                Err(_) => write!(f, "<{} bytes>", self.payload.len()),
+            };
+        }
+        write!(f, "{}", self.v)
+    }
 }
 
 impl Envelope {
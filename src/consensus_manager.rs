@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+use crate::{protobuf, warn, Envelope};
+use crate::client::ClientState;
+use crate::hierarchical_consensus_manager::{request_propose, HierarchicalConsensusManager};
+use crate::protobuf::message::Type;
+use crate::protobuf::ProcessId;
+
+pub const ABSTRACTION_ID: &str = "app.hc";
+
+/// App-level consensus, one hierarchical consensus instance per topic under `app.hc[topic]`. The value of an
+/// AppPropose is proposed as the AppDecide the hub expects back, so the instance's HcDecide, which goes up to
+/// `app`, carries the answer as it is. It relies on the perfect failure detector running.
+pub struct ConsensusManager {
+    /// Keyed by topic
+    instances: HashMap<String, HierarchicalConsensusManager>,
+    /// Processes detected as crashed, for the instances started later
+    crashed: Vec<ProcessId>,
+    tx: Sender<Envelope>,
+}
+
+impl ConsensusManager {
+    pub fn new(tx: Sender<Envelope>) -> Self {
+        ConsensusManager {
+            instances: HashMap::new(),
+            crashed: Vec::new(),
+            tx,
+        }
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        if message.r#type() == Type::AppPropose {
+            let app_propose = message.app_propose.unwrap();
+            let abstraction_id = format!("{}[{}]", ABSTRACTION_ID, app_propose.topic);
+
+            let mut wrapper = Envelope::with_shipping_label(Type::AppDecide);
+            wrapper.app_decide = Option::from(protobuf::AppDecide { value: app_propose.value });
+            wrapper.to_abstraction_id = "app".to_string();
+            request_propose(&abstraction_id, wrapper, &self.tx);
+            return;
+        }
+
+        let Some(topic) = instance_topic(&message.to_abstraction_id) else {
+            warn!("{} got a {:?} for {}", ABSTRACTION_ID, message.r#type(), message.to_abstraction_id);
+            return;
+        };
+        let crashed = &self.crashed;
+        let tx = &self.tx;
+        self.instances.entry(topic.to_string())
+            .or_insert_with(|| HierarchicalConsensusManager::new(format!("{}[{}]", ABSTRACTION_ID, topic), crashed, tx.clone()))
+            .handle_message(message, client_state);
+    }
+
+    /// Called when the failure detector reports that `process` crashed
+    pub fn handle_crash(&mut self, process: &ProcessId, client_state: &ClientState) {
+        self.crashed.push(process.clone());
+        for instance in self.instances.values_mut() {
+            instance.handle_crash(process, client_state);
+        }
+    }
+}
+
+/// Topic of the instance an abstraction id such as `app.hc[t].beb.pl` belongs to
+fn instance_topic(abstraction_id: &str) -> Option<&str> {
+    let rest = abstraction_id.strip_prefix(ABSTRACTION_ID)?.strip_prefix('[')?;
+    rest.split_once(']').map(|(topic, _)| topic)
+}
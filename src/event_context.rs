@@ -0,0 +1,33 @@
+use crate::protobuf::ProcessId;
+use crate::Envelope;
+
+/// Where an event came from. It travels with the event through the handler chain, so that
+/// replies and quorum bookkeeping never depend on which message happened to be handled last.
+#[derive(Clone, Debug, Default)]
+pub struct EventContext {
+    /// Process that delivered the event through a link or a broadcast; `None` for local events
+    pub sender: Option<ProcessId>,
+    pub system_id: String,
+    pub message_uuid: String,
+    pub from_abstraction_id: String,
+}
+
+impl EventContext {
+    /// Context of an event raised locally, e.g. a request from the application
+    pub fn local(message: &Envelope) -> Self {
+        EventContext {
+            sender: None,
+            system_id: message.system_id.clone(),
+            message_uuid: message.message_uuid.clone(),
+            from_abstraction_id: message.from_abstraction_id.clone(),
+        }
+    }
+
+    /// Context of a message unwrapped from a PlDeliver or BebDeliver sent by `sender`
+    pub fn delivered(sender: ProcessId, message: &Envelope) -> Self {
+        EventContext {
+            sender: Some(sender),
+            ..Self::local(message)
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use crate::{info, warn, Envelope};
+use crate::client::ClientState;
+use crate::perfect_link_manager::PerfectLinkManager;
+use crate::protobuf::message::Type;
+use crate::protobuf::{self, ProcessId};
+
+/// How often heartbeats go out. A process that does not answer within one period is detected as crashed.
+const PERIOD: Duration = Duration::from_secs(1);
+
+pub const ABSTRACTION_ID: &str = "app.pfd";
+
+/// Perfect failure detector, Exclude on Timeout. One instance per process, shared by every
+/// abstraction that needs to know who crashed.
+pub struct PerfectFailureDetector {
+    /// Detection only runs once an abstraction asked for it; heartbeat requests are answered regardless
+    running: bool,
+    /// Ports of the processes that answered during the current period
+    alive: HashSet<i32>,
+    detected: Vec<ProcessId>,
+    next_timeout: Instant,
+}
+
+impl Default for PerfectFailureDetector {
+    fn default() -> Self {
+        PerfectFailureDetector {
+            running: false,
+            alive: HashSet::new(),
+            detected: vec![],
+            next_timeout: Instant::now(),
+        }
+    }
+}
+
+impl PerfectFailureDetector {
+    /// Start monitoring the processes of the system, if that is not already the case
+    pub fn start(&mut self, client_state: &ClientState) {
+        if self.running {
+            return;
+        }
+        self.running = true;
+        self.alive = client_state.nodes.iter().map(|node| node.port).collect();
+        self.send_heartbeat_requests(client_state);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Processes detected as crashed so far, in the order they were detected
+    pub fn detected(&self) -> &[ProcessId] {
+        &self.detected
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        let Some(pl_deliver) = message.pl_deliver else {
+            warn!("Failure detector got a {:?} it did not expect", message.r#type());
+            return;
+        };
+        let sender = pl_deliver.sender.unwrap_or_default();
+        let inner = *pl_deliver.message.unwrap();
+
+        match inner.r#type() {
+            Type::EpfdInternalHeartbeatRequest => {
+                let mut reply = Envelope::with_shipping_label(Type::EpfdInternalHeartbeatReply);
+                reply.epfd_internal_heartbeat_reply = Option::from(protobuf::EpfdInternalHeartbeatReply::default());
+                reply.to_abstraction_id = ABSTRACTION_ID.to_string();
+                PerfectLinkManager::send_to(reply, sender, ABSTRACTION_ID, client_state);
+            },
+            Type::EpfdInternalHeartbeatReply => {
+                self.alive.insert(sender.port);
+            },
+            _ => warn!("Failure detector got an unknown message type: {:?}", inner.r#type()),
+        }
+    }
+
+    /// Close the current period if it is over, returning the processes that have just been detected as crashed
+    pub fn handle_timeout(&mut self, client_state: &ClientState) -> Vec<ProcessId> {
+        if !self.running || Instant::now() < self.next_timeout {
+            return vec![];
+        }
+
+        let crashed = client_state.nodes.iter()
+            .filter(|node| !self.alive.contains(&node.port) && !self.detected.contains(node))
+            .cloned()
+            .collect::<Vec<_>>();
+        for process in &crashed {
+            info!("{} detected the crash of {}-{}", client_state.label(), process.owner, process.index);
+        }
+        self.detected.extend(crashed.iter().cloned());
+
+        self.alive.clear();
+        self.send_heartbeat_requests(client_state);
+        crashed
+    }
+
+    fn send_heartbeat_requests(&mut self, client_state: &ClientState) {
+        self.next_timeout = Instant::now() + PERIOD;
+        for node in client_state.nodes.iter().filter(|node| !self.detected.contains(node)) {
+            let mut request = Envelope::with_shipping_label(Type::EpfdInternalHeartbeatRequest);
+            request.epfd_internal_heartbeat_request = Option::from(protobuf::EpfdInternalHeartbeatRequest::default());
+            request.to_abstraction_id = ABSTRACTION_ID.to_string();
+            PerfectLinkManager::send_to(request, node.clone(), ABSTRACTION_ID, client_state);
+        }
+    }
+}
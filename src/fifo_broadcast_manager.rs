@@ -0,0 +1,93 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::Sender;
+use crate::{debug, protobuf, warn, Envelope};
+use crate::client::ClientState;
+use crate::network_service::NetworkService;
+use crate::protobuf::message::Type;
+use crate::protobuf::ProcessId;
+use crate::reliable_broadcast_manager::{deliver_to_parent, request_broadcast, ReliableBroadcastManager};
+
+pub const ABSTRACTION_ID: &str = "app.frb";
+
+/// FIFO reliable broadcast, Broadcast with Sequence Number: messages of the same sender are delivered
+/// in the order it broadcast them. Messages run over an eager reliable broadcast, which may deliver
+/// them in any order; those that arrive ahead of their turn wait in `pending`.
+pub struct FifoBroadcastManager {
+    rb: ReliableBroadcastManager,
+    /// Sequence number of the last message broadcast by this process
+    last_sequence_number: i64,
+    /// Sequence number expected next from each sender, keyed by its port
+    next: HashMap<i32, i64>,
+    /// Messages waiting for the ones their sender broadcast before, keyed by sender port and sequence number
+    pending: HashMap<i32, BTreeMap<i64, (ProcessId, Envelope)>>,
+    tx: Sender<Envelope>,
+}
+
+impl FifoBroadcastManager {
+    pub fn new(tx: Sender<Envelope>) -> Self {
+        FifoBroadcastManager {
+            rb: ReliableBroadcastManager::eager(ABSTRACTION_ID, tx.clone()),
+            last_sequence_number: 0,
+            next: HashMap::new(),
+            pending: HashMap::new(),
+            tx,
+        }
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        if message.to_abstraction_id.starts_with(self.rb.abstraction_id()) {
+            self.rb.handle_message(message, client_state);
+            return;
+        }
+
+        match message.r#type() {
+            Type::RbBroadcast => self.broadcast(message),
+            Type::RbDeliver => {
+                let rb_deliver = message.rb_deliver.unwrap();
+                let sender = rb_deliver.sender.unwrap_or_default();
+                match rb_deliver.message.and_then(|inner| inner.frb_internal_data) {
+                    Some(data) => self.handle_data(*data, sender),
+                    None => warn!("{} got a delivery it cannot read", ABSTRACTION_ID),
+                }
+            },
+            _ => warn!("{} got an unknown message type: {:?}", ABSTRACTION_ID, message.r#type()),
+        }
+    }
+
+    fn broadcast(&mut self, message: Envelope) {
+        let inner = *message.rb_broadcast.unwrap().message.unwrap();
+        self.last_sequence_number += 1;
+
+        let data = protobuf::FrbInternalData {
+            sequence_number: self.last_sequence_number,
+            message: NetworkService::wrap_envelope_contents(inner),
+        };
+
+        let mut wrapper = Envelope::with_shipping_label(Type::FrbInternalData);
+        wrapper.frb_internal_data = NetworkService::wrap_envelope_contents(data);
+        request_broadcast(self.rb.abstraction_id(), wrapper, &self.tx);
+    }
+
+    fn handle_data(&mut self, data: protobuf::FrbInternalData, sender: ProcessId) {
+        let Some(inner) = data.message else {
+            warn!("{} got an empty message from {}-{}", ABSTRACTION_ID, sender.owner, sender.index);
+            return;
+        };
+        let next = self.next.entry(sender.port).or_insert(1);
+        if data.sequence_number < *next {
+            warn!("{} got message {} of {}-{} again", ABSTRACTION_ID, data.sequence_number, sender.owner, sender.index);
+            return;
+        }
+        if data.sequence_number > *next {
+            debug!("{} holds message {} of {}-{} until {} arrives",
+                   ABSTRACTION_ID, data.sequence_number, sender.owner, sender.index, next);
+        }
+
+        let pending = self.pending.entry(sender.port).or_default();
+        pending.insert(data.sequence_number, (sender, *inner));
+        while let Some((sender, message)) = pending.remove(next) {
+            *next += 1;
+            deliver_to_parent(ABSTRACTION_ID, message, sender, &self.tx);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashSet;
+use std::sync::mpsc::Sender;
+use crate::{debug, protobuf, warn, Envelope};
+use crate::broadcast_manager::BroadcastManager;
+use crate::client::ClientState;
+use crate::network_service::NetworkService;
+use crate::perfect_link_manager::PerfectLinkManager;
+use crate::protobuf::message::Type;
+use crate::protobuf::ProcessId;
+use crate::reliable_broadcast_manager::parent_abstraction_id;
+
+/// One instance of consensus, Hierarchical Consensus: the processes take turns by rank, and each one
+/// decides on its proposal in its own round and imposes it on the processes that come after it.
+/// A round ends once its leader's decision arrives or the failure detector reports the leader crashed.
+pub struct HierarchicalConsensusManager {
+    abstraction_id: String,
+    /// Rank order of the process leading the current round
+    round: usize,
+    proposal: Option<Envelope>,
+    /// Rank order of the leader whose decision replaced the proposal
+    proposer: Option<usize>,
+    /// Rounds whose leader's decision arrived
+    delivered: HashSet<usize>,
+    /// Ports of the processes detected as crashed
+    crashed: HashSet<i32>,
+    /// Whether this process decided and told the others
+    broadcast: bool,
+    tx: Sender<Envelope>,
+}
+
+impl HierarchicalConsensusManager {
+    /// Instance under `abstraction_id`, e.g. `app.tob.hc[3]`; it needs the perfect failure detector running
+    pub fn new(abstraction_id: String, crashed: &[ProcessId], tx: Sender<Envelope>) -> Self {
+        HierarchicalConsensusManager {
+            abstraction_id,
+            round: 0,
+            proposal: None,
+            proposer: None,
+            delivered: HashSet::new(),
+            crashed: crashed.iter().map(|process| process.port).collect(),
+            broadcast: false,
+            tx,
+        }
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        match message.r#type() {
+            Type::HcPropose => {
+                let value = message.hc_propose.unwrap().value;
+                if self.proposal.is_none() {
+                    self.proposal = value.map(|value| *value);
+                }
+            },
+            Type::PlDeliver | Type::BebDeliver => {
+                let (sender, inner) = BroadcastManager::unwrap_delivery(message).unwrap();
+                match inner.hc_internal_decided {
+                    Some(decided) => self.handle_decided(decided.value.map(|value| *value), sender, client_state),
+                    None => warn!("{} got an unknown message type: {:?}", self.abstraction_id, inner.r#type()),
+                }
+            },
+            Type::PlSend => {
+                PerfectLinkManager::handle_pl_send(message, &client_state.system_id, client_state.own_port, &client_state.link_keys);
+                return;
+            },
+            _ => {
+                warn!("{} got an unknown message type: {:?}", self.abstraction_id, message.r#type());
+                return;
+            },
+        }
+        self.advance(client_state);
+    }
+
+    /// Called when the failure detector reports that `process` crashed
+    pub fn handle_crash(&mut self, process: &ProcessId, client_state: &ClientState) {
+        if self.crashed.insert(process.port) {
+            self.advance(client_state);
+        }
+    }
+
+    fn handle_decided(&mut self, value: Option<Envelope>, sender: ProcessId, client_state: &ClientState) {
+        let leaders = leaders(client_state);
+        let Some(leader) = leaders.iter().position(|node| node.port == sender.port) else {
+            warn!("{} got a decision from {}-{}, which is not in the system", self.abstraction_id, sender.owner, sender.index);
+            return;
+        };
+        let own = leaders.iter().position(|node| node.port == client_state.own_port as i32);
+
+        if own.is_some_and(|own| leader < own) && self.proposer.is_none_or(|proposer| leader > proposer) {
+            self.proposal = value;
+            self.proposer = Some(leader);
+        }
+        self.delivered.insert(leader);
+    }
+
+    /// Decide if this process leads the current round, then move past the rounds that are over
+    fn advance(&mut self, client_state: &ClientState) {
+        let leaders = leaders(client_state);
+        while let Some(leader) = leaders.get(self.round) {
+            if leader.port == client_state.own_port as i32 && !self.broadcast {
+                let Some(proposal) = self.proposal.clone() else { return };
+                self.broadcast = true;
+                self.decide(proposal, client_state);
+            }
+            if !self.crashed.contains(&leader.port) && !self.delivered.contains(&self.round) {
+                return;
+            }
+            self.round += 1;
+        }
+    }
+
+    fn decide(&self, value: Envelope, client_state: &ClientState) {
+        debug!("{} decided in round {}", self.abstraction_id, self.round + 1);
+
+        let decided = protobuf::HcInternalDecided {
+            value: NetworkService::wrap_envelope_contents(value.clone()),
+        };
+        let mut wrapper = Envelope::with_shipping_label(Type::HcInternalDecided);
+        wrapper.hc_internal_decided = NetworkService::wrap_envelope_contents(decided);
+        wrapper.from_abstraction_id = self.abstraction_id.clone();
+        wrapper.to_abstraction_id = self.abstraction_id.clone();
+        BroadcastManager::do_beb_broadcast(wrapper, &self.tx, client_state);
+
+        let hc_decide = protobuf::HcDecide {
+            value: NetworkService::wrap_envelope_contents(value),
+        };
+        let mut wrapper = Envelope::with_shipping_label(Type::HcDecide);
+        wrapper.hc_decide = NetworkService::wrap_envelope_contents(hc_decide);
+        wrapper.from_abstraction_id = self.abstraction_id.clone();
+        wrapper.to_abstraction_id = parent_abstraction_id(&self.abstraction_id).to_string();
+        self.tx.send(wrapper).unwrap();
+    }
+}
+
+/// Ask the consensus instance at `abstraction_id` to propose `value`
+pub(crate) fn request_propose(abstraction_id: &str, value: Envelope, tx: &Sender<Envelope>) {
+    let hc_propose = protobuf::HcPropose {
+        value: NetworkService::wrap_envelope_contents(value),
+    };
+
+    let mut wrapper = Envelope::with_shipping_label(Type::HcPropose);
+    wrapper.hc_propose = NetworkService::wrap_envelope_contents(hc_propose);
+    wrapper.from_abstraction_id = parent_abstraction_id(abstraction_id).to_string();
+    wrapper.to_abstraction_id = abstraction_id.to_string();
+    tx.send(wrapper).unwrap();
+}
+
+/// Processes of the system by rank, the one at position `i` leading round `i`
+fn leaders(client_state: &ClientState) -> Vec<ProcessId> {
+    let mut leaders = client_state.nodes.clone();
+    leaders.sort_by_key(|node| node.rank);
+    leaders
+}
@@ -0,0 +1,521 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::mem;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use uuid::Uuid;
+use crate::authenticated_link::LinkKeys;
+use crate::broadcast_manager::BroadcastGuarantee;
+use crate::network_service::NetworkService;
+use crate::perfect_link_manager::PerfectLinkManager;
+use crate::protobuf::message::Type;
+use crate::protobuf::{ProcessId, Value};
+use crate::value_codec::{I32Codec, ValueCodec};
+use crate::{debug, info, protobuf, warn, Envelope};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Stand-in for the instructor's hub: processes register with it, it groups them into systems,
+/// triggers the app-level operations and collects what the processes report back
+pub struct Hub {
+}
+
+/// Something a process reported to the hub
+#[derive(Clone, Debug, PartialEq)]
+pub enum HubEvent {
+    Registered { process: String },
+    Delivered { process: String, value: Value, stamp: Option<BroadcastStamp> },
+    ReadReturned { process: String, register: String, value: Value },
+    WriteReturned { process: String, register: String },
+    #[cfg(feature = "write-rejections")]
+    WriteRejected { process: String, register: String, reason: String },
+    Decided { process: String, value: Value },
+    /// A terminating reliable broadcast delivered `value` from `sender`, or `None` if the sender failed
+    TrbDelivered { process: String, topic: String, sender: String, value: Option<Value> },
+    #[cfg(feature = "nbac")]
+    NbacDecided { process: String, transaction: String, commit: bool },
+}
+
+/// What the source of a delivered value stamped it with
+#[derive(Clone, Debug, PartialEq)]
+pub struct BroadcastStamp {
+    /// Name of the process that broadcast the value, e.g. `abc-1`
+    pub sender: String,
+    pub sequence_number: i64,
+    /// Causal broadcasts only: values of each process, by rank, delivered by the sender before this one
+    pub vector_clock: Vec<i64>,
+}
+
+#[derive(Default)]
+struct HubState {
+    port: u16,
+    registered: Vec<ProcessId>,
+    system_id: String,
+    system: Vec<ProcessId>,
+    systems_created: usize,
+    events: Vec<HubEvent>,
+}
+
+pub struct HubHandle {
+    address: SocketAddr,
+    state: Arc<Mutex<HubState>>,
+    stop_listening: Arc<AtomicBool>,
+    stop_worker: Arc<AtomicBool>,
+    server_thread: JoinHandle<()>,
+    worker_thread: JoinHandle<()>,
+}
+
+impl Hub {
+    pub fn start(address: SocketAddr) -> HubHandle {
+        let (tx, rx) = channel();
+        let stop_listening = Arc::new(AtomicBool::new(false));
+        let (address, server_thread) = NetworkService::start_listener(&address, tx, stop_listening.clone(), LinkKeys::default());
+
+        let initial_state = HubState {
+            port: address.port(),
+            ..Default::default()
+        };
+        let state = Arc::new(Mutex::new(initial_state));
+        let stop_worker = Arc::new(AtomicBool::new(false));
+
+        let worker_state = state.clone();
+        let worker_stop = stop_worker.clone();
+        let worker_thread = thread::spawn(move || {
+            Self::handle_messages(rx, worker_state, worker_stop)
+        });
+
+        info!("Hub listening on {}", address);
+        HubHandle { address, state, stop_listening, stop_worker, server_thread, worker_thread }
+    }
+
+    fn handle_messages(rx: Receiver<Envelope>, state: Arc<Mutex<HubState>>, stop: Arc<AtomicBool>) {
+        while !stop.load(Ordering::SeqCst) {
+            match rx.recv_timeout(POLL_INTERVAL) {
+                Ok(msg) => Self::handle_message(msg, &mut state.lock().unwrap()),
+                Err(RecvTimeoutError::Timeout) => continue,
+                // The listener is gone, so nothing else can arrive
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+
+    fn handle_message(message: Envelope, state: &mut HubState) {
+        let pl_deliver = match message.pl_deliver {
+            Some(val) => val,
+            None => {
+                warn!("hub: Expected a PlDeliver from the network, got {:?}", message.r#type());
+                return;
+            }
+        };
+        let sender = pl_deliver.sender.unwrap_or_default();
+        let inner = *pl_deliver.message.unwrap();
+
+        if inner.r#type() == Type::ProcRegistration {
+            let registration = inner.proc_registration.unwrap();
+            let process = ProcessId {
+                host: sender.host,
+                port: sender.port,
+                owner: registration.owner,
+                index: registration.index,
+                rank: 0,
+            };
+            info!("hub: Registered {}-{} at {}:{}", process.owner, process.index, process.host, process.port);
+
+            state.registered.retain(|known| {
+                (known.owner != process.owner || known.index != process.index) && known.port != process.port
+            });
+            state.events.push(HubEvent::Registered { process: process_name(&process) });
+            state.registered.push(process.clone());
+            state.registered.sort_by(|a, b| (&a.owner, a.index).cmp(&(&b.owner, b.index)));
+            Self::rejoin_system(process, state);
+            return;
+        }
+
+        let process = match state.registered.iter().find(|known| known.port == sender.port) {
+            Some(known) => format!("{}/{}", state.system_id, process_name(known)),
+            None => format!("{}/{}:{}", state.system_id, sender.host, sender.port),
+        };
+
+        let event = match inner.r#type() {
+            Type::AppValue => {
+                let app_value = inner.app_value.unwrap();
+                let value = app_value.value.unwrap_or_default();
+                let stamp = app_value.sender.map(|sender| BroadcastStamp {
+                    sender: process_name(&sender),
+                    sequence_number: app_value.sequence_number,
+                    vector_clock: app_value.vector_clock,
+                });
+                match &stamp {
+                    Some(stamp) => info!("hub: {} delivered {} (#{} of {})", process, value, stamp.sequence_number, stamp.sender),
+                    None => info!("hub: {} delivered {}", process, value),
+                }
+                HubEvent::Delivered { process, value, stamp }
+            },
+            Type::AppReadReturn => {
+                let read_return = inner.app_read_return.unwrap();
+                let value = read_return.value.unwrap_or_default();
+                info!("hub: {} read {}={}", process, read_return.register, value);
+                HubEvent::ReadReturned { process, register: read_return.register, value }
+            },
+            Type::AppWriteReturn => {
+                let write_return = inner.app_write_return.unwrap();
+                info!("hub: {} finished writing {}", process, write_return.register);
+                HubEvent::WriteReturned { process, register: write_return.register }
+            },
+            #[cfg(feature = "write-rejections")]
+            Type::AppWriteRejected => {
+                let rejected = inner.app_write_rejected.unwrap();
+                info!("hub: {} may not write {}: {}", process, rejected.register, rejected.reason);
+                HubEvent::WriteRejected { process, register: rejected.register, reason: rejected.reason }
+            },
+            Type::AppDecide => {
+                let value = inner.app_decide.unwrap().value.unwrap_or_default();
+                info!("hub: {} decided {}", process, value);
+                HubEvent::Decided { process, value }
+            },
+            Type::AppTrbDeliver => {
+                let trb_deliver = inner.app_trb_deliver.unwrap();
+                let sender = trb_deliver.sender.as_ref().map(process_name).unwrap_or_default();
+                let value = if trb_deliver.failed { None } else { Some(trb_deliver.value.unwrap_or_default()) };
+                match &value {
+                    Some(value) => info!("hub: {} delivered {} from {} on '{}'", process, value, sender, trb_deliver.topic),
+                    None => info!("hub: {} delivered that {} failed on '{}'", process, sender, trb_deliver.topic),
+                }
+                HubEvent::TrbDelivered { process, topic: trb_deliver.topic, sender, value }
+            },
+            #[cfg(feature = "nbac")]
+            Type::AppNbacDecide => {
+                let decision = inner.app_nbac_decide.unwrap();
+                info!("hub: {} decided to {} '{}'", process, if decision.commit { "commit" } else { "abort" }, decision.transaction);
+                HubEvent::NbacDecided { process, transaction: decision.transaction, commit: decision.commit }
+            },
+            _ => {
+                debug!("hub: Ignoring {:?} from {}", inner.r#type(), process);
+                return;
+            }
+        };
+        state.events.push(event);
+    }
+
+    // A member of the current system that registers again has restarted: hand it the system again,
+    // so that it can recover what it logged for it
+    fn rejoin_system(process: ProcessId, state: &mut HubState) {
+        let Some(member) = state.system.iter_mut()
+            .find(|member| member.owner == process.owner && member.index == process.index) else {
+            return;
+        };
+        member.host = process.host;
+        member.port = process.port;
+
+        let member = member.clone();
+        info!("hub: {} rejoins system {}", process_name(&member), state.system_id);
+        let mut init_wrapper = Envelope::with_shipping_label(Type::ProcInitializeSystem);
+        init_wrapper.proc_initialize_system = Option::from(protobuf::ProcInitializeSystem {
+            processes: state.system.clone()
+        });
+        Self::send(state.port, &member, init_wrapper, &state.system_id);
+    }
+
+    fn send(hub_port: u16, destination: &ProcessId, mut message: Envelope, system_id: &str) {
+        message.system_id = system_id.to_string();
+        message.to_abstraction_id = "app".to_string();
+
+        let pl_send = protobuf::PlSend {
+            message: NetworkService::wrap_envelope_contents(message),
+            destination: Option::from(destination.clone()),
+        };
+
+        let mut pl_send_wrapper = Envelope::with_shipping_label(Type::PlSend);
+        pl_send_wrapper.pl_send = NetworkService::wrap_envelope_contents(pl_send);
+        pl_send_wrapper.to_abstraction_id = "app".to_string();
+
+        PerfectLinkManager::handle_pl_send(pl_send_wrapper, system_id, hub_port, &LinkKeys::default());
+    }
+}
+
+impl HubHandle {
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Every process that registered so far, ordered by owner and index
+    pub fn processes(&self) -> Vec<ProcessId> {
+        self.state.lock().unwrap().registered.clone()
+    }
+
+    /// Processes of the current system, with the ranks they were given
+    pub fn system(&self) -> (String, Vec<ProcessId>) {
+        let state = self.state.lock().unwrap();
+        (state.system_id.clone(), state.system.clone())
+    }
+
+    /// Everything the processes have reported back, in arrival order
+    pub fn events(&self) -> Vec<HubEvent> {
+        self.state.lock().unwrap().events.clone()
+    }
+
+    /// Destroy the current system, if any, and start a new one out of the processes of the given owners
+    pub fn create_system(&self, owners: &[&str]) -> Result<String, String> {
+        if owners.is_empty() {
+            return Err("A system needs at least one owner".to_string());
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let mut processes = vec![];
+        for owner in owners {
+            let owned = state.registered.iter()
+                .filter(|process| process.owner == *owner)
+                .cloned()
+                .collect::<Vec<_>>();
+            if owned.is_empty() {
+                return Err(format!("No process of owner '{}' has registered", owner));
+            }
+            processes.extend(owned);
+        }
+        for (position, process) in processes.iter_mut().enumerate() {
+            process.rank = (position + 1) as i32;
+        }
+
+        // The new system is recorded under the lock, and the messages go out once it is released
+        let old_system_id = state.system_id.clone();
+        let old_system = mem::replace(&mut state.system, processes.clone());
+        state.systems_created += 1;
+        state.system_id = format!("sys-{}", state.systems_created);
+        let system_id = state.system_id.clone();
+        drop(state);
+
+        for process in &old_system {
+            let destroy_wrapper = Envelope::with_shipping_label(Type::ProcDestroySystem);
+            self.send(process, destroy_wrapper, &old_system_id);
+        }
+
+        for process in &processes {
+            info!("hub: Starting system {} of process {}", system_id, process_name(process));
+            let mut init_wrapper = Envelope::with_shipping_label(Type::ProcInitializeSystem);
+            init_wrapper.proc_initialize_system = Option::from(protobuf::ProcInitializeSystem {
+                processes: processes.clone()
+            });
+            self.send(process, init_wrapper, &system_id);
+        }
+        Ok(system_id)
+    }
+
+    pub fn broadcast(&self, process: &str, value: i32) -> Result<(), String> {
+        let app_broadcast = protobuf::AppBroadcast {
+            value: Option::from(I32Codec::encode(&value)),
+        };
+
+        let mut wrapper = Envelope::with_shipping_label(Type::AppBroadcast);
+        wrapper.app_broadcast = Option::from(app_broadcast);
+        self.send_to_system(&[process], wrapper)
+    }
+
+    /// Read a register on the named processes, or on every process of the system if none is named
+    pub fn read(&self, register: &str, processes: &[&str]) -> Result<(), String> {
+        let app_read = protobuf::AppRead {
+            register: register.to_string(),
+        };
+
+        let mut wrapper = Envelope::with_shipping_label(Type::AppRead);
+        wrapper.app_read = Option::from(app_read);
+        self.send_to_system(processes, wrapper)
+    }
+
+    /// Write a register from the named processes, or from every process of the system if none is named
+    pub fn write(&self, register: &str, value: i32, processes: &[&str]) -> Result<(), String> {
+        let app_write = protobuf::AppWrite {
+            register: register.to_string(),
+            value: Option::from(I32Codec::encode(&value)),
+        };
+
+        let mut wrapper = Envelope::with_shipping_label(Type::AppWrite);
+        wrapper.app_write = Option::from(app_write);
+        self.send_to_system(processes, wrapper)
+    }
+
+    /// Have every process of the system propose a random value on the topic; each one reports the decided value
+    /// as a `HubEvent::Decided`
+    pub fn consensus(&self, topic: &str) -> Result<(), String> {
+        let (system_id, processes) = self.system();
+        if processes.is_empty() {
+            return Err("There is no system yet, create one with 'system'".to_string());
+        }
+
+        for process in &processes {
+            let value = (Uuid::new_v4().as_u128() % 100) as i32;
+            info!("hub: {}/{} will propose {}", system_id, process_name(process), value);
+
+            let app_propose = protobuf::AppPropose {
+                topic: topic.to_string(),
+                value: Option::from(I32Codec::encode(&value)),
+            };
+
+            let mut wrapper = Envelope::with_shipping_label(Type::AppPropose);
+            wrapper.app_propose = Option::from(app_propose);
+            self.send(process, wrapper, &system_id);
+        }
+        Ok(())
+    }
+
+    /// Start a terminating reliable broadcast on the topic, with `sender` as its designated sender
+    pub fn trb(&self, topic: &str, sender: &str, value: i32) -> Result<(), String> {
+        let (system_id, processes) = self.system();
+        let sender = processes.iter()
+            .find(|process| process_name(process) == sender)
+            .cloned()
+            .ok_or(format!("Process '{}' is not part of {}", sender, system_id))?;
+
+        let app_trb_broadcast = protobuf::AppTrbBroadcast {
+            topic: topic.to_string(),
+            sender: Option::from(sender),
+            value: Option::from(I32Codec::encode(&value)),
+        };
+
+        let mut wrapper = Envelope::with_shipping_label(Type::AppTrbBroadcast);
+        wrapper.app_trb_broadcast = Option::from(app_trb_broadcast);
+        self.send_to_system(&[], wrapper)
+    }
+
+    /// Have every process of the system vote on the transaction, to commit unless it is one of `aborting`
+    #[cfg(feature = "nbac")]
+    pub fn nbac(&self, transaction: &str, aborting: &[&str]) -> Result<(), String> {
+        let (system_id, processes) = self.system();
+        if processes.is_empty() {
+            return Err("There is no system yet, create one with 'system'".to_string());
+        }
+        if let Some(unknown) = aborting.iter().find(|name| !processes.iter().any(|process| process_name(process) == **name)) {
+            return Err(format!("Process '{}' is not part of {}", unknown, system_id));
+        }
+
+        for process in &processes {
+            let app_nbac_vote = protobuf::AppNbacVote {
+                transaction: transaction.to_string(),
+                commit: !aborting.contains(&process_name(process).as_str()),
+            };
+
+            let mut wrapper = Envelope::with_shipping_label(Type::AppNbacVote);
+            wrapper.app_nbac_vote = Option::from(app_nbac_vote);
+            self.send(process, wrapper, &system_id);
+        }
+        Ok(())
+    }
+
+    /// Ways in which the stamped deliveries of the current system break `guarantee`, empty if there are none.
+    /// A process that crashed is reported for the values it never delivered, like any other.
+    pub fn check_deliveries(&self, guarantee: BroadcastGuarantee) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        let prefix = format!("{}/", state.system_id);
+        let mut members = state.system.clone();
+        members.sort_by_key(|member| member.rank);
+        let members = members.iter().map(process_name).collect::<Vec<_>>();
+
+        let mut problems = vec![];
+        // Values delivered by each process, as (sender, sequence number)
+        let mut delivered: HashMap<String, HashSet<(String, i64)>> = HashMap::new();
+        // Values each process delivered from each sender, keyed by (process, sender)
+        let mut counts: HashMap<(String, String), i64> = HashMap::new();
+        // Values delivered by each process, in delivery order
+        let mut order: HashMap<String, Vec<(String, i64)>> = HashMap::new();
+        for event in &state.events {
+            let HubEvent::Delivered { process, stamp: Some(stamp), .. } = event else { continue };
+            let Some(process) = process.strip_prefix(&prefix) else { continue };
+            let (sender, sequence_number) = (&stamp.sender, stamp.sequence_number);
+
+            if !delivered.entry(process.to_string()).or_default().insert((sender.clone(), sequence_number)) {
+                problems.push(format!("{} delivered #{} of {} twice", process, sequence_number, sender));
+                continue;
+            }
+            order.entry(process.to_string()).or_default().push((sender.clone(), sequence_number));
+            let count = counts.entry((process.to_string(), sender.clone())).or_default();
+            if guarantee.is_fifo() && sequence_number != *count + 1 {
+                problems.push(format!("{} delivered #{} of {} after #{}", process, sequence_number, sender, count));
+            }
+            *count += 1;
+
+            if guarantee == BroadcastGuarantee::Causal {
+                for (member, needed) in members.iter().zip(&stamp.vector_clock) {
+                    let has = counts.get(&(process.to_string(), member.clone())).copied().unwrap_or(0);
+                    if member != sender && has < *needed {
+                        problems.push(format!("{} delivered #{} of {} before #{} of {}",
+                                              process, sequence_number, sender, needed, member));
+                    }
+                }
+            }
+        }
+
+        if guarantee == BroadcastGuarantee::TotalOrder {
+            // Every process must deliver the values it has in common with the first one in the same order
+            let mut processes = members.iter().filter(|member| order.contains_key(*member));
+            if let Some(first) = processes.next() {
+                for other in processes {
+                    let common = |of: &str, with: &str| order[of].iter()
+                        .filter(|value| delivered[with].contains(value))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    if common(first, other) != common(other, first) {
+                        problems.push(format!("{} and {} delivered their common values in different orders", first, other));
+                    }
+                }
+            }
+        }
+
+        if guarantee.is_total() {
+            let all = delivered.values().flatten().cloned().collect::<BTreeSet<_>>();
+            for member in &members {
+                let own = delivered.get(member);
+                for (sender, sequence_number) in &all {
+                    if !own.is_some_and(|own| own.contains(&(sender.clone(), *sequence_number))) {
+                        problems.push(format!("{} never delivered #{} of {}", member, sequence_number, sender));
+                    }
+                }
+            }
+        }
+        problems
+    }
+
+    pub fn shutdown(self) {
+        info!("Stopping hub ...");
+        self.stop_listening.store(true, Ordering::SeqCst);
+        let _ = TcpStream::connect(self.address);
+        self.server_thread.join().expect("Joining the hub listener should not cause a panic");
+
+        self.stop_worker.store(true, Ordering::SeqCst);
+        self.worker_thread.join().expect("Joining the hub worker should not cause a panic");
+    }
+
+    fn send_to_system(&self, names: &[&str], message: Envelope) -> Result<(), String> {
+        let (system_id, processes) = self.system();
+        if processes.is_empty() {
+            return Err("There is no system yet, create one with 'system'".to_string());
+        }
+
+        let destinations = if names.is_empty() {
+            processes
+        } else {
+            names.iter()
+                .map(|name| processes.iter()
+                    .find(|process| process_name(process) == *name)
+                    .cloned()
+                    .ok_or(format!("Process '{}' is not part of {}", name, system_id)))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        for destination in &destinations {
+            self.send(destination, message.clone(), &system_id);
+        }
+        Ok(())
+    }
+
+    fn send(&self, destination: &ProcessId, message: Envelope, system_id: &str) {
+        Hub::send(self.address.port(), destination, message, system_id);
+    }
+}
+
+/// Name of a process as used in hub commands, e.g. `abc-2`
+pub fn process_name(process: &ProcessId) -> String {
+    format!("{}-{}", process.owner, process.index)
+}
@@ -0,0 +1,41 @@
+pub mod logging;
+pub mod network_service;
+pub mod client;
+pub mod register_manager;
+pub mod register_log;
+pub mod register_snapshot;
+pub mod tag;
+pub mod regular_register_manager;
+pub mod single_writer_register_manager;
+pub mod failure_detector;
+pub mod perfect_link_manager;
+pub mod authenticated_link;
+pub mod broadcast_manager;
+pub mod reliable_broadcast_manager;
+pub mod uniform_reliable_broadcast_manager;
+pub mod fifo_broadcast_manager;
+pub mod causal_broadcast_manager;
+pub mod hierarchical_consensus_manager;
+pub mod consensus_manager;
+pub mod total_order_broadcast_manager;
+pub mod probabilistic_broadcast_manager;
+pub mod byzantine_broadcast_manager;
+pub mod terminating_reliable_broadcast_manager;
+#[cfg(feature = "nbac")]
+pub mod non_blocking_atomic_commit_manager;
+pub mod event_context;
+pub mod quorum;
+pub mod value_codec;
+pub mod node;
+pub mod hub;
+
+pub(crate) use logging::{debug, info, trace, warn};
+pub use client::{Client, ClientState, ProcessStatus};
+pub use network_service::NetworkService;
+pub use node::{Node, NodeConfig, NodeHandle, ShutdownReport};
+
+pub type Envelope = protobuf::Message;
+
+pub mod protobuf {
+    include!(concat!(env!("OUT_DIR"), "/protobuf.rs"));
+}
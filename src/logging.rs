@@ -0,0 +1,92 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Verbosity levels, in the order in which they get noisier.
+/// Warnings are always shown, the others depend on the level set at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Warn = 0,
+    Info = 1,
+    Debug = 2,
+    Trace = 3,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> LogLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => LogLevel::Warn,
+        1 => LogLevel::Info,
+        2 => LogLevel::Debug,
+        _ => LogLevel::Trace,
+    }
+}
+
+pub fn enabled(level: LogLevel) -> bool {
+    level <= self::level()
+}
+
+/// Print a line to stderr, prefixed by the UTC time of day and the level tag, e.g. `09:41:50.854 INF ...`
+pub fn log(level: LogLevel, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() % 86_400;
+    eprintln!("{:02}:{:02}:{:02}.{:03} {} {}",
+             seconds / 3600, seconds / 60 % 60, seconds % 60, since_epoch.subsec_millis(),
+             level.tag(), args);
+}
+
+impl LogLevel {
+    fn tag(&self) -> &'static str {
+        match self {
+            LogLevel::Warn => "WRN",
+            LogLevel::Info => "INF",
+            LogLevel::Debug => "DBG",
+            LogLevel::Trace => "TRC",
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(format!("Unknown log level '{}'", s)),
+        }
+    }
+}
+
+// Crate-private, so that they do not clash with the macros of logging crates used next to this one.
+// They go by `log_*` here because importing a macro named `warn` by itself is ambiguous with the `warn` attribute;
+// the lib root re-exports them as `crate::{warn, info, debug, trace}`
+macro_rules! log_warn {
+    ($($arg:tt)*) => { $crate::logging::log($crate::logging::LogLevel::Warn, format_args!($($arg)*)) };
+}
+
+macro_rules! log_info {
+    ($($arg:tt)*) => { $crate::logging::log($crate::logging::LogLevel::Info, format_args!($($arg)*)) };
+}
+
+macro_rules! log_debug {
+    ($($arg:tt)*) => { $crate::logging::log($crate::logging::LogLevel::Debug, format_args!($($arg)*)) };
+}
+
+macro_rules! log_trace {
+    ($($arg:tt)*) => { $crate::logging::log($crate::logging::LogLevel::Trace, format_args!($($arg)*)) };
+}
+
+pub(crate) use {log_debug as debug, log_info as info, log_trace as trace, log_warn as warn};
@@ -1,127 +1,603 @@
-mod network_service;
-mod client;
-mod register_manager;
-mod perfect_link_manager;
-mod broadcast_manager;
-
-use std::{env, thread};
+use std::{env, io, thread};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
 use std::net::SocketAddr;
-use std::sync::mpsc::channel;
-use network_service::NetworkService;
-use uuid::Uuid;
-use crate::client::Client;
-use crate::protobuf::message::Type;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::Duration;
+use dp_algo::authenticated_link::LinkKeys;
+use dp_algo::broadcast_manager::BroadcastGuarantee;
+use dp_algo::hub::{process_name, Hub, HubHandle};
+use dp_algo::logging::{self, LogLevel};
+use dp_algo::protobuf::message::Type;
+use dp_algo::{protobuf, Envelope, Node, NodeConfig, NodeHandle};
+use dp_algo::probabilistic_broadcast_manager::{DEFAULT_FANOUT, DEFAULT_ROUNDS};
+use dp_algo::register_manager::RegisterKind;
+use dp_algo::register_snapshot;
+use dp_algo::value_codec::{I32Codec, ValueCodec};
 
-type Envelope = protobuf::Message;
+const PROMPT: &str = "dp-algo> ";
+const HUB_PROMPT: &str = "dalgs> ";
 
-pub mod protobuf {
-    include!(concat!(env!("OUT_DIR"), "/protobuf.rs"));
+/// Same as the library's own `info!`, which it keeps to itself
+macro_rules! info {
+    ($($arg:tt)*) => { logging::log(LogLevel::Info, format_args!($($arg)*)) };
 }
 
-struct Options {
-    hub_address: SocketAddr,
-    own_addresses: Vec<SocketAddr>
+enum ConsoleEvent {
+    Line(String),
+    Quit,
 }
 
-fn main() {
-    let options = set_config();
-    println!("Hub address is: {}", options.hub_address);
+fn main() -> ExitCode {
+    let mut args = env::args().collect::<Vec<String>>();
+    let owner = take_option(&mut args, "--owner");
+    let register_kinds = take_register_kinds(&mut args);
+    let data_dir = take_option(&mut args, "--data-dir").map(PathBuf::from);
+    let fast_reads = take_flag(&mut args, "--fast-reads");
+    let gossip_fanout = take_number(&mut args, "--gossip-fanout");
+    let gossip_rounds = take_number(&mut args, "--gossip-rounds");
+    let broadcast_guarantee = take_broadcast_guarantee(&mut args);
+    let beb_loopback = take_flag(&mut args, "--beb-loopback");
+    let link_keys = take_link_keys(&mut args);
 
-    let mut server_threads = Vec::with_capacity(3);
-    let mut client_threads = Vec::with_capacity(3);
+    let (events_tx, events_rx) = channel();
+    let signal_tx = events_tx.clone();
+    ctrlc::set_handler(move || {
+        let _ = signal_tx.send(ConsoleEvent::Quit);
+    }).expect("Installing the SIGINT/SIGTERM handler should not fail");
+    spawn_stdin_reader(events_tx);
 
-    for index in 0..3 {
-        let node_socket = options.own_addresses[index];
+    if args.get(1).map(String::as_str) == Some("hub") {
+        run_hub(&args[1..], owner.unwrap_or("ref".to_string()), register_kinds, broadcast_guarantee, link_keys, events_rx)
+    } else {
+        let mut config = set_config(&args);
+        if let Some(owner) = owner {
+            config.owner = owner;
+        }
+        config.register_kinds = register_kinds;
+        config.data_dir = data_dir;
+        config.fast_reads = fast_reads;
+        config.broadcast_guarantee = broadcast_guarantee;
+        config.beb_loopback = beb_loopback;
+        config.link_keys = link_keys;
+        if let Some(fanout) = gossip_fanout {
+            config.gossip_fanout = fanout;
+        }
+        if let Some(rounds) = gossip_rounds {
+            config.gossip_rounds = rounds;
+        }
+        run_node(config, events_rx)
+    }
+}
+
+fn run_node(config: NodeConfig, events: Receiver<ConsoleEvent>) -> ExitCode {
+    info!("Hub address is: {}", config.hub_address);
+
+    let node = Node::start(config);
+    run_console(PROMPT, events, |words| run_command(&node, words));
+
+    info!("Stopping process {} ...", node.owner());
+    let report = node.shutdown();
+    info!("Abandoned {} register operation(s), {} thread(s) had panicked",
+          report.failed_operations, report.panicked_threads);
+    report.exit_code()
+}
+
+/// Run the hub, along with stand-in processes under `owner` when their addresses are given,
+/// the same way the reference binary starts the hub together with `ref-1..3`
+fn run_hub(args: &[String], owner: String, register_kinds: HashMap<String, RegisterKind>,
+           broadcast_guarantee: BroadcastGuarantee, link_keys: LinkKeys, events: Receiver<ConsoleEvent>) -> ExitCode {
+    let hub_address: SocketAddr = match args.get(1).map(|address| address.parse()) {
+        Some(Ok(val)) => val,
+        _ => {
+            show_usage_info();
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let hub = Hub::start(hub_address);
+    let stand_ins = if args.len() > 2 {
+        let mut config = NodeConfig::new(hub_address, parse_own_addresses(&args[2..]));
+        config.owner = owner;
+        config.register_kinds = register_kinds;
+        config.broadcast_guarantee = broadcast_guarantee;
+        config.link_keys = link_keys;
+        Some(Node::start(config))
+    } else {
+        None
+    };
+
+    run_console(HUB_PROMPT, events, |words| run_hub_command(&hub, words));
+
+    let mut exit_code = ExitCode::SUCCESS;
+    if let Some(stand_ins) = stand_ins {
+        info!("Stopping process {} ...", stand_ins.owner());
+        exit_code = stand_ins.shutdown().exit_code();
+    }
+    hub.shutdown();
+    info!("Stopped");
+    exit_code
+}
+
+/// Forward console lines to the main thread. Reaching the end of stdin does not stop the node,
+/// so it can still be run detached and stopped with a signal.
+fn spawn_stdin_reader(events: Sender<ConsoleEvent>) {
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            match line {
+                Ok(line) => if events.send(ConsoleEvent::Line(line)).is_err() { break },
+                Err(_) => break,
+            }
+        }
+    });
+}
+
+/// Feed console lines to `execute` until it returns `Ok(false)` or a signal arrives
+fn run_console(prompt: &str, events: Receiver<ConsoleEvent>,
+               mut execute: impl FnMut(&[&str]) -> Result<bool, String>) {
+    show_prompt(prompt);
+    for event in events {
+        match event {
+            ConsoleEvent::Quit => break,
+            ConsoleEvent::Line(line) => {
+                let words = line.split_whitespace().collect::<Vec<_>>();
+                match execute(&words) {
+                    Ok(true) => {},
+                    Ok(false) => break,
+                    Err(message) => println!("{}", message),
+                }
+                show_prompt(prompt);
+            }
+        }
+    }
+}
+
+fn show_prompt(prompt: &str) {
+    print!("{}", prompt);
+    let _ = io::stdout().flush();
+}
+
+fn run_log_command(args: &[&str]) -> Result<(), String> {
+    match args.first() {
+        Some(level) => logging::set_level(level.parse::<LogLevel>()?),
+        None => println!("Log level is {:?}", logging::level()),
+    }
+    Ok(())
+}
+
+/// Execute one console command; `Ok(false)` means the console should stop
+fn run_command(node: &NodeHandle, words: &[&str]) -> Result<bool, String> {
+    let Some((command, args)) = words.split_first() else {
+        return Ok(true);
+    };
+
+    match *command {
+        "help" => show_console_help(),
+        "quit" => return Ok(false),
+        "log" => run_log_command(args)?,
+        "list" => show_processes(node),
+        "registers" => show_registers(node, args)?,
+        "init" => node.initialize_local_system(args.first().unwrap_or(&"sys-local")),
+        "broadcast" => {
+            let [process, value] = args else {
+                return Err("Usage: broadcast <process> <value>".to_string());
+            };
+            let app_broadcast = protobuf::AppBroadcast {
+                value: Option::from(parse_value(value)?),
+            };
 
-        // Create message queue for current node
-        let (tx, rx)  = channel();
+            let mut wrapper = Envelope::with_shipping_label(Type::AppBroadcast);
+            wrapper.app_broadcast = Option::from(app_broadcast);
+            inject(node, &resolve_processes(node, &[process])?, wrapper);
+        },
+        "read" => {
+            let Some((register, processes)) = args.split_first() else {
+                return Err("Usage: read <register> [process...]".to_string());
+            };
+            let app_read = protobuf::AppRead {
+                register: register.to_string(),
+            };
 
-        let server_thread = NetworkService::start_listener(
-            &options.own_addresses[index], tx.clone()
-        );
-        server_threads.push(server_thread);
+            let mut wrapper = Envelope::with_shipping_label(Type::AppRead);
+            wrapper.app_read = Option::from(app_read);
+            inject(node, &resolve_processes(node, processes)?, wrapper);
+        },
+        "write" => {
+            let [register, value, processes @ ..] = args else {
+                return Err("Usage: write <register> <value> [process...]".to_string());
+            };
+            let app_write = protobuf::AppWrite {
+                register: register.to_string(),
+                value: Option::from(parse_value(value)?),
+            };
 
-        // Start client for current node
-        let mut client = Client::new(rx, tx, node_socket.port(), options.hub_address);
-        let client_thread = thread::spawn(move || {
-            client.start_worker()
-        });
-        client_threads.push(client_thread);
+            let mut wrapper = Envelope::with_shipping_label(Type::AppWrite);
+            wrapper.app_write = Option::from(app_write);
+            inject(node, &resolve_processes(node, processes)?, wrapper);
+        },
+        "propose" => {
+            let [topic, value, processes @ ..] = args else {
+                return Err("Usage: propose <topic> <value> [process...]".to_string());
+            };
+            let app_propose = protobuf::AppPropose {
+                topic: topic.to_string(),
+                value: Option::from(parse_value(value)?),
+            };
 
-        // Register the new node with the Hub
-        let connection_message = make_connection_message((index + 1) as u8, &options.hub_address);
-        NetworkService::send(&options.hub_address, connection_message, node_socket.port());
+            let mut wrapper = Envelope::with_shipping_label(Type::AppPropose);
+            wrapper.app_propose = Option::from(app_propose);
+            inject(node, &resolve_processes(node, processes)?, wrapper);
+        },
+        "trb" => {
+            let [topic, sender, value] = args else {
+                return Err("Usage: trb <topic> <process> <value>".to_string());
+            };
+            let index = resolve_processes(node, &[sender])?[0];
+            let sender = node.processes()[index - 1].state.own_process().cloned()
+                .ok_or("The process is not part of a system yet".to_string())?;
+            let app_trb_broadcast = protobuf::AppTrbBroadcast {
+                topic: topic.to_string(),
+                sender: Option::from(sender),
+                value: Option::from(parse_value(value)?),
+            };
+
+            let mut wrapper = Envelope::with_shipping_label(Type::AppTrbBroadcast);
+            wrapper.app_trb_broadcast = Option::from(app_trb_broadcast);
+            inject(node, &resolve_processes(node, &[])?, wrapper);
+        },
+        #[cfg(feature = "nbac")]
+        "vote" => {
+            let [transaction, vote, processes @ ..] = args else {
+                return Err("Usage: vote <transaction> <commit|abort> [process...]".to_string());
+            };
+            let mut app_nbac_vote = protobuf::AppNbacVote {
+                transaction: transaction.to_string(),
+                ..Default::default()
+            };
+            app_nbac_vote.commit = match *vote {
+                "commit" => true,
+                "abort" => false,
+                _ => return Err(format!("Expected commit or abort, got '{}'", vote)),
+            };
+
+            let mut wrapper = Envelope::with_shipping_label(Type::AppNbacVote);
+            wrapper.app_nbac_vote = Option::from(app_nbac_vote);
+            inject(node, &resolve_processes(node, processes)?, wrapper);
+        },
+        _ => return Err(format!("Unknown command '{}', try 'help'", command)),
     }
+    Ok(true)
+}
+
+/// Execute one hub console command; `Ok(false)` means the hub should stop
+fn run_hub_command(hub: &HubHandle, words: &[&str]) -> Result<bool, String> {
+    let Some((command, args)) = words.split_first() else {
+        return Ok(true);
+    };
 
-    // Join server threads before exiting
-    for thread in server_threads {
-        thread.join().expect("Joining server threads with the main one should not cause a panic");
+    match *command {
+        "help" => show_hub_help(),
+        "quit" => return Ok(false),
+        "log" => run_log_command(args)?,
+        "list" => show_registered_processes(hub),
+        "system" => {
+            hub.create_system(args)?;
+        },
+        "broadcast" => {
+            let [process, value] = args else {
+                return Err("Usage: broadcast <process> <value>".to_string());
+            };
+            hub.broadcast(process, parse_value(value)?.v)?;
+        },
+        "read" => {
+            let Some((register, processes)) = args.split_first() else {
+                return Err("Usage: read <register> [process...]".to_string());
+            };
+            if processes.is_empty() {
+                println!("INFO: No process name(s) provided. Triggering all processes");
+            }
+            hub.read(register, processes)?;
+        },
+        "write" => {
+            let [register, value, processes @ ..] = args else {
+                return Err("Usage: write <register> <value> [process...]".to_string());
+            };
+            hub.write(register, parse_value(value)?.v, processes)?;
+        },
+        "check" => {
+            let guarantee = match args.first() {
+                Some(guarantee) => guarantee.parse::<BroadcastGuarantee>()?,
+                None => return Err("Usage: check <beb|rb|aurb|murb|fifo|causal|tob|bcb|brb>".to_string()),
+            };
+            let problems = hub.check_deliveries(guarantee);
+            if problems.is_empty() {
+                println!("The deliveries of {} respect {:?}", hub.system().0, guarantee);
+            }
+            for problem in problems {
+                println!("{}", problem);
+            }
+        },
+        "consensus" => {
+            let [topic] = args else {
+                return Err("Usage: consensus <topic>".to_string());
+            };
+            hub.consensus(topic)?;
+        },
+        "trb" => {
+            let [topic, sender, value] = args else {
+                return Err("Usage: trb <topic> <process> <value>".to_string());
+            };
+            hub.trb(topic, sender, parse_value(value)?.v)?;
+        },
+        #[cfg(feature = "nbac")]
+        "nbac" => {
+            let Some((transaction, aborting)) = args.split_first() else {
+                return Err("Usage: nbac <transaction> [aborting process...]".to_string());
+            };
+            hub.nbac(transaction, aborting)?;
+        },
+        "wait" => {
+            let seconds = args.first()
+                .and_then(|seconds| seconds.parse::<u64>().ok())
+                .ok_or("Usage: wait <seconds>".to_string())?;
+            thread::sleep(Duration::from_secs(seconds));
+        },
+        _ => return Err(format!("Unknown command '{}', try 'help'", command)),
     }
-    for thread in client_threads {
-        thread.join().expect("Joining client threads with the main one should not cause a panic");
+    Ok(true)
+}
+
+fn show_hub_help() {
+    println!("Commands:");
+    println!("    log [info|debug|trace]                  - set logging level");
+    println!("    quit                                    - quit the program");
+    println!("    help                                    - show usage");
+    println!("    list                                    - list the registered processes");
+    println!("    system owner1 owner2 ...                - initialize a system with the processes of the owners");
+    println!("    broadcast <process> <value>             - have a process broadcast a value");
+    println!("    read <register> [process...]            - read a register (all processes by default)");
+    println!("    write <register> <value> [process...]   - write a register (all processes by default)");
+    println!("    check <guarantee>                       - check the broadcast deliveries of the system against a --broadcast guarantee");
+    println!("    consensus <topic>                       - have all processes propose a random value on the topic");
+    println!("    trb <topic> <process> <value>           - terminating reliable broadcast of a value, from a designated process");
+    #[cfg(feature = "nbac")]
+    println!("    nbac <transaction> [process...]         - have all processes vote on a transaction, the listed ones to abort");
+    println!("    wait <seconds>                          - pause the console");
+}
+
+fn show_registered_processes(hub: &HubHandle) {
+    let (system_id, system) = hub.system();
+    println!("PROCESS  HOST             PORT   RANK");
+    for process in hub.processes() {
+        let rank = system.iter()
+            .find(|member| member.port == process.port)
+            .map(|member| format!("{} in {}", member.rank, system_id))
+            .unwrap_or("-".to_string());
+        println!("{:<8} {:<16} {:<6} {}", process_name(&process), process.host, process.port, rank);
     }
 }
 
-fn make_connection_message(index: u8, destination: &SocketAddr) -> Envelope {
-    let mut register_message = protobuf::ProcRegistration::default();
-    register_message.owner = "uwu".to_string();
-    register_message.index = index as i32;
+fn show_console_help() {
+    println!("Commands:");
+    println!("    log [info|debug|trace]                  - set logging level");
+    println!("    quit                                    - quit the program");
+    println!("    help                                    - show usage");
+    println!("    list                                    - list this node's processes and the systems they are in");
+    println!("    registers [--json] [process...]         - show the register replicas and pending operations of the processes");
+    println!("    init [system]                           - put this node's processes in a system without the hub");
+    println!("    broadcast <process> <value>             - trigger an app broadcast from a process");
+    println!("    read <register> [process...]            - read a register (all processes by default)");
+    println!("    write <register> <value> [process...]   - write a register (all processes by default)");
+    println!("    propose <topic> <value> [process...]    - propose a value on a topic (all processes by default)");
+    println!("    trb <topic> <process> <value>           - terminating reliable broadcast of a value, from a designated process");
+    #[cfg(feature = "nbac")]
+    println!("    vote <transaction> <commit|abort> [process...] - vote on a transaction (all processes by default)");
+    println!("Processes are given by index (2) or by name (owner-2)");
+}
 
-    let mut register_wrapper = Envelope::default();
-    register_wrapper.proc_registration = Option::from(register_message);
-    register_wrapper.set_type(Type::ProcRegistration);
-    register_wrapper.to_abstraction_id = "app".to_string();
-    register_wrapper.message_uuid = Uuid::new_v4().to_string();
+fn show_processes(node: &NodeHandle) {
+    for (index, process) in node.processes().iter().enumerate() {
+        let state = &process.state;
+        let system = if state.system_id.is_empty() { "-" } else { &state.system_id };
+        println!("{}-{}  port {}  system {}  rank {}", node.owner(), index + 1, state.own_port, system, state.rank);
+        let gossip = &process.gossip_metrics;
+        if gossip.broadcast + gossip.delivered > 0 {
+            println!("    gossip: {} broadcast, {} delivered, {} relays sent, {} duplicates",
+                     gossip.broadcast, gossip.delivered, gossip.sent, gossip.duplicates);
+        }
+        for peer in &state.nodes {
+            println!("    {}-{}  {}:{}  rank {}", peer.owner, peer.index, peer.host, peer.port, peer.rank);
+        }
+    }
+}
 
-    let pl_destination = protobuf::ProcessId {
-        host: destination.ip().to_string(),
-        port: destination.port() as i32,
-        owner: "ref".to_string(),
-        ..Default::default()
+fn show_registers(node: &NodeHandle, args: &[&str]) -> Result<(), String> {
+    let (json, names) = match args.split_first() {
+        Some((&"--json", names)) => (true, names),
+        _ => (false, args),
     };
-    let mut pl_send_msg = protobuf::PlSend::default();
-    pl_send_msg.message = Option::from(Box::from(register_wrapper));
-    pl_send_msg.destination = Option::from(pl_destination);
+    let systems = node.register_snapshot(&resolve_processes(node, names)?);
+    if json {
+        println!("{}", register_snapshot::to_json(&systems));
+        return Ok(());
+    }
+    if systems.is_empty() {
+        println!("None of these processes is part of a system");
+    }
 
+    for system in &systems {
+        println!("{}:", system.system_id);
+        for process in &system.processes {
+            println!("  {} (rank {}, {} fast and {} slow reads):",
+                     process.name, process.rank, process.read_metrics.fast, process.read_metrics.slow);
+            if process.registers.is_empty() {
+                println!("    no registers");
+            }
+            for register in &process.registers {
+                println!("    {} = {}  {}  {}", register.name, register.value, register.tag, register.abstraction_id);
+                for operation in &register.operations {
+                    let kind = if operation.is_write { "write" } else { "read" };
+                    match operation.read_id {
+                        Some(read_id) => println!("      {} #{}: {}, {}/{} replies",
+                                                  kind, read_id, operation.phase, operation.replies, operation.needed),
+                        None => println!("      {}: {}", kind, operation.phase),
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
 
-    let mut pl_wrapper = protobuf::Message::default();
-    pl_wrapper.set_type(Type::PlSend);
-    pl_wrapper.from_abstraction_id = "app.pl".to_string();
-    pl_wrapper.to_abstraction_id = "app.pl".to_string();
-    pl_wrapper.pl_send = Option::from(Box::from(pl_send_msg));
-    pl_wrapper.system_id = "uwu".to_string();
-    pl_wrapper.message_uuid = Uuid::new_v4().to_string();
-    pl_wrapper
+/// Turn process names such as `2` or `abc-2` into 1-based indexes, defaulting to every process
+fn resolve_processes(node: &NodeHandle, names: &[&str]) -> Result<Vec<usize>, String> {
+    let count = node.own_addresses().len();
+    if names.is_empty() {
+        return Ok((1..=count).collect());
+    }
+
+    names.iter()
+        .map(|name| {
+            let index = match name.rsplit_once('-') {
+                Some((owner, index)) if owner == node.owner() => index,
+                Some(_) => return Err(format!("Process '{}' does not belong to {}", name, node.owner())),
+                None => name,
+            };
+            match index.parse::<usize>() {
+                Ok(index) if (1..=count).contains(&index) => Ok(index),
+                _ => Err(format!("Unknown process '{}'", name)),
+            }
+        })
+        .collect()
 }
+
+fn parse_value(text: &str) -> Result<protobuf::Value, String> {
+    match text.parse() {
+        Ok(v) => Ok(I32Codec::encode(&v)),
+        Err(err) => Err(format!("Invalid value '{}'; {}", text, err)),
+    }
+}
+
+fn inject(node: &NodeHandle, indexes: &[usize], mut message: Envelope) {
+    message.to_abstraction_id = "app".to_string();
+    for index in indexes {
+        let queue = node.queue(*index).expect("Process indexes are validated when parsed");
+        queue.send(message.clone()).unwrap();
+    }
+}
+
 fn show_usage_info() {
     println!("Usage");
-    println!("dp-algo <Hub IP address>:<Hub port> <Node-1 IP>:<Node-1 port> <Node-2 IP>:<Node-2 port> <Node-3 IP>:<Node-3 port>");
+    println!("dp-algo [--owner <alias>] [--data-dir <dir>] [--fast-reads] [--gossip-fanout <k>] [--gossip-rounds <r>] [--broadcast <beb|rb|aurb|murb|fifo|causal|tob|bcb|brb>] [--beb-loopback] [--link-key <port>-<port>=<key>...] [--register <name>=<nnar|rowa|majority|onar>...] <Hub IP address>:<Hub port> <Node-1 IP>:<Node-1 port> <Node-2 IP>:<Node-2 port> <Node-3 IP>:<Node-3 port>");
+    println!("dp-algo hub [--owner <alias>] [--broadcast <beb|rb|aurb|murb|fifo|causal|tob|bcb|brb>] [--link-key <port>-<port>=<key>...] [--register <name>=<nnar|rowa|majority|onar>...] <Hub IP address>:<Hub port> [<Node-1 IP>:<Node-1 port> <Node-2 IP>:<Node-2 port> <Node-3 IP>:<Node-3 port>]");
+    println!("    In hub mode, the optional nodes are stand-in processes owned by 'ref' unless --owner says otherwise");
+    println!("    With --data-dir, (N,N) atomic registers are logged there and recovered after a restart");
+    println!("    With --fast-reads, (N,N) atomic reads skip the write-back when a majority already holds the value");
+    println!("    Probabilistic broadcast relays each message to --gossip-fanout random processes (default {}), for --gossip-rounds hops (default {})",
+             DEFAULT_FANOUT, DEFAULT_ROUNDS);
+    println!("    With --beb-loopback, a process's own copy of a best-effort broadcast goes through its listener instead of its queue");
+    println!("    App-level broadcasts go over --broadcast, best-effort by default, and carry their sender and sequence number");
+    println!("    Each --link-key shares a key between the processes listening on two ports, authenticating the messages between them;");
+    println!("    the Byzantine broadcasts bcb and brb need one for every pair of processes, and tolerate f faults among N > 3f");
+    println!("    Registers are (N,N) atomic unless --register picks a (1,N) one, written by the process of rank 1");
+}
+
+fn failure_message(message: &str) -> String {
+    let failure =
+        format!(
+            "The provided arguments are not valid\
+            Reason: {}",
+            message);
+    show_usage_info();
+    failure
+}
+
+/// Remove `name <value>` from the arguments, returning the value
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let position = args.iter().position(|arg| arg == name)?;
+    if position + 1 >= args.len() {
+        panic!("{}", failure_message(&format!("Missing value for {}", name)));
+    }
+    let value = args.remove(position + 1);
+    args.remove(position);
+    Some(value)
+}
+
+/// Remove `name` from the arguments, returning whether it was there
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let Some(position) = args.iter().position(|arg| arg == name) else { return false };
+    args.remove(position);
+    true
+}
+
+/// Remove `name <number>` from the arguments, returning the number
+fn take_number<T: FromStr>(args: &mut Vec<String>, name: &str) -> Option<T> {
+    let value = take_option(args, name)?;
+    match value.parse() {
+        Ok(number) => Some(number),
+        Err(_) => panic!("{}", failure_message(&format!("Invalid value '{}' for {}", value, name))),
+    }
 }
 
-fn set_config() -> Options {
-    fn failure_message(message: &str) -> String {
-        let failure =
-            format!(
-                "The provided arguments are not valid\
-                Reason: {}",
-                message);
-        show_usage_info();
-        failure
+/// Remove `--broadcast <guarantee>` from the arguments, defaulting to best-effort
+fn take_broadcast_guarantee(args: &mut Vec<String>) -> BroadcastGuarantee {
+    match take_option(args, "--broadcast").map(|guarantee| guarantee.parse()) {
+        Some(Ok(guarantee)) => guarantee,
+        Some(Err(err)) => panic!("{}", failure_message(&err)),
+        None => BroadcastGuarantee::default(),
     }
+}
 
-    let args = env::args().collect::<Vec<String>>();
+/// Remove every `--link-key <port>-<port>=<key>` from the arguments
+fn take_link_keys(args: &mut Vec<String>) -> LinkKeys {
+    let mut link_keys = LinkKeys::default();
+    while let Some(option) = take_option(args, "--link-key") {
+        let parsed = option.split_once('=')
+            .and_then(|(ports, key)| Some((ports.split_once('-')?, key)))
+            .and_then(|((first, second), key)| Some((first.parse::<u16>().ok()?, second.parse::<u16>().ok()?, key)));
+        match parsed {
+            Some((first_port, second_port, key)) if !key.is_empty() => link_keys.insert(first_port, second_port, key),
+            _ => panic!("{}", failure_message(&format!("Expected <port>-<port>=<key>, got '{}'", option))),
+        }
+    }
+    link_keys
+}
+
+/// Remove every `--register <name>=<kind>` from the arguments
+fn take_register_kinds(args: &mut Vec<String>) -> HashMap<String, RegisterKind> {
+    let mut register_kinds = HashMap::new();
+    while let Some(option) = take_option(args, "--register") {
+        let parsed = option.split_once('=')
+            .ok_or(format!("Expected <name>=<kind>, got '{}'", option))
+            .and_then(|(name, kind)| Ok((name.to_string(), kind.parse::<RegisterKind>()?)));
+        match parsed {
+            Ok((name, kind)) => { register_kinds.insert(name, kind); },
+            Err(err) => panic!("{}", failure_message(&err)),
+        }
+    }
+    register_kinds
+}
+
+fn set_config(args: &[String]) -> NodeConfig {
     if args.len() < 2 {
         panic!("{}", failure_message("Not enough arguments"));
     }
 
     let hub_address: SocketAddr = match args.get(1).unwrap().parse() {
         Ok(val) => val,
-        Err(err) => panic!("{} {}", failure_message("Invalid hub IP-port pair"), err.to_string())
+        Err(err) => panic!("{} {}", failure_message("Invalid hub IP-port pair"), err)
     };
 
+    NodeConfig::new(hub_address, parse_own_addresses(&args[2..]))
+}
+
+fn parse_own_addresses(args: &[String]) -> Vec<SocketAddr> {
     let mut own_addresses= Vec::with_capacity(3);
     for index in 0..3 {
-        let address= match args.get(2 + index) {
+        let address= match args.get(index) {
             Some(val) => val,
             None => panic!("Missing own IP-port pair number {}", index)
         };
@@ -132,10 +608,5 @@ fn set_config() -> Options {
         };
         own_addresses.push(address);
     }
-
-
-    Options {
-        hub_address,
-        own_addresses,
-    }
+    own_addresses
 }
@@ -1,6 +1,9 @@
+use std::io;
 use std::io::{Read, Write};
 use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
+use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
 use std::time::Duration;
@@ -8,79 +11,132 @@ use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
 use prost::bytes::{Bytes, BytesMut};
 use prost::Message;
 use uuid::Uuid;
-use crate::{protobuf, Envelope};
+use crate::{protobuf, trace, warn, Envelope};
+use crate::authenticated_link::{LinkKeys, ReplayWindow};
 use crate::protobuf::message::Type;
 
 
+/// How long a connection may stay silent before the listener gives up on it, so that a peer that connects and
+/// sends nothing cannot hold back the messages of everybody else
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+/// Longest message the listener reads, so that a made-up length cannot make it allocate without bound
+const MAX_MESSAGE_LENGTH: u32 = 16 * 1024 * 1024;
+
 pub struct NetworkService {
 }
 
 impl NetworkService {
-    pub fn start_listener(listening_socket: &SocketAddr, queue: Sender<Envelope>) -> JoinHandle<()> {
+    /// Listen for NetworkMessages, dropping the ones from processes sharing a key in `link_keys` with this one
+    /// unless their MAC matches. Returns the address actually listened on, which tells the port the system
+    /// picked when `listening_socket` asks for port 0.
+    pub fn start_listener(listening_socket: &SocketAddr, queue: Sender<Envelope>,
+                          shutdown: Arc<AtomicBool>, link_keys: LinkKeys) -> (SocketAddr, JoinHandle<()>) {
         // Open TCP Listener socket
         let server = TcpListener::bind(listening_socket).unwrap();
+        let listening_socket = server.local_addr().unwrap();
+        let own_port = listening_socket.port() as i32;
         let thread = thread::spawn(move || {
+            // Authenticated messages already delivered, so that replaying one does not deliver it twice
+            let mut authenticated = ReplayWindow::default();
             for stream in server.incoming() {
+                // Whoever requests the shutdown wakes us up with an empty connection
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
                 match stream {
                     Ok(mut stream) => {
-                        Self::receive(&mut stream, queue.clone());
+                        Self::receive(&mut stream, queue.clone(), &link_keys, own_port, &mut authenticated);
                     }
-                    Err(e) => { eprintln!("Server connection accept failed; {}", e)}
+                    Err(e) => { warn!("Server connection accept failed; {}", e)}
                 }
             }
         });
-        thread
+        (listening_socket, thread)
     }
 
-    // Read a NetworkMessage over a TCP connection, and transform it into a PL message
-    fn receive(connection: &mut TcpStream, queue: Sender<Envelope>) {
-        let message_buffer = Self::read(connection);
+    // Read a NetworkMessage over a TCP connection, and transform it into a PL message.
+    // Whatever a faulty peer sends, the connection is dropped with a warning rather than taking the listener down.
+    fn receive(connection: &mut TcpStream, queue: Sender<Envelope>, link_keys: &LinkKeys, own_port: i32,
+               authenticated: &mut ReplayWindow) {
+        let message_buffer = match Self::read(connection) {
+            Ok(val) => val,
+            Err(err) => {
+                warn!("Failed reading a message from {:?}; {}", connection.peer_addr(), err);
+                return;
+            }
+        };
         let envelope = match Envelope::decode(message_buffer) {
             Ok(val) => val,
-            Err(err) => panic!("Failed to decode message from {:?}; {}", connection.peer_addr(), err)
+            Err(err) => {
+                warn!("Failed to decode message from {:?}; {}", connection.peer_addr(), err);
+                return;
+            }
         };
 
         if envelope.r#type() != Type::NetworkMessage {
-            eprintln!("Received a message from the network that is not a NetworkMessage");
+            warn!("Received a message from the network that is not a NetworkMessage");
             return;
         }
 
+        if !link_keys.verify(&envelope, own_port) {
+            warn!("Dropped a message whose MAC does not match the one of its sender");
+            return;
+        }
         let net_msg = match envelope.network_message {
             Some(val) => val,
-            None => panic!("Message from {:?} field NetworkMessage is not populated", connection.peer_addr())
+            None => {
+                warn!("Message from {:?} field NetworkMessage is not populated", connection.peer_addr());
+                return;
+            }
         };
+        let sender_port = net_msg.sender_listening_port;
+        if link_keys.key(sender_port, own_port).is_some() && !authenticated.accept(sender_port, net_msg.sequence_number) {
+            warn!("Dropped a replayed message from port {}", sender_port);
+            return;
+        }
 
         let payload = match net_msg.message {
             Some(val) => val,
-            None => panic!("Message from {}:{} has a NetworkMessage but contains no inner message",
-                           net_msg.sender_host, net_msg.sender_listening_port)
+            None => {
+                warn!("Message from {}:{} has a NetworkMessage but contains no inner message",
+                      net_msg.sender_host, net_msg.sender_listening_port);
+                return;
+            }
         };
 
-        let mut pl_deliver = protobuf::PlDeliver::default();
-        pl_deliver.message = Option::from(payload);
-        pl_deliver.sender = Option::from(protobuf::ProcessId {
-            host: net_msg.sender_host,
-            port: net_msg.sender_listening_port,
-            ..Default::default()
-        });
+        let pl_deliver = protobuf::PlDeliver {
+            message: Option::from(payload),
+            sender: Option::from(protobuf::ProcessId {
+                host: net_msg.sender_host,
+                port: net_msg.sender_listening_port,
+                ..Default::default()
+            }),
+        };
 
-        let mut to_be_added = Envelope::default();
-        to_be_added.pl_deliver = Self::wrap_envelope_contents(pl_deliver);
+        let mut to_be_added = Envelope {
+            pl_deliver: Self::wrap_envelope_contents(pl_deliver),
+            to_abstraction_id: envelope.to_abstraction_id,
+            system_id: envelope.system_id,
+            ..Default::default()
+        };
         to_be_added.set_type(Type::PlDeliver);
-        to_be_added.to_abstraction_id = envelope.to_abstraction_id;
 
-        // println!("Got message: {:?}", to_be_added);
+        trace!("Got message: {:?}", to_be_added);
 
-        match queue.send(to_be_added) {
-            Ok(_) => {},
-            Err(err) => panic!(
-                "[{:?}] Could not add received message to internal queue; {}",
-                connection.local_addr(), err)
-        };
+        // The client may already be gone when the node shuts down, which is no reason to take the listener down
+        if let Err(err) = queue.send(to_be_added) {
+            warn!("[{:?}] Could not add received message to internal queue; {}", connection.local_addr(), err);
+        }
     }
 
     /// Transform a PL message into a NetworkMessage, and send it over to a host via TCP
     pub fn send(destination: &SocketAddr, message: protobuf::Message, reply_port: u16) {
+        Self::send_authenticated(destination, message, reply_port, &LinkKeys::default())
+    }
+
+    /// Like `send`, signing the NetworkMessage when `link_keys` has a key shared with the destination
+    pub fn send_authenticated(destination: &SocketAddr, message: protobuf::Message, reply_port: u16,
+                              link_keys: &LinkKeys) {
         // We implement a Perfect Link using TCP connections.
         // The specification requires we strip the outer Envelope and the PL_Send-layer message.
         let inner = message.pl_send
@@ -88,10 +144,12 @@ impl NetworkService {
             .message.expect("Tried to send an empty PL_Send message");
 
         // Next, we must wrap the inner message in a NetworkMessage, and then an Envelope
-        let mut network_message = protobuf::NetworkMessage::default();
-        network_message.message = Option::from(Box::from(inner));
-        network_message.sender_listening_port = reply_port as i32;
-        network_message.sender_host = "127.0.0.1".to_string();
+        let network_message = protobuf::NetworkMessage {
+            message: Option::from(inner),
+            sender_listening_port: reply_port as i32,
+            sender_host: "127.0.0.1".to_string(),
+            ..Default::default()
+        };
 
         let mut network_message_wrapper = Envelope::default();
         network_message_wrapper.set_type(Type::NetworkMessage);
@@ -99,44 +157,40 @@ impl NetworkService {
         network_message_wrapper.system_id = message.system_id;
         network_message_wrapper.to_abstraction_id = message.to_abstraction_id;
         network_message_wrapper.message_uuid = Uuid::new_v4().to_string();
+        link_keys.sign(&mut network_message_wrapper, destination.port() as i32);
 
         // Actually send the message
-        Self::write(destination, &*network_message_wrapper.encode_to_vec())
+        Self::write(destination, &network_message_wrapper.encode_to_vec())
     }
     fn write(destination: &SocketAddr, message: &[u8]) {
+        // A peer that is down (or a hub that was never started) must not take this process down with it
         let mut connection = match TcpStream::connect_timeout(
             destination,
             Duration::new(10, 0)
         ) {
             Ok(val) => val,
-            Err(err) => panic!("Connecting to {} failed; {}", destination, err)
+            Err(err) => {
+                warn!("Connecting to {} failed; {}", destination, err);
+                return;
+            }
         };
-        Self::write_message_length(&mut connection, message.len());
-        connection.write_all(message).unwrap();
-    }
-
-    fn write_message_length(connection: &mut TcpStream, length: usize) {
-        let mut message_length = vec![];
-        message_length.write_u32::<NetworkEndian>(length as u32).expect("TODO: panic message");
-        connection.write(&*message_length).expect("TODO: panic message");
+        let mut framed = Vec::with_capacity(4 + message.len());
+        framed.write_u32::<NetworkEndian>(message.len() as u32).unwrap();
+        framed.extend_from_slice(message);
+        if let Err(err) = connection.write_all(&framed) {
+            warn!("Sending {} octets to {} failed; {}", message.len(), destination, err);
+        }
     }
 
-    fn read(connection: &mut TcpStream) -> Bytes {
-        let length = Self::read_message_length(connection);
+    fn read(connection: &mut TcpStream) -> io::Result<Bytes> {
+        connection.set_read_timeout(Some(READ_TIMEOUT))?;
+        let length = connection.read_u32::<NetworkEndian>()?;
+        if length > MAX_MESSAGE_LENGTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("a message of {} octets is too long", length)));
+        }
         let mut buffer  = BytesMut::zeroed(length as usize);
-
-        match connection.read_exact(&mut *buffer) {
-            Ok(_) => {},
-            Err(err) => panic!(
-                "Failed reading {} octets from {:?}; {}",
-                length, connection.peer_addr(), err
-            ),
-        };
-        buffer.freeze()
-    }
-
-    fn read_message_length(connection: &mut TcpStream) -> u32 {
-        connection.read_i32::<NetworkEndian>().expect("TODO: yeah, handle this some day") as u32
+        connection.read_exact(&mut buffer)?;
+        Ok(buffer.freeze())
     }
 
     pub fn wrap_envelope_contents<T>(contents: T) -> Option<Box<T>> {
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use uuid::Uuid;
+use crate::authenticated_link::LinkKeys;
+use crate::client::{Client, ProcessStatus};
+use crate::network_service::NetworkService;
+use crate::broadcast_manager::BroadcastGuarantee;
+use crate::probabilistic_broadcast_manager;
+use crate::register_log::RegisterLog;
+use crate::register_manager::RegisterKind;
+use crate::register_snapshot::{self, SystemSnapshot};
+use crate::value_codec::RegisterCodec;
+use crate::protobuf::message::Type;
+use crate::{protobuf, Envelope};
+
+/// Everything needed to bring up the processes of one owner
+pub struct NodeConfig {
+    pub hub_address: SocketAddr,
+    pub owner: String,
+    /// Where the processes listen; port 0 picks a free port, which `NodeHandle::own_addresses` then tells
+    pub own_addresses: Vec<SocketAddr>,
+    /// Register abstraction of each register name; unlisted registers are (N,N) atomic
+    pub register_kinds: HashMap<String, RegisterKind>,
+    /// Type of the values each register name holds; unlisted registers hold `i32` values
+    pub register_codecs: HashMap<String, RegisterCodec>,
+    /// Directory holding one register log per process; without it, registers only live in memory
+    pub data_dir: Option<PathBuf>,
+    /// Whether (N,N) atomic reads skip their write-back when a majority already agrees on the value
+    pub fast_reads: bool,
+    /// Processes each probabilistic broadcast relay goes to
+    pub gossip_fanout: usize,
+    /// Hops a probabilistic broadcast makes away from its source
+    pub gossip_rounds: i32,
+    /// Abstraction carrying app-level broadcasts
+    pub broadcast_guarantee: BroadcastGuarantee,
+    /// Whether best-effort broadcasts reach their own process through its listener, as they reach the others
+    pub beb_loopback: bool,
+    /// Keys shared with other processes, making the links to them authenticated perfect links
+    pub link_keys: LinkKeys,
+}
+
+impl NodeConfig {
+    pub fn new(hub_address: SocketAddr, own_addresses: Vec<SocketAddr>) -> Self {
+        NodeConfig {
+            hub_address,
+            owner: "uwu".to_string(),
+            own_addresses,
+            register_kinds: HashMap::new(),
+            register_codecs: HashMap::new(),
+            data_dir: None,
+            fast_reads: false,
+            gossip_fanout: probabilistic_broadcast_manager::DEFAULT_FANOUT,
+            gossip_rounds: probabilistic_broadcast_manager::DEFAULT_ROUNDS,
+            broadcast_guarantee: BroadcastGuarantee::default(),
+            beb_loopback: false,
+            link_keys: LinkKeys::default(),
+        }
+    }
+}
+
+pub struct Node {
+}
+
+/// Owns the listener and client threads started by `Node::start`
+pub struct NodeHandle {
+    stop_listening: Arc<AtomicBool>,
+    stop_clients: Arc<AtomicBool>,
+    owner: String,
+    own_addresses: Vec<SocketAddr>,
+    queues: Vec<Sender<Envelope>>,
+    statuses: Vec<Arc<Mutex<ProcessStatus>>>,
+    server_threads: Vec<JoinHandle<()>>,
+    client_threads: Vec<JoinHandle<usize>>,
+}
+
+/// What happened while the node was being stopped
+#[derive(Debug, Default)]
+pub struct ShutdownReport {
+    /// Register operations that were still waiting for a quorum and got abandoned
+    pub failed_operations: usize,
+    /// Listener or client threads that had panicked before they could be joined
+    pub panicked_threads: usize,
+}
+
+impl ShutdownReport {
+    /// Whether every thread stopped normally and no register operation was left without a reply
+    pub fn is_clean(&self) -> bool {
+        self.panicked_threads == 0 && self.failed_operations == 0
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        if self.is_clean() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+    }
+}
+
+impl Node {
+    /// Start one listener and one client per configured address, and register each process with the hub
+    pub fn start(config: NodeConfig) -> NodeHandle {
+        let stop_listening = Arc::new(AtomicBool::new(false));
+        let stop_clients = Arc::new(AtomicBool::new(false));
+        let process_count = config.own_addresses.len();
+
+        let mut queues = Vec::with_capacity(process_count);
+        let mut statuses = Vec::with_capacity(process_count);
+        let mut server_threads = Vec::with_capacity(process_count);
+        let mut client_threads = Vec::with_capacity(process_count);
+
+        let mut own_addresses = Vec::with_capacity(process_count);
+
+        for (index, node_socket) in config.own_addresses.iter().enumerate() {
+            // Create message queue for current node
+            let (tx, rx) = channel();
+
+            // Addresses with port 0 get a free port, which is the one the process goes by from now on
+            let (node_socket, server_thread) = NetworkService::start_listener(
+                node_socket, tx.clone(), stop_listening.clone(), config.link_keys.clone()
+            );
+            server_threads.push(server_thread);
+            own_addresses.push(node_socket);
+
+            let register_log = config.data_dir.as_ref().map(|dir| {
+                let path = dir.join(format!("{}-{}.wal", config.owner, index + 1));
+                fs::create_dir_all(dir)
+                    .and_then(|_| RegisterLog::open(&path))
+                    .unwrap_or_else(|e| panic!("Opening the register log {} should succeed; {}", path.display(), e))
+            });
+
+            // Start client for current node
+            let mut client = Client::new(rx, tx.clone(), node_socket.port(), &config, register_log, stop_clients.clone());
+            statuses.push(client.status());
+            let client_thread = thread::spawn(move || {
+                client.start_worker()
+            });
+            client_threads.push(client_thread);
+            queues.push(tx);
+
+            // Register the new node with the Hub
+            let connection_message = make_connection_message(
+                &config.owner, (index + 1) as u8, &config.hub_address
+            );
+            NetworkService::send(&config.hub_address, connection_message, node_socket.port());
+        }
+
+        NodeHandle {
+            stop_listening,
+            stop_clients,
+            owner: config.owner,
+            own_addresses,
+            queues,
+            statuses,
+            server_threads,
+            client_threads,
+        }
+    }
+}
+
+impl NodeHandle {
+    /// Message queue of the process with the given 1-based index
+    pub fn queue(&self, index: usize) -> Option<&Sender<Envelope>> {
+        index.checked_sub(1).and_then(|index| self.queues.get(index))
+    }
+
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    pub fn own_addresses(&self) -> &[SocketAddr] {
+        &self.own_addresses
+    }
+
+    /// Latest state published by each process, in index order
+    pub fn processes(&self) -> Vec<ProcessStatus> {
+        self.statuses.iter()
+            .map(|status| status.lock().unwrap().clone())
+            .collect()
+    }
+
+    /// Registers of the processes given by 1-based `indexes`, grouped by the system they are in
+    pub fn register_snapshot(&self, indexes: &[usize]) -> Vec<SystemSnapshot> {
+        let processes = self.processes();
+        let selected = indexes.iter()
+            .filter_map(|index| processes.get(index.wrapping_sub(1)).cloned())
+            .collect::<Vec<_>>();
+        register_snapshot::snapshot(&selected)
+    }
+
+    /// Put this node's own processes in a system of their own, the way the hub would with `system <owner>`
+    pub fn initialize_local_system(&self, system_id: &str) {
+        let processes = self.own_addresses.iter().enumerate()
+            .map(|(index, address)| protobuf::ProcessId {
+                host: address.ip().to_string(),
+                port: address.port() as i32,
+                owner: self.owner.clone(),
+                index: (index + 1) as i32,
+                rank: (index + 1) as i32,
+            })
+            .collect::<Vec<_>>();
+
+        for queue in &self.queues {
+            let mut init_wrapper = Envelope::with_shipping_label(Type::ProcInitializeSystem);
+            init_wrapper.proc_initialize_system = Option::from(protobuf::ProcInitializeSystem {
+                processes: processes.clone()
+            });
+            init_wrapper.system_id = system_id.to_string();
+            init_wrapper.to_abstraction_id = "app".to_string();
+            queue.send(init_wrapper).unwrap();
+        }
+    }
+
+    /// Let every client drain its queue and abandon the register operations still in flight, then stop
+    /// accepting connections, and join all threads
+    pub fn shutdown(self) -> ShutdownReport {
+        let mut report = ShutdownReport::default();
+
+        // The listeners keep running meanwhile, so that whatever the backlog sends to this node's own
+        // processes still finds someone listening; the drain is bounded, so they cannot keep the clients busy
+        self.stop_clients.store(true, Ordering::SeqCst);
+        for thread in self.client_threads {
+            match thread.join() {
+                Ok(failed_operations) => report.failed_operations += failed_operations,
+                Err(_) => report.panicked_threads += 1,
+            }
+        }
+
+        self.stop_listening.store(true, Ordering::SeqCst);
+        // Listeners block in accept(), so poke each of them with a connection that carries no message
+        for address in &self.own_addresses {
+            let _ = TcpStream::connect(address);
+        }
+        for thread in self.server_threads {
+            if thread.join().is_err() {
+                report.panicked_threads += 1;
+            }
+        }
+
+        report
+    }
+}
+
+fn make_connection_message(owner: &str, index: u8, destination: &SocketAddr) -> Envelope {
+    let register_message = protobuf::ProcRegistration {
+        owner: owner.to_string(),
+        index: index as i32,
+    };
+
+    let mut register_wrapper = Envelope {
+        proc_registration: Option::from(register_message),
+        ..Default::default()
+    };
+    register_wrapper.set_type(Type::ProcRegistration);
+    register_wrapper.to_abstraction_id = "app".to_string();
+    register_wrapper.message_uuid = Uuid::new_v4().to_string();
+
+    let pl_destination = protobuf::ProcessId {
+        host: destination.ip().to_string(),
+        port: destination.port() as i32,
+        owner: "ref".to_string(),
+        ..Default::default()
+    };
+    let pl_send_msg = protobuf::PlSend {
+        message: NetworkService::wrap_envelope_contents(register_wrapper),
+        destination: Option::from(pl_destination),
+    };
+
+
+    let mut pl_wrapper = protobuf::Message::default();
+    pl_wrapper.set_type(Type::PlSend);
+    pl_wrapper.from_abstraction_id = "app.pl".to_string();
+    pl_wrapper.to_abstraction_id = "app.pl".to_string();
+    pl_wrapper.pl_send = NetworkService::wrap_envelope_contents(pl_send_msg);
+    pl_wrapper.system_id = owner.to_string();
+    pl_wrapper.message_uuid = Uuid::new_v4().to_string();
+    pl_wrapper
+}
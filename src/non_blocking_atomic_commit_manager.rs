@@ -0,0 +1,184 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::Sender;
+use crate::{debug, protobuf, warn, Envelope};
+use crate::broadcast_manager::BroadcastManager;
+use crate::client::ClientState;
+use crate::hierarchical_consensus_manager::{request_propose, HierarchicalConsensusManager};
+use crate::perfect_link_manager::PerfectLinkManager;
+use crate::protobuf::message::Type;
+use crate::protobuf::ProcessId;
+
+pub const ABSTRACTION_ID: &str = "app.nbac";
+
+/// Non-blocking atomic commit, Consensus-Based, one instance per transaction under `app.nbac[transaction]`:
+/// every process that decides, decides the same, and it only commits if every process voted to commit and
+/// none crashed. Votes go over a best-effort broadcast; a consensus instance turns what each process saw
+/// into one decision. It relies on the perfect failure detector running.
+pub struct NonBlockingAtomicCommitManager {
+    /// Keyed by transaction
+    instances: HashMap<String, Instance>,
+    /// Processes detected as crashed, which makes the instances started later abort
+    crashed: Vec<ProcessId>,
+    tx: Sender<Envelope>,
+}
+
+struct Instance {
+    transaction: String,
+    abstraction_id: String,
+    consensus: HierarchicalConsensusManager,
+    /// Whether this process voted
+    voted: bool,
+    /// Ports of the processes whose vote to commit arrived
+    commits: HashSet<i32>,
+    proposed: bool,
+    decided: bool,
+    tx: Sender<Envelope>,
+}
+
+impl NonBlockingAtomicCommitManager {
+    pub fn new(tx: Sender<Envelope>) -> Self {
+        NonBlockingAtomicCommitManager {
+            instances: HashMap::new(),
+            crashed: Vec::new(),
+            tx,
+        }
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        if message.r#type() == Type::AppNbacVote {
+            let vote = message.app_nbac_vote.unwrap();
+            self.instance(&vote.transaction).vote(vote.commit, client_state);
+            return;
+        }
+
+        let Some(transaction) = instance_transaction(&message.to_abstraction_id) else {
+            warn!("{} got a {:?} for {}", ABSTRACTION_ID, message.r#type(), message.to_abstraction_id);
+            return;
+        };
+        self.instance(transaction).handle_message(message, client_state);
+    }
+
+    /// Called when the failure detector reports that `process` crashed
+    pub fn handle_crash(&mut self, process: &ProcessId, client_state: &ClientState) {
+        self.crashed.push(process.clone());
+        for instance in self.instances.values_mut() {
+            instance.consensus.handle_crash(process, client_state);
+            instance.propose(false);
+        }
+    }
+
+    fn instance(&mut self, transaction: &str) -> &mut Instance {
+        let crashed = &self.crashed;
+        let tx = &self.tx;
+        self.instances.entry(transaction.to_string())
+            .or_insert_with(|| {
+                let mut instance = Instance::new(transaction, crashed, tx.clone());
+                // A process that crashed already will never vote
+                if !crashed.is_empty() {
+                    instance.propose(false);
+                }
+                instance
+            })
+    }
+}
+
+impl Instance {
+    fn new(transaction: &str, crashed: &[ProcessId], tx: Sender<Envelope>) -> Self {
+        let abstraction_id = format!("{}[{}]", ABSTRACTION_ID, transaction);
+        Instance {
+            transaction: transaction.to_string(),
+            consensus: HierarchicalConsensusManager::new(consensus_abstraction_id(&abstraction_id), crashed, tx.clone()),
+            abstraction_id,
+            voted: false,
+            commits: HashSet::new(),
+            proposed: false,
+            decided: false,
+            tx,
+        }
+    }
+
+    fn vote(&mut self, commit: bool, client_state: &ClientState) {
+        if self.voted {
+            warn!("{} ignored a second vote", self.abstraction_id);
+            return;
+        }
+        self.voted = true;
+
+        let mut wrapper = Envelope::with_shipping_label(Type::NbacInternalVote);
+        wrapper.nbac_internal_vote = Option::from(protobuf::NbacInternalVote { commit });
+        wrapper.from_abstraction_id = self.abstraction_id.clone();
+        wrapper.to_abstraction_id = self.abstraction_id.clone();
+        BroadcastManager::do_beb_broadcast(wrapper, &self.tx, client_state);
+    }
+
+    fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        if message.to_abstraction_id.starts_with(&consensus_abstraction_id(&self.abstraction_id)) {
+            self.consensus.handle_message(message, client_state);
+            return;
+        }
+
+        match message.r#type() {
+            Type::PlDeliver | Type::BebDeliver => {
+                let (sender, inner) = BroadcastManager::unwrap_delivery(message).unwrap();
+                match inner.nbac_internal_vote {
+                    Some(vote) => self.handle_vote(vote.commit, sender, client_state),
+                    None => warn!("{} got an unknown message type: {:?}", self.abstraction_id, inner.r#type()),
+                }
+            },
+            Type::PlSend => PerfectLinkManager::handle_pl_send(message, &client_state.system_id, client_state.own_port, &client_state.link_keys),
+            Type::HcDecide => {
+                let value = message.hc_decide.unwrap().value.map(|value| *value).unwrap_or_default();
+                self.decide(value);
+            },
+            _ => warn!("{} got an unknown message type: {:?}", self.abstraction_id, message.r#type()),
+        }
+    }
+
+    fn handle_vote(&mut self, commit: bool, sender: ProcessId, client_state: &ClientState) {
+        if !commit {
+            debug!("{} aborts, as port {} voted to", self.abstraction_id, sender.port);
+            self.propose(false);
+            return;
+        }
+        self.commits.insert(sender.port);
+        if client_state.nodes.iter().all(|node| self.commits.contains(&node.port)) {
+            self.propose(true);
+        }
+    }
+
+    fn propose(&mut self, commit: bool) {
+        if self.proposed {
+            return;
+        }
+        self.proposed = true;
+
+        let decision = protobuf::AppNbacDecide { transaction: self.transaction.clone(), commit };
+        let mut wrapper = Envelope::with_shipping_label(Type::AppNbacDecide);
+        wrapper.app_nbac_decide = Option::from(decision);
+        wrapper.to_abstraction_id = "app".to_string();
+        request_propose(&consensus_abstraction_id(&self.abstraction_id), wrapper, &self.tx);
+    }
+
+    /// Hand the decision up to the app, which tells the hub
+    fn decide(&mut self, mut value: Envelope) {
+        if self.decided {
+            return;
+        }
+        self.decided = true;
+        debug!("{} decides {:?}", self.abstraction_id, value.app_nbac_decide);
+
+        value.from_abstraction_id = self.abstraction_id.clone();
+        value.to_abstraction_id = "app".to_string();
+        self.tx.send(value).unwrap();
+    }
+}
+
+fn consensus_abstraction_id(abstraction_id: &str) -> String {
+    format!("{}.hc", abstraction_id)
+}
+
+/// Transaction of the instance an abstraction id such as `app.nbac[t].hc.beb.pl` belongs to
+fn instance_transaction(abstraction_id: &str) -> Option<&str> {
+    let rest = abstraction_id.strip_prefix(ABSTRACTION_ID)?.strip_prefix('[')?;
+    rest.split_once(']').map(|(transaction, _)| transaction)
+}
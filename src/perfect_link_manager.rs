@@ -1,6 +1,11 @@
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use crate::Envelope;
+use std::sync::mpsc::Sender;
+use crate::{protobuf, Envelope};
+use crate::authenticated_link::LinkKeys;
+use crate::client::ClientState;
 use crate::network_service::NetworkService;
+use crate::protobuf::message::Type;
+use crate::protobuf::ProcessId;
 
 pub struct PerfectLinkManager {}
 
@@ -10,7 +15,8 @@ impl PerfectLinkManager {
         *inner
     }
 
-    pub fn handle_pl_send(message: Envelope, my_system_id: &str, my_port: u16) {
+    /// Send the message inside a PlSend over the network, signed if `link_keys` holds a key shared with its destination
+    pub fn handle_pl_send(message: Envelope, my_system_id: &str, my_port: u16, link_keys: &LinkKeys) {
         let mut to_be_sent = message.clone();
         to_be_sent.to_abstraction_id = format!("{}.pl", message.to_abstraction_id);
         to_be_sent.system_id = my_system_id.to_string();
@@ -22,6 +28,37 @@ impl PerfectLinkManager {
         let destination_port = destination_data.port as u16;
         let destination_socket = SocketAddr::new(IpAddr::V4(destination_ip), destination_port);
 
-        NetworkService::send(&destination_socket, to_be_sent, my_port);
+        NetworkService::send_authenticated(&destination_socket, to_be_sent, my_port, link_keys);
+    }
+
+    /// Send `message` to another process of the system right away, on behalf of `abstraction_id`
+    pub fn send_to(message: Envelope, destination: ProcessId, abstraction_id: &str, client_state: &ClientState) {
+        let pl_send = protobuf::PlSend {
+            message: NetworkService::wrap_envelope_contents(message),
+            destination: Option::from(destination),
+        };
+
+        let mut pl_send_wrapper = Envelope::with_shipping_label(Type::PlSend);
+        pl_send_wrapper.pl_send = NetworkService::wrap_envelope_contents(pl_send);
+        pl_send_wrapper.to_abstraction_id = abstraction_id.to_string();
+
+        Self::handle_pl_send(pl_send_wrapper, &client_state.system_id, client_state.own_port, &client_state.link_keys);
+    }
+
+    /// Queue `message` for the hub, e.g. an AppValue or an AppReadReturn
+    pub fn send_to_hub(message: Envelope, hub_socket: &SocketAddr, tx: &Sender<Envelope>) {
+        let pl_send = protobuf::PlSend {
+            message: NetworkService::wrap_envelope_contents(message),
+            destination: Option::from(ProcessId {
+                host: hub_socket.ip().to_string(),
+                port: hub_socket.port() as i32,
+                ..Default::default()
+            }),
+        };
+
+        let mut pl_send_wrapper = Envelope::with_shipping_label(Type::PlSend);
+        pl_send_wrapper.pl_send = NetworkService::wrap_envelope_contents(pl_send);
+
+        tx.send(pl_send_wrapper).unwrap();
     }
 }
@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+use std::sync::mpsc::Sender;
+use uuid::Uuid;
+use crate::{protobuf, warn, Envelope};
+use crate::client::ClientState;
+use crate::network_service::NetworkService;
+use crate::perfect_link_manager::PerfectLinkManager;
+use crate::protobuf::message::Type;
+use crate::protobuf::ProcessId;
+use crate::reliable_broadcast_manager::deliver_to_parent;
+
+pub const ABSTRACTION_ID: &str = "app.pb";
+
+/// Processes each relay goes to, unless configured otherwise
+pub const DEFAULT_FANOUT: usize = 3;
+/// Hops a message makes away from its source, unless configured otherwise
+pub const DEFAULT_ROUNDS: i32 = 3;
+
+/// Messages a process sent and received through probabilistic broadcast since it joined its system
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GossipMetrics {
+    /// Messages this process broadcast
+    pub broadcast: usize,
+    /// Relays sent to other processes
+    pub sent: usize,
+    /// Messages delivered, its own included
+    pub delivered: usize,
+    /// Relays received for messages already delivered
+    pub duplicates: usize,
+}
+
+/// Probabilistic broadcast, Eager Probabilistic Broadcast: each process relays a message the first time
+/// it gets it, to `fanout` processes picked at random, until the message made `rounds` hops.
+/// With enough of both, every correct process delivers it with high probability, while a process
+/// only opens `fanout` connections per message however large the system is.
+pub struct ProbabilisticBroadcastManager {
+    fanout: usize,
+    rounds: i32,
+    /// Ids of the messages delivered so far
+    delivered: HashSet<String>,
+    metrics: GossipMetrics,
+    tx: Sender<Envelope>,
+}
+
+impl ProbabilisticBroadcastManager {
+    pub fn new(fanout: usize, rounds: i32, tx: Sender<Envelope>) -> Self {
+        ProbabilisticBroadcastManager {
+            fanout,
+            rounds,
+            delivered: HashSet::new(),
+            metrics: GossipMetrics::default(),
+            tx,
+        }
+    }
+
+    pub fn metrics(&self) -> GossipMetrics {
+        self.metrics
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        match message.r#type() {
+            Type::RbBroadcast => self.broadcast(message, client_state),
+            Type::PlDeliver => {
+                let inner = *message.pl_deliver.unwrap().message.unwrap();
+                match inner.pb_internal_data {
+                    Some(data) => self.handle_data(*data, client_state),
+                    None => warn!("{} got an unknown message type: {:?}", ABSTRACTION_ID, inner.r#type()),
+                }
+            },
+            _ => warn!("{} got an unknown message type: {:?}", ABSTRACTION_ID, message.r#type()),
+        }
+    }
+
+    fn broadcast(&mut self, message: Envelope, client_state: &ClientState) {
+        let Some(source) = client_state.own_process().cloned() else {
+            warn!("{} cannot broadcast outside of a system", ABSTRACTION_ID);
+            return;
+        };
+        let inner = *message.rb_broadcast.unwrap().message.unwrap();
+        self.metrics.broadcast += 1;
+
+        let data = protobuf::PbInternalData {
+            message_id: inner.message_uuid.clone(),
+            source: Option::from(source.clone()),
+            message: NetworkService::wrap_envelope_contents(inner.clone()),
+            rounds: self.rounds,
+        };
+
+        self.deliver(data.message_id.clone(), inner, source);
+        self.gossip(data, client_state);
+    }
+
+    fn handle_data(&mut self, data: protobuf::PbInternalData, client_state: &ClientState) {
+        if self.delivered.contains(&data.message_id) {
+            self.metrics.duplicates += 1;
+            return;
+        }
+        let (Some(message), Some(source)) = (data.message.clone(), data.source.clone()) else {
+            warn!("{} got message {} without its contents", ABSTRACTION_ID, data.message_id);
+            return;
+        };
+        self.deliver(data.message_id.clone(), *message, source);
+
+        if data.rounds > 1 {
+            self.gossip(protobuf::PbInternalData { rounds: data.rounds - 1, ..data }, client_state);
+        }
+    }
+
+    fn deliver(&mut self, message_id: String, message: Envelope, source: ProcessId) {
+        self.delivered.insert(message_id);
+        self.metrics.delivered += 1;
+        deliver_to_parent(ABSTRACTION_ID, message, source, &self.tx);
+    }
+
+    fn gossip(&mut self, data: protobuf::PbInternalData, client_state: &ClientState) {
+        let mut wrapper = Envelope::with_shipping_label(Type::PbInternalData);
+        wrapper.pb_internal_data = NetworkService::wrap_envelope_contents(data);
+        wrapper.from_abstraction_id = ABSTRACTION_ID.to_string();
+        wrapper.to_abstraction_id = ABSTRACTION_ID.to_string();
+
+        for target in self.pick_targets(client_state) {
+            PerfectLinkManager::send_to(wrapper.clone(), target, ABSTRACTION_ID, client_state);
+            self.metrics.sent += 1;
+        }
+    }
+
+    /// Up to `fanout` distinct processes other than this one, picked at random
+    fn pick_targets(&self, client_state: &ClientState) -> Vec<ProcessId> {
+        let mut candidates = client_state.nodes.iter()
+            .filter(|node| node.port != client_state.own_port as i32)
+            .cloned()
+            .collect::<Vec<_>>();
+        let count = self.fanout.min(candidates.len());
+        for picked in 0..count {
+            let remaining = (candidates.len() - picked) as u128;
+            let chosen = picked + (Uuid::new_v4().as_u128() % remaining) as usize;
+            candidates.swap(picked, chosen);
+        }
+        candidates.truncate(count);
+        candidates
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use crate::protobuf::ProcessId;
+
+/// Replies gathered from distinct processes of a system, until enough of them answered.
+/// A process that replies twice is only counted once.
+pub struct Quorum<T> {
+    threshold: usize,
+    replies: HashMap<ProcessId, T>,
+}
+
+impl<T> Quorum<T> {
+    /// Reached once more than half of the `system_size` processes replied
+    pub fn majority(system_size: usize) -> Self {
+        Quorum {
+            threshold: system_size / 2 + 1,
+            replies: HashMap::new(),
+        }
+    }
+
+    /// Record the reply of `sender`, returning false if it had already replied
+    pub fn insert(&mut self, sender: ProcessId, reply: T) -> bool {
+        if self.replies.contains_key(&sender) {
+            return false;
+        }
+        self.replies.insert(sender, reply);
+        true
+    }
+
+    pub fn is_reached(&self) -> bool {
+        self.replies.len() >= self.threshold
+    }
+
+    pub fn len(&self) -> usize {
+        self.replies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replies.is_empty()
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn replies(&self) -> impl Iterator<Item = &T> {
+        self.replies.values()
+    }
+
+    pub fn senders(&self) -> impl Iterator<Item = &ProcessId> {
+        self.replies.keys()
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::num::ParseIntError;
+use std::str::FromStr;
+use std::path::Path;
+use prost::Message;
+use crate::{info, protobuf, warn};
+use crate::tag::Tag;
+
+/// Write-ahead log of the NNAR replicas of one process, so that after a crash it comes back with
+/// what it had acknowledged instead of the undefined value.
+/// One record per line, appended and synced to disk before the caller goes on:
+///
+/// ```text
+/// W <system id> <register> <timestamp> <writer rank> <hex-encoded protobuf::Value>
+/// R <system id> <register> <read id>
+/// ```
+///
+/// Records only get appended while the process runs; opening the log compacts it to the last write and the
+/// last read id of each register, so it grows with the operations of one run rather than of every run.
+///
+/// System ids and register names have their spaces, line breaks and `%` written as `%XX` escapes, so that any
+/// name stays a single field.
+pub struct RegisterLog {
+    file: File,
+    recovered: HashMap<(String, String), LoggedRegister>,
+}
+
+/// What the log holds about one register
+#[derive(Clone, Debug, Default)]
+pub struct LoggedRegister {
+    pub tag: Tag,
+    pub value: protobuf::Value,
+    /// Last read id handed out, so that replies to operations started before the crash are not mistaken for new ones
+    pub read_id: i32,
+}
+
+impl RegisterLog {
+    /// Open the log at `path`, creating it if needed, and replay the records it already holds
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut recovered: HashMap<(String, String), LoggedRegister> = HashMap::new();
+        if path.exists() {
+            let mut records = 0;
+            for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+                let line = line?;
+                records += 1;
+                // A crash in the middle of an append leaves a partial last line
+                if let Err(e) = Self::replay(&line, &mut recovered) {
+                    warn!("Skipping line {} of {}: {}", number + 1, path.display(), e);
+                }
+            }
+            info!("Recovered {} register(s) from {}", recovered.len(), path.display());
+
+            let compacted = Self::compacted(&recovered);
+            if compacted.len() < records {
+                Self::rewrite(path, &compacted)?;
+                info!("Compacted {} from {} to {} record(s)", path.display(), records, compacted.len());
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(RegisterLog { file, recovered })
+    }
+
+    /// State of `register` in `system_id` as it was when the process stopped
+    pub fn recovered(&self, system_id: &str, register: &str) -> Option<&LoggedRegister> {
+        self.recovered.get(&(system_id.to_string(), register.to_string()))
+    }
+
+    /// Persist the replica of `register` before it gets acknowledged
+    pub fn log_write(&mut self, system_id: &str, register: &str, tag: Tag, value: &protobuf::Value) -> io::Result<()> {
+        self.append(&write_record(system_id, register, tag, value))
+    }
+
+    /// Persist a read id before the operation using it goes out
+    pub fn log_read_id(&mut self, system_id: &str, register: &str, read_id: i32) -> io::Result<()> {
+        self.append(&read_id_record(system_id, register, read_id))
+    }
+
+    /// The fewest records that replay to `recovered`: the last write and the last read id of each register
+    fn compacted(recovered: &HashMap<(String, String), LoggedRegister>) -> Vec<String> {
+        let mut registers = recovered.iter().collect::<Vec<_>>();
+        registers.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut records = vec![];
+        for ((system_id, register), logged) in registers {
+            if logged.tag != Tag::default() || logged.value != protobuf::Value::default() {
+                records.push(write_record(system_id, register, logged.tag, &logged.value));
+            }
+            if logged.read_id != 0 {
+                records.push(read_id_record(system_id, register, logged.read_id));
+            }
+        }
+        records
+    }
+
+    /// Replace the log at `path` with `records`, through a temporary file so that a crash leaves either log whole
+    fn rewrite(path: &Path, records: &[String]) -> io::Result<()> {
+        let temporary = path.with_extension("wal.tmp");
+        let mut file = File::create(&temporary)?;
+        for record in records {
+            writeln!(file, "{}", record)?;
+        }
+        file.sync_all()?;
+        fs::rename(&temporary, path)
+    }
+
+    fn append(&mut self, record: &str) -> io::Result<()> {
+        writeln!(self.file, "{}", record)?;
+        self.file.sync_data()
+    }
+
+    fn replay(line: &str, recovered: &mut HashMap<(String, String), LoggedRegister>) -> Result<(), String> {
+        let fields = line.split(' ').collect::<Vec<_>>();
+
+        match (fields.first(), fields.len()) {
+            (Some(&"W"), 6) => {
+                let bytes = (0..fields[5].len()).step_by(2)
+                    .map(|i| fields[5].get(i..i + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or("invalid hex value")?;
+                let value = protobuf::Value::decode(bytes.as_slice()).map_err(|e| e.to_string())?;
+
+                let tag = Tag::new(parse_field(fields[3])?, parse_field(fields[4])?);
+
+                let register = recovered.entry((unescape(fields[1])?, unescape(fields[2])?)).or_default();
+                register.tag = tag;
+                register.value = value;
+            },
+            (Some(&"R"), 4) => {
+                let read_id = parse_field(fields[3])?;
+                recovered.entry((unescape(fields[1])?, unescape(fields[2])?)).or_default().read_id = read_id;
+            },
+            _ => return Err("unknown record".to_string()),
+        }
+        Ok(())
+    }
+}
+
+fn write_record(system_id: &str, register: &str, tag: Tag, value: &protobuf::Value) -> String {
+    let encoded = value.encode_to_vec().iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    format!("W {} {} {} {} {}", escape(system_id), escape(register), tag.ts, tag.rank, encoded)
+}
+
+fn read_id_record(system_id: &str, register: &str, read_id: i32) -> String {
+    format!("R {} {} {}", escape(system_id), escape(register), read_id)
+}
+
+fn parse_field<T: FromStr<Err = ParseIntError>>(field: &str) -> Result<T, String> {
+    field.parse().map_err(|e: ParseIntError| format!("'{}': {}", field, e))
+}
+
+/// A name as written in a record, with the characters that would split or end the record escaped
+fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for character in name.chars() {
+        match character {
+            ' ' | '%' | '\n' | '\r' => escaped.push_str(&format!("%{:02X}", character as u8)),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+fn unescape(field: &str) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok()).ok_or("truncated escape")?;
+            bytes.push(u8::from_str_radix(hex, 16).map_err(|e| format!("escape '%{}': {}", hex, e))?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
@@ -1,348 +1,712 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 use std::sync::mpsc::Sender;
-use regex::Regex;
-use crate::{protobuf, Envelope};
+use std::sync::{Arc, Mutex};
+use crate::{debug, info, protobuf, warn, Envelope};
 use crate::broadcast_manager::BroadcastManager;
 use crate::client::ClientState;
-use crate::network_service::NetworkService;
+use crate::event_context::EventContext;
 use crate::perfect_link_manager::PerfectLinkManager;
+use crate::quorum::Quorum;
+use crate::register_log::RegisterLog;
+use crate::tag::Tag;
 use crate::protobuf::message::Type;
 use crate::protobuf::{NnarInternalValue, ProcessId};
+use crate::value_codec::{I32Codec, ValueCodec};
 
 type RegisterValue = protobuf::Value;
 
-pub struct RegisterManager {
-    registers: HashMap<String, Register>,
+/// What a register holds, `None` being the undefined value
+pub(crate) type Contents<C> = Option<<C as ValueCodec>::Value>;
+
+/// Which register abstraction serves a register name. Every process of a system must agree on it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RegisterKind {
+    /// (N,N) atomic register, Read-Impose Write-Consult-Majority
+    #[default]
+    Nnar,
+    /// (1,N) regular register, Read-One Write-All; relies on the perfect failure detector
+    ReadOneWriteAll,
+    /// (1,N) regular register, Majority Voting
+    MajorityVoting,
+    /// (1,N) atomic register, Read-Impose Write-Majority
+    SingleWriterAtomic,
+}
+
+/// The replica a process holds for one register
+#[derive(Clone, Debug)]
+pub struct RegisterSummary {
+    pub name: String,
+    /// Abstraction serving the register, e.g. `app.nnar[x]`
+    pub abstraction_id: String,
+    pub tag: Tag,
+    pub value: RegisterValue,
+    /// Reads and writes started by this process that have not returned yet, in the order they will complete
+    pub operations: Vec<OperationSummary>,
+}
+
+/// Where a read or write started by a process stands
+#[derive(Clone, Debug)]
+pub struct OperationSummary {
+    /// Id its messages carry, `None` while queued
+    pub read_id: Option<i32>,
+    pub is_write: bool,
+    pub phase: OperationPhase,
+    /// Distinct replicas that answered during the current phase
+    pub replies: usize,
+    /// Replies the current phase waits for
+    pub needed: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperationPhase {
+    /// Waiting for the operation in flight to return
+    Queued,
+    /// Collecting the values held by the replicas
+    Query,
+    /// Waiting for the replicas to acknowledge a value
+    Impose,
+}
+
+/// Registers of one process, holding values of the type described by `C`
+pub struct RegisterManager<C: ValueCodec = I32Codec> {
+    registers: HashMap<String, Register<C>>,
+    /// Where replicas are persisted, for crash-recovery
+    log: Option<Arc<Mutex<RegisterLog>>>,
+    /// Whether reads may skip their write-back, see `with_fast_reads`
+    fast_reads: bool,
     tx: Sender<Envelope>,
 }
 
+/// How the reads started by a process completed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReadMetrics {
+    /// Reads that returned right after the query phase
+    pub fast: usize,
+    /// Reads that imposed the value they return on a majority first
+    pub slow: usize,
+}
+
 
 // Handles all messages addressed to `app.nnar` and its subroutes
-impl RegisterManager {
+impl<C: ValueCodec> RegisterManager<C> {
     pub fn new(tx: Sender<Envelope>) -> Self {
         RegisterManager {
             registers: HashMap::new(),
+            log: None,
+            fast_reads: false,
             tx
         }
     }
 
+    /// Let reads skip the write-back when a majority already holds the highest tag.
+    /// Every later query phase meets that majority, so no later read can return an older value.
+    pub fn with_fast_reads(self, enabled: bool) -> Self {
+        RegisterManager {
+            fast_reads: enabled,
+            ..self
+        }
+    }
+
+    /// Registers that persist their replicas to `log` before acknowledging writes, and recover from it
+    pub fn with_log(tx: Sender<Envelope>, log: Arc<Mutex<RegisterLog>>) -> Self {
+        RegisterManager {
+            log: Some(log),
+            ..Self::new(tx)
+        }
+    }
+
     pub fn handle_message(&mut self, message: Envelope, client_state: ClientState) {
         // Forward to the right Register
-        let regex = Regex::new(r"^.*\[(?<register>.*)].*$")
-            .expect("Register regex should be valid");
-
         let destination = message.to_abstraction_id.clone();
-        let register_name = match regex.captures(&destination).unwrap().name("register") {
-            Some(val) => val.as_str(),
-            None => panic!("Message not addressed to NNAR arrived in RegisterManager")
+        let Some(register_name) = register_name(&destination) else {
+            warn!("Message not addressed to NNAR arrived in RegisterManager: {}", destination);
+            return;
         };
 
+        let context = EventContext::local(&message);
         match self.registers.get_mut(register_name) {
-            Some(register) => register.handle_message(message, client_state),
+            Some(register) => register.handle_message(message, context, client_state),
             None => {
                 self.registers.insert(
                     register_name.to_string(),
-                    Register::new(self.tx.clone(), register_name)
+                    Register::new(self.tx.clone(), register_name, self.log.clone(), self.fast_reads, &client_state.system_id)
                 );
                 self.registers.get_mut(register_name).unwrap()
-                    .handle_message(message, client_state);
+                    .handle_message(message, context, client_state);
             }
         };
     }
+
+    pub fn summaries(&self) -> Vec<RegisterSummary> {
+        let mut summaries = self.registers.values()
+            .map(|register| register.summary())
+            .collect::<Vec<_>>();
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        summaries
+    }
+
+    pub fn read_metrics(&self) -> ReadMetrics {
+        self.registers.values().fold(ReadMetrics::default(), |total, register| ReadMetrics {
+            fast: total.fast + register.read_metrics.fast,
+            slow: total.slow + register.read_metrics.slow,
+        })
+    }
+
+    /// Abandon every read or write still waiting for a quorum, returning how many there were
+    pub fn fail_pending_operations(&mut self) -> usize {
+        self.registers.values_mut()
+            .map(|register| register.fail_pending_operations())
+            .sum()
+    }
+}
+
+/// What the application asked a register to do
+pub(crate) enum OperationKind<C: ValueCodec> {
+    Read,
+    Write(C::Value),
+}
+
+/// A replica's answer to the query phase
+pub(crate) struct Receipt<C: ValueCodec> {
+    pub tag: Tag,
+    pub value: Contents<C>,
+}
+
+impl RegisterKind {
+    /// Abstraction id under which the register called `register` runs
+    pub fn abstraction_id(&self, register: &str) -> String {
+        match self {
+            RegisterKind::Nnar => format!("app.nnar[{}]", register),
+            RegisterKind::ReadOneWriteAll | RegisterKind::MajorityVoting => format!("app.onrr[{}]", register),
+            RegisterKind::SingleWriterAtomic => format!("app.onar[{}]", register),
+        }
+    }
+}
+
+impl FromStr for RegisterKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nnar" => Ok(RegisterKind::Nnar),
+            "rowa" => Ok(RegisterKind::ReadOneWriteAll),
+            "majority" => Ok(RegisterKind::MajorityVoting),
+            "onar" => Ok(RegisterKind::SingleWriterAtomic),
+            _ => Err(format!("Unknown register kind '{}', expected nnar, rowa, majority or onar", s)),
+        }
+    }
+}
+
+impl<C: ValueCodec> Receipt<C> {
+    pub fn from_wire(value: NnarInternalValue) -> Result<Self, String> {
+        Ok(Receipt {
+            tag: Tag::from_wire(value.timestamp, value.writer_rank)?,
+            value: C::from_wire(&value.value.unwrap_or_default())?,
+        })
+    }
+
+    /// The receipt carrying the highest tag
+    pub fn highest<'a>(receipts: impl Iterator<Item = &'a Self>) -> Option<&'a Self> where C: 'a {
+        receipts.max_by_key(|receipt| receipt.tag)
+    }
 }
 
-struct Register {
-    timestamp: usize,
-    writer_rank: usize,
-    value: RegisterValue,
-    ack_count: usize,
-    my_value_for_writing: RegisterValue,
-    my_value_for_reading: RegisterValue,
+/// A read or write in flight, from its query phase until a majority acknowledged the write-back
+pub(crate) struct Operation<C: ValueCodec> {
+    pub kind: OperationKind<C>,
+    pub read_receipts: Quorum<Receipt<C>>,
+    pub acks: Quorum<()>,
+    /// Value imposed on a majority once the query phase is over; for reads, this is what gets returned
+    pub imposed_value: Option<Contents<C>>,
+}
+
+struct Register<C: ValueCodec> {
+    tag: Tag,
+    value: Contents<C>,
     read_tracking_counter: usize,
-    read_receipts: HashMap<ProcessId, NnarInternalValue>,
-    am_i_reading: bool,
-    reply_to: ProcessId,
+    /// Operations started by this process, keyed by their read id
+    operations: HashMap<i32, Operation<C>>,
+    /// Client requests that arrived while another operation was in flight
+    queued_operations: VecDeque<OperationKind<C>>,
 
     my_name: String,
+    log: Option<Arc<Mutex<RegisterLog>>>,
+    fast_reads: bool,
+    read_metrics: ReadMetrics,
 
     tx: Sender<Envelope>
 }
 
-impl Register {
-    fn new(tx: Sender<Envelope>, name: &str) -> Self {
-        Self {
-            timestamp: 0,
-            writer_rank: 0,
-            value: RegisterValue {defined: false, v: 0},
-            ack_count: 0,
-            my_value_for_writing: RegisterValue {defined: false, v: 0},
-            my_value_for_reading: RegisterValue {defined: false, v: 0},
+impl<C: ValueCodec> Operation<C> {
+    pub fn new(kind: OperationKind<C>, system_size: usize) -> Self {
+        Operation {
+            kind,
+            read_receipts: Quorum::majority(system_size),
+            acks: Quorum::majority(system_size),
+            imposed_value: None,
+        }
+    }
+
+    /// Progress of the operation, whose messages carry `read_id`
+    pub fn summary(&self, read_id: i32) -> OperationSummary {
+        let (phase, replies, needed) = match self.imposed_value {
+            None => (OperationPhase::Query, self.read_receipts.len(), self.read_receipts.threshold()),
+            Some(_) => (OperationPhase::Impose, self.acks.len(), self.acks.threshold()),
+        };
+        OperationSummary { read_id: Some(read_id), is_write: self.kind.is_write(), phase, replies, needed }
+    }
+}
+
+impl<C: ValueCodec> OperationKind<C> {
+    pub fn is_write(&self) -> bool {
+        matches!(self, OperationKind::Write(_))
+    }
+
+    /// Progress of the operation while it waits behind the one in flight
+    pub fn queued_summary(&self) -> OperationSummary {
+        OperationSummary { read_id: None, is_write: self.is_write(), phase: OperationPhase::Queued, replies: 0, needed: 0 }
+    }
+}
+
+impl fmt::Display for OperationPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperationPhase::Queued => write!(f, "queued"),
+            OperationPhase::Query => write!(f, "query"),
+            OperationPhase::Impose => write!(f, "impose"),
+        }
+    }
+}
+
+impl<C: ValueCodec> Register<C> {
+    fn new(tx: Sender<Envelope>, name: &str, log: Option<Arc<Mutex<RegisterLog>>>, fast_reads: bool,
+           system_id: &str) -> Self {
+        let mut register = Self {
+            tag: Tag::default(),
+            value: None,
             read_tracking_counter: 0,
-            read_receipts: HashMap::new(),
-            am_i_reading: false,
+            operations: HashMap::new(),
+            queued_operations: VecDeque::new(),
             my_name: name.to_string(),
-            reply_to: ProcessId::default(),
+            log,
+            fast_reads,
+            read_metrics: ReadMetrics::default(),
             tx
+        };
+        register.recover(system_id);
+        register
+    }
+
+    fn recover(&mut self, system_id: &str) {
+        let Some(log) = &self.log else { return };
+        let Some(logged) = log.lock().unwrap().recovered(system_id, &self.my_name).cloned() else { return };
+
+        match C::from_wire(&logged.value) {
+            Ok(value) => {
+                self.tag = logged.tag;
+                self.value = value;
+                self.read_tracking_counter = logged.read_id as usize;
+                info!("Register '{}' recovered {}", self.my_name, logged.tag);
+            },
+            Err(e) => warn!("Register '{}' could not recover its logged value: {}", self.my_name, e),
         }
     }
     
-    pub fn handle_message(&mut self, message: Envelope, client_state: ClientState) {
+    pub fn handle_message(&mut self, message: Envelope, context: EventContext, client_state: ClientState) {
         match message.r#type() {
             Type::NnarRead => self.handle_nnar_read(client_state),
-            Type::NnarInternalRead => self.handle_nnar_internal_read(message, client_state),
+            Type::NnarInternalRead => self.handle_nnar_internal_read(message, context, client_state),
             Type::NnarWrite => self.handle_nnar_write(message, client_state),
-            Type::NnarInternalWrite => self.handle_nnar_internal_write(message, client_state),
-            Type::NnarInternalValue => self.handle_nnar_internal_value(message, client_state),
-            Type::NnarInternalAck => self.handle_nnar_internal_ack(message, client_state),
+            Type::NnarInternalWrite => self.handle_nnar_internal_write(message, context, client_state),
+            Type::NnarInternalValue => self.handle_nnar_internal_value(message, context, client_state),
+            Type::NnarInternalAck => self.handle_nnar_internal_ack(message, context, client_state),
             Type::NnarWriteReturn => self.handle_nnar_write_return(client_state),
             Type::NnarReadReturn => self.handle_nnar_read_return(message, client_state),
             
 
             Type::BebDeliver => self.unwrap_beb(message, client_state),
-            Type::BebBroadcast => BroadcastManager::do_beb_broadcast(message, &self.tx, &client_state.nodes, &*client_state.system_id),
+            Type::BebBroadcast => BroadcastManager::do_beb_broadcast(message, &self.tx, &client_state),
             Type::PlDeliver => self.unwrap_pl(message, client_state),
-            Type::PlSend => PerfectLinkManager::handle_pl_send(message, &client_state.system_id, client_state.own_port),
+            Type::PlSend => PerfectLinkManager::handle_pl_send(message, &client_state.system_id, client_state.own_port, &client_state.link_keys),
             
-            _ => {println!("Register '{}' got an unknown message type: {:?}", self.my_name, message)}
+            _ => {warn!("Register '{}' got an unknown message type: {:?}", self.my_name, message)}
         }
     }
 
     fn unwrap_beb(&mut self, message: Envelope, client_state: ClientState) {
         let beb_deliver = message.beb_deliver.unwrap();
-        self.reply_to = beb_deliver.sender.unwrap().clone();
-        self.handle_message(*beb_deliver.message.unwrap(), client_state);
+        let inner = *beb_deliver.message.unwrap();
+        let context = EventContext::delivered(beb_deliver.sender.unwrap(), &inner);
+        self.handle_message(inner, context, client_state);
     }
 
     fn unwrap_pl(&mut self, message: Envelope, client_state: ClientState) {
         let pl_deliver = message.pl_deliver.unwrap();
-        self.reply_to = pl_deliver.sender.unwrap().clone();
-        self.handle_message(*pl_deliver.message.unwrap(), client_state);
+        let inner = *pl_deliver.message.unwrap();
+        let context = EventContext::delivered(pl_deliver.sender.unwrap(), &inner);
+        self.handle_message(inner, context, client_state);
     }
 
     fn handle_nnar_read(&mut self, client_state: ClientState) {
+        self.submit_operation(OperationKind::Read, client_state);
+    }
+
+    fn handle_nnar_write(&mut self, message: Envelope, client_state: ClientState) {
+        let nnar_write_command = message.nnar_write.unwrap();
+        match C::from_wire(&nnar_write_command.value.unwrap_or_default()) {
+            Ok(Some(value)) => self.submit_operation(OperationKind::Write(value), client_state),
+            Ok(None) => reject_write(&self.my_name, "the undefined value cannot be written".to_string(), &client_state, &self.tx),
+            Err(e) => reject_write(&self.my_name, format!("that value cannot be written: {}", e), &client_state, &self.tx),
+        }
+    }
+
+    // Operations of the same process run one after the other: two writes in flight at once
+    // would both impose the same (timestamp, rank) pair with different values
+    fn submit_operation(&mut self, kind: OperationKind<C>, client_state: ClientState) {
+        if self.operations.is_empty() {
+            self.start_operation(kind, client_state);
+        } else {
+            debug!("Register '{}' queued an operation behind the one in flight", self.my_name);
+            self.queued_operations.push_back(kind);
+        }
+    }
+
+    fn start_operation(&mut self, kind: OperationKind<C>, client_state: ClientState) {
         self.read_tracking_counter += 1;
-        self.ack_count = 0;
-        self.read_receipts.clear();
-        self.am_i_reading = true;
+        let read_id = self.read_tracking_counter as i32;
+        let logged = self.log.as_ref()
+            .map(|log| log.lock().unwrap().log_read_id(&client_state.system_id, &self.my_name, read_id));
+        if let Some(Err(e)) = logged {
+            warn!("Register '{}' could not log read id {}: {}", self.my_name, read_id, e);
+        }
+        self.operations.insert(read_id, Operation::new(kind, client_state.nodes.len()));
 
         let payload = protobuf::NnarInternalRead {
-            read_id: self.read_tracking_counter as i32
+            read_id
         };
 
         let mut beb_wrapper = Envelope::with_shipping_label(Type::NnarInternalRead);
         beb_wrapper.nnar_internal_read = Option::from(payload);
         beb_wrapper.from_abstraction_id = format!("app.nnar[{}]", self.my_name);
         beb_wrapper.to_abstraction_id = format!("app.nnar[{}]", self.my_name);
-        BroadcastManager::do_beb_broadcast(beb_wrapper, &self.tx, &client_state.nodes, &client_state.system_id);
+        BroadcastManager::do_beb_broadcast(beb_wrapper, &self.tx, &client_state);
     }
     
-    fn handle_nnar_internal_read(&self, message: Envelope, client_state: ClientState) {
+    fn handle_nnar_internal_read(&self, message: Envelope, context: EventContext, client_state: ClientState) {
         let read_command = message.nnar_internal_read.unwrap();
+        let Some(reader) = context.sender else {
+            warn!("Register '{}' got a Read without knowing who to answer", self.my_name);
+            return;
+        };
 
-        let mut value = protobuf::NnarInternalValue::default();
-        value.writer_rank = self.writer_rank as i32;
-        value.timestamp = self.timestamp as i32;
-        value.read_id = read_command.read_id;
-        value.value = Option::from(self.value);
+        let (timestamp, writer_rank) = match self.tag.to_wire() {
+            Ok(pair) => pair,
+            Err(e) => {
+                warn!("Register '{}' cannot answer a Read: {}", self.my_name, e);
+                return;
+            }
+        };
+
+        let value = protobuf::NnarInternalValue {
+            writer_rank,
+            timestamp,
+            read_id: read_command.read_id,
+            value: Option::from(C::to_wire(self.value.as_ref())),
+        };
 
         let mut value_wrapper = Envelope::with_shipping_label(Type::NnarInternalValue);
         value_wrapper.nnar_internal_value = Option::from(value);
 
-        let mut pl_send = protobuf::PlSend::default();
-        pl_send.message = NetworkService::wrap_envelope_contents(value_wrapper);
-        pl_send.destination = Option::from(self.reply_to.clone());
-
-        let mut pl_send_wrapper = Envelope::with_shipping_label(Type::PlSend);
-        pl_send_wrapper.to_abstraction_id = format!("app.nnar[{}]", self.my_name);
-        pl_send_wrapper.pl_send = NetworkService::wrap_envelope_contents(pl_send);
-
-        PerfectLinkManager::handle_pl_send(
-            pl_send_wrapper, &client_state.system_id, client_state.own_port
-        );
+        PerfectLinkManager::send_to(value_wrapper, reader, &format!("app.nnar[{}]", self.my_name), &client_state);
     }
 
-    fn handle_nnar_internal_value(&mut self, message: Envelope, client_state: ClientState) {
+    fn handle_nnar_internal_value(&mut self, message: Envelope, context: EventContext, client_state: ClientState) {
         let read_value = message.nnar_internal_value.unwrap();
+        let Some(replica) = context.sender else {
+            warn!("Register '{}' got a Value without knowing who sent it", self.my_name);
+            return;
+        };
 
-        if read_value.read_id < self.read_tracking_counter as i32 {
+        let read_id = read_value.read_id;
+        let Some(operation) = self.operations.get_mut(&read_id) else {
+            self.ignore_unknown_read_id("Value", read_id);
+            return;
+        };
+        if operation.imposed_value.is_some() {
+            // The query phase already has its majority
             return;
-        } else if read_value.read_id > self.read_tracking_counter as i32 {
-            panic!("NNAR received a Value for a Read that has not yet happened");
         }
 
-        self.read_receipts.insert(self.reply_to.clone(), read_value);
+        let receipt = match Receipt::from_wire(read_value) {
+            Ok(receipt) => receipt,
+            Err(e) => {
+                warn!("Register '{}' ignored a Value it cannot decode: {}", self.my_name, e);
+                return;
+            }
+        };
+        operation.read_receipts.insert(replica, receipt);
 
-        if self.read_receipts.len() <= (client_state.nodes.len() / 2) {
+        if !operation.read_receipts.is_reached() {
             return;
         }
 
-        let mut max_timestamp = -1;
-        let mut max_writer_rank = -1;
-        let mut max_value = RegisterValue::default();
-        for receipt in self.read_receipts.values() {
-            if receipt.timestamp > max_timestamp {
-                max_timestamp = receipt.timestamp;
-                max_writer_rank = receipt.writer_rank;
-                max_value = receipt.value.unwrap();
-            } else if receipt.timestamp == max_timestamp && receipt.writer_rank > max_writer_rank {
-                max_timestamp = receipt.timestamp;
-                max_writer_rank = receipt.writer_rank;
-                max_value = receipt.value.unwrap();
+        let highest = Receipt::highest(operation.read_receipts.replies())
+            .expect("A quorum holds at least one receipt");
+        let (max_tag, max_value) = (highest.tag, highest.value.clone());
+
+        if let OperationKind::Read = operation.kind {
+            let agreeing = operation.read_receipts.replies()
+                .filter(|receipt| receipt.tag == max_tag)
+                .count();
+            if self.fast_reads && agreeing >= operation.read_receipts.threshold() {
+                // A majority already holds the value, writing it back would change nothing
+                self.read_metrics.fast += 1;
+                self.operations.remove(&read_id);
+                return_read(&self.my_name, C::to_wire(max_value.as_ref()), &client_state, &self.tx);
+                self.start_queued_operation(client_state);
+                return;
             }
+            self.read_metrics.slow += 1;
         }
-        self.my_value_for_reading = max_value;
-
-        self.read_receipts.clear();
 
-        let mut payload = protobuf::NnarInternalWrite::default();
-        payload.read_id = self.read_tracking_counter as i32;
+        // Reads write back what they found, writes supersede it
+        let (imposed_tag, imposed_value) = match &operation.kind {
+            OperationKind::Read => (Ok(max_tag), max_value),
+            OperationKind::Write(value) => (max_tag.next(client_state.rank), Some(value.clone())),
+        };
+        let (timestamp, writer_rank) = match imposed_tag.and_then(Tag::to_wire) {
+            Ok(pair) => pair,
+            Err(reason) => {
+                let operation = self.operations.remove(&read_id).unwrap();
+                match operation.kind {
+                    OperationKind::Read => warn!("Register '{}' abandoned read id {}: {}", self.my_name, read_id, reason),
+                    OperationKind::Write(_) => reject_write(&self.my_name, reason, &client_state, &self.tx),
+                }
+                self.start_queued_operation(client_state);
+                return;
+            }
+        };
 
-        if self.am_i_reading {
-            payload.timestamp = max_timestamp;
-            payload.writer_rank = max_writer_rank;
-            payload.value = Option::from(self.my_value_for_reading);
-        } else {
-            payload.timestamp = max_timestamp + 1;
-            payload.writer_rank = client_state.rank;
-            payload.value = Option::from(self.my_value_for_writing);
-        }
+        let payload = protobuf::NnarInternalWrite {
+            read_id,
+            timestamp,
+            writer_rank,
+            value: Option::from(C::to_wire(imposed_value.as_ref())),
+        };
+        operation.imposed_value = Some(imposed_value);
 
         let mut wrapper = Envelope::with_shipping_label(Type::NnarInternalWrite);
         wrapper.nnar_internal_write = Option::from(payload);
         wrapper.from_abstraction_id = format!("app.nnar[{}]", self.my_name);
         wrapper.to_abstraction_id = format!("app.nnar[{}]", self.my_name);
-        BroadcastManager::do_beb_broadcast(wrapper, &self.tx, &client_state.nodes, &client_state.system_id);
+        BroadcastManager::do_beb_broadcast(wrapper, &self.tx, &client_state);
     }
 
-
-    fn handle_nnar_write(&mut self, message: Envelope, client_state: ClientState) {
-        let nnar_write_command = message.nnar_write.unwrap();
-
-        self.read_tracking_counter += 1;
-        self.my_value_for_writing = nnar_write_command.value.unwrap();
-        self.ack_count = 0;
-        self.read_receipts.clear();
-
-        let payload = protobuf::NnarInternalRead {
-            read_id: self.read_tracking_counter as i32
+    fn handle_nnar_internal_write(&mut self, message: Envelope, context: EventContext, client_state: ClientState) {
+        let nnar_internal_write = message.nnar_internal_write.unwrap();
+        let Some(writer) = context.sender else {
+            warn!("Register '{}' got a Write without knowing who to acknowledge", self.my_name);
+            return;
+        };
+        let wire_value = nnar_internal_write.value.unwrap_or_default();
+        let value = match C::from_wire(&wire_value) {
+            Ok(value) => value,
+            Err(e) => {
+                warn!("Register '{}' refused a Write it cannot decode: {}", self.my_name, e);
+                return;
+            }
+        };
+        let tag = match Tag::from_wire(nnar_internal_write.timestamp, nnar_internal_write.writer_rank) {
+            Ok(tag) => tag,
+            Err(e) => {
+                warn!("Register '{}' refused a Write with a bad tag: {}", self.my_name, e);
+                return;
+            }
         };
 
-        let mut wrapper = Envelope::with_shipping_label(Type::NnarInternalRead);
-        wrapper.nnar_internal_read = Option::from(payload);
-        wrapper.from_abstraction_id = format!("app.nnar[{}]", self.my_name);
-        wrapper.to_abstraction_id = format!("app.nnar[{}]", self.my_name);
-        BroadcastManager::do_beb_broadcast(wrapper, &self.tx, &client_state.nodes, &client_state.system_id)
-    }
-    
-    fn handle_nnar_internal_write(&mut self, message: Envelope, client_state: ClientState) {
-        let nnar_internal_write = message.nnar_internal_write.unwrap();
-        let my_ts = self.timestamp as i32;
-        let my_wr = self.writer_rank as i32;
-
-        if (nnar_internal_write.timestamp > my_ts) ||
-            (nnar_internal_write.timestamp == my_ts && nnar_internal_write.writer_rank > my_wr) {
-            self.timestamp = nnar_internal_write.timestamp as usize;
-            self.writer_rank = nnar_internal_write.writer_rank as usize;
-            self.value = nnar_internal_write.value.unwrap();
+        if tag > self.tag {
+            if let Some(log) = &self.log {
+                // Without the record on disk, the write must not be acknowledged
+                let logged = log.lock().unwrap().log_write(&client_state.system_id, &self.my_name, tag, &wire_value);
+                if let Err(e) = logged {
+                    warn!("Register '{}' could not log a Write, not acknowledging it: {}", self.my_name, e);
+                    return;
+                }
+            }
+            self.tag = tag;
+            self.value = value;
         }
 
-        let mut ack = protobuf::NnarInternalAck::default();
-        ack.read_id = nnar_internal_write.read_id;
+        let ack = protobuf::NnarInternalAck {
+            read_id: nnar_internal_write.read_id,
+        };
         let mut ack_wrapper = Envelope::with_shipping_label(Type::NnarInternalAck);
         ack_wrapper.nnar_internal_ack = Option::from(ack);
         ack_wrapper.to_abstraction_id = format!("app.nnar[{}]", self.my_name);
 
-        let mut pl_send = protobuf::PlSend::default();
-        pl_send.destination = Option::from(self.reply_to.clone());
-        pl_send.message = NetworkService::wrap_envelope_contents(ack_wrapper);
-        let mut pl_send_wrapper = Envelope::with_shipping_label(Type::PlSend);
-        pl_send_wrapper.pl_send = NetworkService::wrap_envelope_contents(pl_send);
-        pl_send_wrapper.to_abstraction_id = format!("app.nnar[{}]", self.my_name);
-
-        PerfectLinkManager::handle_pl_send(pl_send_wrapper, &client_state.system_id, client_state.own_port);
+        PerfectLinkManager::send_to(ack_wrapper, writer, &format!("app.nnar[{}]", self.my_name), &client_state);
     }
 
-    fn handle_nnar_internal_ack(&mut self, message: Envelope, client_state: ClientState) {
+    fn handle_nnar_internal_ack(&mut self, message: Envelope, context: EventContext, client_state: ClientState) {
         let ack = message.nnar_internal_ack.unwrap();
+        let Some(replica) = context.sender else {
+            warn!("Register '{}' got an Ack without knowing who sent it", self.my_name);
+            return;
+        };
 
-        if ack.read_id < self.read_tracking_counter as i32 {
+        let read_id = ack.read_id;
+        let Some(operation) = self.operations.get_mut(&read_id) else {
+            self.ignore_unknown_read_id("Ack", read_id);
+            return;
+        };
+        let Some(imposed_value) = operation.imposed_value.clone() else {
+            warn!("Register '{}' got an Ack for read id {} before imposing a value", self.my_name, read_id);
+            return;
+        };
+
+        if !operation.acks.insert(replica, ()) {
+            debug!("Register '{}' ignored a duplicate Ack for read id {}", self.my_name, read_id);
             return;
-        } else if ack.read_id > self.read_tracking_counter as i32 {
-            panic!("NNAR received an Ack for a Read that has not yet happened");
         }
 
-        self.ack_count += 1;
+        if !operation.acks.is_reached() { return }
 
-        if self.ack_count <= (client_state.nodes.len() / 2) { return }
+        let operation = self.operations.remove(&read_id).unwrap();
+        match operation.kind {
+            OperationKind::Read => {
+                let read_return = protobuf::NnarReadReturn {
+                    value: Option::from(C::to_wire(imposed_value.as_ref())),
+                };
 
-        self.ack_count = 0;
-        if self.am_i_reading {
-            self.am_i_reading = false;
-            
-            let mut read_return = protobuf::NnarReadReturn::default();
-            read_return.value = Option::from(self.my_value_for_reading.clone());
-            
-            let mut wrapper = Envelope::with_shipping_label(Type::NnarReadReturn);
-            wrapper.nnar_read_return = Option::from(read_return);
-            
-            // self.tx.send(wrapper).unwrap();
-            self.handle_nnar_read_return(wrapper, client_state);
-        } else {
-            // let write_return = protobuf::NnarWriteReturn::default();
+                let mut wrapper = Envelope::with_shipping_label(Type::NnarReadReturn);
+                wrapper.nnar_read_return = Option::from(read_return);
+
+                self.handle_nnar_read_return(wrapper, client_state.clone());
+            },
+            OperationKind::Write(_) => self.handle_nnar_write_return(client_state.clone()),
+        }
+        self.start_queued_operation(client_state);
+    }
 
-            // let mut wrapper = Envelope::with_shipping_label(Type::NnarWriteReturn);
-            // wrapper.nnar_write_return = Option::from(write_return);
+    fn start_queued_operation(&mut self, client_state: ClientState) {
+        if let Some(next) = self.queued_operations.pop_front() {
+            self.start_operation(next, client_state);
+        }
+    }
+
+    // Late replies to finished operations are expected; replies to ids never handed out are not
+    fn ignore_unknown_read_id(&self, kind: &str, read_id: i32) {
+        if read_id <= self.read_tracking_counter as i32 {
+            debug!("Register '{}' ignored a late {} for read id {}", self.my_name, kind, read_id);
+        } else {
+            warn!("Register '{}' ignored a {} for read id {}, which it never started", self.my_name, kind, read_id);
+        }
+    }
 
-            self.handle_nnar_write_return(client_state);
-            // self.tx.send(wrapper).unwrap();
+    fn summary(&self) -> RegisterSummary {
+        let mut in_flight = self.operations.iter().collect::<Vec<_>>();
+        in_flight.sort_by_key(|(read_id, _)| **read_id);
+        let operations = in_flight.into_iter()
+            .map(|(read_id, operation)| operation.summary(*read_id))
+            .chain(self.queued_operations.iter().map(|kind| kind.queued_summary()))
+            .collect();
+
+        RegisterSummary {
+            name: self.my_name.clone(),
+            abstraction_id: format!("app.nnar[{}]", self.my_name),
+            tag: self.tag,
+            value: C::to_wire(self.value.as_ref()),
+            operations,
         }
     }
     
+    /// Abandon the operation in flight and the queued ones, returning how many there were
+    fn fail_pending_operations(&mut self) -> usize {
+        let count = self.operations.len() + self.queued_operations.len();
+        if count > 0 {
+            info!("Register '{}' abandoned {} pending operation(s)", self.my_name, count);
+        }
+
+        // Once they are out of the table, late Values and Acks for them are ignored
+        self.operations.clear();
+        self.queued_operations.clear();
+        count
+    }
+
     fn handle_nnar_read_return(&self, message: Envelope, client_state: ClientState) {
         let nnar_read_return = message.nnar_read_return.unwrap();
-        
-        let mut app_read_return = protobuf::AppReadReturn::default();
-        app_read_return.value = nnar_read_return.value;
-        app_read_return.register = self.my_name.clone();
-        
-        let mut app_wrapper = Envelope::with_shipping_label(Type::AppReadReturn);
-        app_wrapper.app_read_return = Option::from(app_read_return);
-        
-        let mut pl_send = protobuf::PlSend::default();
-        pl_send.message = NetworkService::wrap_envelope_contents(app_wrapper);
-        pl_send.destination = Option::from(ProcessId {
-            host: client_state.hub_socket.ip().to_string(),
-            port: client_state.hub_socket.port() as i32,
-            ..Default::default()
-        });
-        
-        let mut pl_wrapper = Envelope::with_shipping_label(Type::PlSend);
-        pl_wrapper.pl_send = NetworkService::wrap_envelope_contents(pl_send);
-        
-        self.tx.send(pl_wrapper).unwrap();
+        return_read(&self.my_name, nnar_read_return.value.unwrap_or_default(), &client_state, &self.tx);
     }
     
     fn handle_nnar_write_return(&self, client_state: ClientState) {
-        let mut app_write_return = protobuf::AppWriteReturn::default();
-        app_write_return.register = self.my_name.clone();
+        return_write(&self.my_name, &client_state, &self.tx);
+    }
+}
 
-        let mut app_wrapper = Envelope::with_shipping_label(Type::AppWriteReturn);
-        app_wrapper.app_write_return = Option::from(app_write_return);
+/// Name of the register an abstraction id such as `app.nnar[x].beb.pl` belongs to
+pub(crate) fn register_name(abstraction_id: &str) -> Option<&str> {
+    let (_, rest) = abstraction_id.split_once('[')?;
+    rest.split_once(']').map(|(register, _)| register)
+}
 
-        let mut pl_send = protobuf::PlSend::default();
-        pl_send.message = NetworkService::wrap_envelope_contents(app_wrapper);
-        pl_send.destination = Option::from(ProcessId {
-            host: client_state.hub_socket.ip().to_string(),
-            port: client_state.hub_socket.port() as i32,
-            ..Default::default()
-        });
+/// Tell the hub what a read of `register` returned
+pub(crate) fn return_read(register: &str, value: RegisterValue, client_state: &ClientState, tx: &Sender<Envelope>) {
+    info!("{} read {}={}", client_state.label(), register, value);
 
-        let mut pl_wrapper = Envelope::with_shipping_label(Type::PlSend);
-        pl_wrapper.pl_send = NetworkService::wrap_envelope_contents(pl_send);
+    let app_read_return = protobuf::AppReadReturn {
+        value: Option::from(value),
+        register: register.to_string(),
+    };
 
-        self.tx.send(pl_wrapper).unwrap();
-    }
+    let mut app_wrapper = Envelope::with_shipping_label(Type::AppReadReturn);
+    app_wrapper.app_read_return = Option::from(app_read_return);
+
+    PerfectLinkManager::send_to_hub(app_wrapper, &client_state.hub_socket, tx);
+}
+
+/// Tell the hub that this process may not write `register`, instead of completing the write
+#[cfg(feature = "write-rejections")]
+pub(crate) fn reject_write(register: &str, reason: String, client_state: &ClientState, tx: &Sender<Envelope>) {
+    warn!("{} cannot write {}: {}", client_state.label(), register, reason);
+
+    let app_write_rejected = protobuf::AppWriteRejected {
+        register: register.to_string(),
+        reason,
+    };
+
+    let mut app_wrapper = Envelope::with_shipping_label(Type::AppWriteRejected);
+    app_wrapper.app_write_rejected = Option::from(app_write_rejected);
+
+    PerfectLinkManager::send_to_hub(app_wrapper, &client_state.hub_socket, tx);
+}
+
+/// Without AppWriteRejected, which the reference hub does not know about, the hub gets the AppWriteReturn
+/// it waits for and only the log tells that the write did not happen
+#[cfg(not(feature = "write-rejections"))]
+pub(crate) fn reject_write(register: &str, reason: String, client_state: &ClientState, tx: &Sender<Envelope>) {
+    warn!("{} cannot write {}, returning without writing: {}", client_state.label(), register, reason);
+
+    let mut app_wrapper = Envelope::with_shipping_label(Type::AppWriteReturn);
+    app_wrapper.app_write_return = Option::from(protobuf::AppWriteReturn { register: register.to_string() });
+
+    PerfectLinkManager::send_to_hub(app_wrapper, &client_state.hub_socket, tx);
+}
+
+/// Tell the hub that a write of `register` completed
+pub(crate) fn return_write(register: &str, client_state: &ClientState, tx: &Sender<Envelope>) {
+    info!("{} finished writing {}", client_state.label(), register);
+
+    let app_write_return = protobuf::AppWriteReturn {
+        register: register.to_string(),
+    };
+
+    let mut app_wrapper = Envelope::with_shipping_label(Type::AppWriteReturn);
+    app_wrapper.app_write_return = Option::from(app_write_return);
+
+    PerfectLinkManager::send_to_hub(app_wrapper, &client_state.hub_socket, tx);
 }
 
 impl Eq for ProcessId {}
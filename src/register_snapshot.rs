@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use crate::client::ProcessStatus;
+use crate::protobuf;
+use crate::register_manager::{OperationSummary, ReadMetrics, RegisterSummary};
+
+/// Registers held by the processes of one system, as they were published last
+#[derive(Clone, Debug)]
+pub struct SystemSnapshot {
+    pub system_id: String,
+    pub processes: Vec<ProcessSnapshot>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProcessSnapshot {
+    /// `owner-index`, as the hub listed the process
+    pub name: String,
+    pub port: u16,
+    pub rank: i32,
+    pub registers: Vec<RegisterSummary>,
+    pub read_metrics: ReadMetrics,
+}
+
+/// Group processes by the system they are in, leaving out those that are not part of one
+pub fn snapshot(statuses: &[ProcessStatus]) -> Vec<SystemSnapshot> {
+    let mut systems: BTreeMap<String, Vec<ProcessSnapshot>> = BTreeMap::new();
+    for status in statuses {
+        let Some(process) = status.state.own_process() else { continue };
+        systems.entry(status.state.system_id.clone()).or_default().push(ProcessSnapshot {
+            name: format!("{}-{}", process.owner, process.index),
+            port: status.state.own_port,
+            rank: status.state.rank,
+            registers: status.registers.clone(),
+            read_metrics: status.read_metrics,
+        });
+    }
+
+    systems.into_iter()
+        .map(|(system_id, processes)| SystemSnapshot { system_id, processes })
+        .collect()
+}
+
+/// JSON document listing `systems`, for comparing replicas with other tools
+pub fn to_json(systems: &[SystemSnapshot]) -> String {
+    let systems = systems.iter()
+        .map(|system| {
+            let processes = system.processes.iter().map(process_json).collect::<Vec<_>>();
+            format!("{{\"system_id\":{},\"processes\":[{}]}}", json_string(&system.system_id), processes.join(","))
+        })
+        .collect::<Vec<_>>();
+    format!("{{\"systems\":[{}]}}", systems.join(","))
+}
+
+fn process_json(process: &ProcessSnapshot) -> String {
+    let registers = process.registers.iter().map(register_json).collect::<Vec<_>>();
+    format!("{{\"process\":{},\"port\":{},\"rank\":{},\"reads\":{{\"fast\":{},\"slow\":{}}},\"registers\":[{}]}}",
+            json_string(&process.name), process.port, process.rank, process.read_metrics.fast,
+            process.read_metrics.slow, registers.join(","))
+}
+
+fn register_json(register: &RegisterSummary) -> String {
+    let operations = register.operations.iter().map(operation_json).collect::<Vec<_>>();
+    format!("{{\"name\":{},\"abstraction_id\":{},\"timestamp\":{},\"writer_rank\":{},\"value\":{},\"operations\":[{}]}}",
+            json_string(&register.name), json_string(&register.abstraction_id), register.tag.ts,
+            register.tag.rank, value_json(&register.value), operations.join(","))
+}
+
+fn operation_json(operation: &OperationSummary) -> String {
+    let read_id = operation.read_id.map(|id| id.to_string()).unwrap_or("null".to_string());
+    format!("{{\"read_id\":{},\"kind\":\"{}\",\"phase\":\"{}\",\"replies\":{},\"needed\":{}}}",
+            read_id, if operation.is_write { "write" } else { "read" }, operation.phase,
+            operation.replies, operation.needed)
+}
+
+/// The value as it travels on the wire, so that replicas can be compared field by field
+fn value_json(value: &protobuf::Value) -> String {
+    #[cfg(feature = "value-payload")]
+    {
+        let payload = value.payload.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        format!("{{\"defined\":{},\"v\":{},\"payload\":\"{}\"}}", value.defined, value.v, payload)
+    }
+    #[cfg(not(feature = "value-payload"))]
+    format!("{{\"defined\":{},\"v\":{}}}", value.defined, value.v)
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => { let _ = write!(escaped, "\\u{:04x}", c as u32); },
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
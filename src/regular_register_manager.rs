@@ -0,0 +1,423 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::mpsc::Sender;
+use crate::{debug, protobuf, warn, Envelope};
+use crate::broadcast_manager::BroadcastManager;
+use crate::client::ClientState;
+use crate::event_context::EventContext;
+use crate::perfect_link_manager::PerfectLinkManager;
+use crate::protobuf::message::Type;
+use crate::protobuf::ProcessId;
+use crate::quorum::Quorum;
+use crate::register_manager::{register_name, reject_write, return_read, return_write, Contents, OperationKind, OperationPhase, OperationSummary, Receipt, RegisterKind, RegisterSummary};
+use crate::tag::Tag;
+use crate::value_codec::{I32Codec, ValueCodec};
+
+/// Handles all messages addressed to `app.onrr` and its subroutes: (1,N) regular registers,
+/// written by the process of lowest rank and read by every process.
+/// They speak the NNAR messages, the read id of an Ack being the timestamp of the acknowledged write.
+pub struct RegularRegisterManager<C: ValueCodec = I32Codec> {
+    registers: HashMap<String, RegularRegister<C>>,
+    kinds: HashMap<String, RegisterKind>,
+    /// Ports of the processes the failure detector reported as crashed
+    crashed: HashSet<i32>,
+    tx: Sender<Envelope>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Algorithm {
+    ReadOneWriteAll,
+    MajorityVoting,
+}
+
+enum PendingOperation<C: ValueCodec> {
+    Read { read_id: i32, receipts: Quorum<Receipt<C>> },
+    Write { timestamp: i32, acks: Quorum<()> },
+}
+
+struct RegularRegister<C: ValueCodec> {
+    algorithm: Algorithm,
+    tag: Tag,
+    value: Contents<C>,
+    /// Tag of the last write started here, when this process is the writer
+    write_tag: Tag,
+    read_id: i32,
+    in_flight: Option<PendingOperation<C>>,
+    queued_operations: VecDeque<OperationKind<C>>,
+    /// Ports of the processes not detected as crashed, which Read-One Write-All waits for
+    correct: HashSet<i32>,
+
+    my_name: String,
+
+    tx: Sender<Envelope>,
+}
+
+/// The process allowed to write (1,N) registers: the one of lowest rank
+pub(crate) fn designated_writer(client_state: &ClientState) -> Option<&ProcessId> {
+    client_state.nodes.iter().min_by_key(|node| node.rank)
+}
+
+/// Whether this process is the one allowed to write (1,N) registers, with the reason if it is not
+pub(crate) fn check_designated_writer(client_state: &ClientState) -> Result<(), String> {
+    match designated_writer(client_state) {
+        Some(writer) if writer.rank == client_state.rank => Ok(()),
+        Some(writer) => Err(format!("only {}-{} (rank {}) writes (1,N) registers", writer.owner, writer.index, writer.rank)),
+        None => Err("this process is not part of a system".to_string()),
+    }
+}
+
+impl<C: ValueCodec> RegularRegisterManager<C> {
+    pub fn new(tx: Sender<Envelope>, kinds: HashMap<String, RegisterKind>) -> Self {
+        RegularRegisterManager {
+            registers: HashMap::new(),
+            kinds,
+            crashed: HashSet::new(),
+            tx,
+        }
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: ClientState) {
+        let destination = message.to_abstraction_id.clone();
+        let Some(name) = register_name(&destination) else {
+            warn!("Message not addressed to a regular register arrived in RegularRegisterManager");
+            return;
+        };
+
+        let context = EventContext::local(&message);
+        if !self.registers.contains_key(name) {
+            // Registers that were not configured default to the algorithm that needs no failure detector
+            let algorithm = match self.kinds.get(name) {
+                Some(RegisterKind::ReadOneWriteAll) => Algorithm::ReadOneWriteAll,
+                _ => Algorithm::MajorityVoting,
+            };
+            let correct = client_state.nodes.iter()
+                .map(|node| node.port)
+                .filter(|port| !self.crashed.contains(port))
+                .collect();
+            self.registers.insert(name.to_string(), RegularRegister::new(self.tx.clone(), name, algorithm, correct));
+        }
+        self.registers.get_mut(name).unwrap().handle_message(message, context, client_state);
+    }
+
+    /// Called when the failure detector reports that `process` crashed
+    pub fn handle_crash(&mut self, process: &ProcessId, client_state: ClientState) {
+        self.crashed.insert(process.port);
+        for register in self.registers.values_mut() {
+            register.handle_crash(process.port, client_state.clone());
+        }
+    }
+
+    pub fn summaries(&self) -> Vec<RegisterSummary> {
+        let mut summaries = self.registers.values()
+            .map(|register| register.summary())
+            .collect::<Vec<_>>();
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        summaries
+    }
+
+    /// Abandon every read or write still in flight or queued, returning how many there were
+    pub fn fail_pending_operations(&mut self) -> usize {
+        self.registers.values_mut()
+            .map(|register| register.fail_pending_operations())
+            .sum()
+    }
+}
+
+impl<C: ValueCodec> RegularRegister<C> {
+    fn new(tx: Sender<Envelope>, name: &str, algorithm: Algorithm, correct: HashSet<i32>) -> Self {
+        RegularRegister {
+            algorithm,
+            tag: Tag::default(),
+            value: None,
+            write_tag: Tag::default(),
+            read_id: 0,
+            in_flight: None,
+            queued_operations: VecDeque::new(),
+            correct,
+            my_name: name.to_string(),
+            tx,
+        }
+    }
+
+    fn abstraction_id(&self) -> String {
+        format!("app.onrr[{}]", self.my_name)
+    }
+
+    fn handle_message(&mut self, message: Envelope, context: EventContext, client_state: ClientState) {
+        match message.r#type() {
+            Type::NnarRead => self.submit_operation(OperationKind::Read, client_state),
+            Type::NnarWrite => self.handle_write(message, client_state),
+            Type::NnarInternalRead => self.handle_internal_read(message, context, client_state),
+            Type::NnarInternalValue => self.handle_internal_value(message, context, client_state),
+            Type::NnarInternalWrite => self.handle_internal_write(message, context, client_state),
+            Type::NnarInternalAck => self.handle_internal_ack(message, context, client_state),
+
+            Type::PlDeliver | Type::BebDeliver => {
+                let (sender, inner) = BroadcastManager::unwrap_delivery(message).unwrap();
+                let context = EventContext::delivered(sender, &inner);
+                self.handle_message(inner, context, client_state);
+            },
+            Type::PlSend => PerfectLinkManager::handle_pl_send(message, &client_state.system_id, client_state.own_port, &client_state.link_keys),
+
+            _ => warn!("Regular register '{}' got an unknown message type: {:?}", self.my_name, message.r#type()),
+        }
+    }
+
+    fn handle_write(&mut self, message: Envelope, client_state: ClientState) {
+        if let Err(reason) = check_designated_writer(&client_state) {
+            reject_write(&self.my_name, reason, &client_state, &self.tx);
+            return;
+        }
+
+        match C::from_wire(&message.nnar_write.unwrap().value.unwrap_or_default()) {
+            Ok(Some(value)) => self.submit_operation(OperationKind::Write(value), client_state),
+            Ok(None) => reject_write(&self.my_name, "the undefined value cannot be written".to_string(), &client_state, &self.tx),
+            Err(e) => reject_write(&self.my_name, format!("that value cannot be written: {}", e), &client_state, &self.tx),
+        }
+    }
+
+    fn submit_operation(&mut self, kind: OperationKind<C>, client_state: ClientState) {
+        if self.in_flight.is_some() {
+            debug!("Regular register '{}' queued an operation behind the one in flight", self.my_name);
+        }
+        self.queued_operations.push_back(kind);
+        self.start_next_operation(client_state);
+    }
+
+    fn start_next_operation(&mut self, client_state: ClientState) {
+        // Read-One Write-All reads complete on the spot, so several queued operations may go at once
+        while self.in_flight.is_none() {
+            let Some(kind) = self.queued_operations.pop_front() else { return };
+            self.start_operation(kind, client_state.clone());
+        }
+    }
+
+    fn start_operation(&mut self, kind: OperationKind<C>, client_state: ClientState) {
+        match (kind, self.algorithm) {
+            (OperationKind::Read, Algorithm::ReadOneWriteAll) => {
+                return_read(&self.my_name, C::to_wire(self.value.as_ref()), &client_state, &self.tx);
+            },
+            (OperationKind::Read, Algorithm::MajorityVoting) => {
+                self.read_id += 1;
+                self.in_flight = Some(PendingOperation::Read {
+                    read_id: self.read_id,
+                    receipts: Quorum::majority(client_state.nodes.len()),
+                });
+
+                let mut wrapper = Envelope::with_shipping_label(Type::NnarInternalRead);
+                wrapper.nnar_internal_read = Option::from(protobuf::NnarInternalRead { read_id: self.read_id });
+                self.broadcast(wrapper, &client_state);
+            },
+            (OperationKind::Write(value), _) => {
+                let next = self.write_tag.next(client_state.rank)
+                    .and_then(|tag| Ok((tag, tag.to_wire()?)));
+                let (tag, (timestamp, writer_rank)) = match next {
+                    Ok(next) => next,
+                    Err(reason) => {
+                        reject_write(&self.my_name, reason, &client_state, &self.tx);
+                        return;
+                    }
+                };
+                self.write_tag = tag;
+                self.in_flight = Some(PendingOperation::Write {
+                    timestamp,
+                    acks: Quorum::majority(client_state.nodes.len()),
+                });
+
+                let payload = protobuf::NnarInternalWrite {
+                    timestamp,
+                    writer_rank,
+                    value: Option::from(C::encode(&value)),
+                    ..Default::default()
+                };
+
+                let mut wrapper = Envelope::with_shipping_label(Type::NnarInternalWrite);
+                wrapper.nnar_internal_write = Option::from(payload);
+                self.broadcast(wrapper, &client_state);
+            },
+        }
+    }
+
+    fn handle_internal_read(&self, message: Envelope, context: EventContext, client_state: ClientState) {
+        let Some(reader) = context.sender else {
+            warn!("Regular register '{}' got a Read without knowing who to answer", self.my_name);
+            return;
+        };
+
+        let (timestamp, writer_rank) = match self.tag.to_wire() {
+            Ok(pair) => pair,
+            Err(e) => {
+                warn!("Regular register '{}' cannot answer a Read: {}", self.my_name, e);
+                return;
+            }
+        };
+
+        let value = protobuf::NnarInternalValue {
+            read_id: message.nnar_internal_read.unwrap().read_id,
+            timestamp,
+            writer_rank,
+            value: Option::from(C::to_wire(self.value.as_ref())),
+        };
+
+        let mut wrapper = Envelope::with_shipping_label(Type::NnarInternalValue);
+        wrapper.nnar_internal_value = Option::from(value);
+        wrapper.to_abstraction_id = self.abstraction_id();
+        PerfectLinkManager::send_to(wrapper, reader, &self.abstraction_id(), &client_state);
+    }
+
+    fn handle_internal_value(&mut self, message: Envelope, context: EventContext, client_state: ClientState) {
+        let value = message.nnar_internal_value.unwrap();
+        let Some(replica) = context.sender else {
+            warn!("Regular register '{}' got a Value without knowing who sent it", self.my_name);
+            return;
+        };
+        let Some(PendingOperation::Read { read_id, receipts }) = &mut self.in_flight else {
+            debug!("Regular register '{}' ignored a Value while not reading", self.my_name);
+            return;
+        };
+        if value.read_id != *read_id {
+            debug!("Regular register '{}' ignored a Value for read id {}", self.my_name, value.read_id);
+            return;
+        }
+
+        match Receipt::from_wire(value) {
+            Ok(receipt) => { receipts.insert(replica, receipt); },
+            Err(e) => {
+                warn!("Regular register '{}' ignored a Value it cannot decode: {}", self.my_name, e);
+                return;
+            }
+        }
+        if !receipts.is_reached() {
+            return;
+        }
+
+        let highest = Receipt::highest(receipts.replies())
+            .expect("A quorum holds at least one receipt");
+        let value = C::to_wire(highest.value.as_ref());
+        self.in_flight = None;
+        return_read(&self.my_name, value, &client_state, &self.tx);
+        self.start_next_operation(client_state);
+    }
+
+    fn handle_internal_write(&mut self, message: Envelope, context: EventContext, client_state: ClientState) {
+        let write = message.nnar_internal_write.unwrap();
+        let Some(writer) = context.sender else {
+            warn!("Regular register '{}' got a Write without knowing who to acknowledge", self.my_name);
+            return;
+        };
+        if designated_writer(&client_state).map(|designated| designated.port) != Some(writer.port) {
+            warn!("Regular register '{}' refused a Write from {}:{}, which is not its writer",
+                  self.my_name, writer.host, writer.port);
+            return;
+        }
+        let decoded = Tag::from_wire(write.timestamp, write.writer_rank)
+            .and_then(|tag| Ok((tag, C::from_wire(&write.value.unwrap_or_default())?)));
+        let (tag, value) = match decoded {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                warn!("Regular register '{}' refused a Write it cannot decode: {}", self.my_name, e);
+                return;
+            }
+        };
+
+        if tag > self.tag {
+            self.tag = tag;
+            self.value = value;
+        }
+
+        let ack = protobuf::NnarInternalAck {
+            read_id: write.timestamp,
+        };
+        let mut wrapper = Envelope::with_shipping_label(Type::NnarInternalAck);
+        wrapper.nnar_internal_ack = Option::from(ack);
+        wrapper.to_abstraction_id = self.abstraction_id();
+        PerfectLinkManager::send_to(wrapper, writer, &self.abstraction_id(), &client_state);
+    }
+
+    fn handle_internal_ack(&mut self, message: Envelope, context: EventContext, client_state: ClientState) {
+        let ack = message.nnar_internal_ack.unwrap();
+        let Some(replica) = context.sender else {
+            warn!("Regular register '{}' got an Ack without knowing who sent it", self.my_name);
+            return;
+        };
+        let Some(PendingOperation::Write { timestamp, acks }) = &mut self.in_flight else {
+            debug!("Regular register '{}' ignored an Ack while not writing", self.my_name);
+            return;
+        };
+        if ack.read_id != *timestamp {
+            debug!("Regular register '{}' ignored an Ack for timestamp {}", self.my_name, ack.read_id);
+            return;
+        }
+
+        acks.insert(replica, ());
+        self.complete_write_if_acknowledged(client_state);
+    }
+
+    fn handle_crash(&mut self, port: i32, client_state: ClientState) {
+        self.correct.remove(&port);
+        self.complete_write_if_acknowledged(client_state);
+    }
+
+    fn complete_write_if_acknowledged(&mut self, client_state: ClientState) {
+        let Some(PendingOperation::Write { acks, .. }) = &self.in_flight else { return };
+
+        let acknowledged = match self.algorithm {
+            Algorithm::ReadOneWriteAll => {
+                let acked = acks.senders().map(|sender| sender.port).collect::<HashSet<_>>();
+                self.correct.is_subset(&acked)
+            },
+            Algorithm::MajorityVoting => acks.is_reached(),
+        };
+        if !acknowledged {
+            return;
+        }
+
+        self.in_flight = None;
+        return_write(&self.my_name, &client_state, &self.tx);
+        self.start_next_operation(client_state);
+    }
+
+    fn summary(&self) -> RegisterSummary {
+        let in_flight = self.in_flight.as_ref().map(|operation| match operation {
+            PendingOperation::Read { read_id, receipts } => OperationSummary {
+                read_id: Some(*read_id),
+                is_write: false,
+                phase: OperationPhase::Query,
+                replies: receipts.len(),
+                needed: receipts.threshold(),
+            },
+            PendingOperation::Write { timestamp, acks } => OperationSummary {
+                read_id: Some(*timestamp),
+                is_write: true,
+                phase: OperationPhase::Impose,
+                replies: acks.len(),
+                needed: match self.algorithm {
+                    Algorithm::ReadOneWriteAll => self.correct.len(),
+                    Algorithm::MajorityVoting => acks.threshold(),
+                },
+            },
+        });
+
+        RegisterSummary {
+            name: self.my_name.clone(),
+            abstraction_id: self.abstraction_id(),
+            tag: self.tag,
+            value: C::to_wire(self.value.as_ref()),
+            operations: in_flight.into_iter()
+                .chain(self.queued_operations.iter().map(|kind| kind.queued_summary()))
+                .collect(),
+        }
+    }
+
+    fn fail_pending_operations(&mut self) -> usize {
+        let count = self.in_flight.iter().count() + self.queued_operations.len();
+        self.in_flight = None;
+        self.queued_operations.clear();
+        count
+    }
+
+    fn broadcast(&self, mut message: Envelope, client_state: &ClientState) {
+        message.from_abstraction_id = self.abstraction_id();
+        message.to_abstraction_id = self.abstraction_id();
+        BroadcastManager::do_beb_broadcast(message, &self.tx, client_state);
+    }
+}
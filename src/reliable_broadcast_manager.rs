@@ -0,0 +1,164 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::Sender;
+use crate::{debug, protobuf, warn, Envelope};
+use crate::broadcast_manager::BroadcastManager;
+use crate::client::ClientState;
+use crate::network_service::NetworkService;
+use crate::perfect_link_manager::PerfectLinkManager;
+use crate::protobuf::message::Type;
+use crate::protobuf::ProcessId;
+
+pub const EAGER_ABSTRACTION_ID: &str = "app.erb";
+pub const LAZY_ABSTRACTION_ID: &str = "app.lrb";
+
+/// Reliable broadcast: if a correct process delivers a message, every correct process does,
+/// even when the sender crashed halfway through its best-effort broadcast.
+///
+/// The eager variant relays every message the first time it gets it. The lazy one only relays the
+/// messages of a process once the failure detector reports it crashed.
+pub struct ReliableBroadcastManager {
+    abstraction_id: String,
+    lazy: bool,
+    /// Ids of the messages delivered so far
+    delivered: HashSet<String>,
+    /// Lazy only: messages each process relayed to us, keyed by its port, to relay if it crashes
+    from: HashMap<i32, Vec<protobuf::RbInternalData>>,
+    /// Lazy only: ports of the processes detected as crashed
+    crashed: HashSet<i32>,
+    tx: Sender<Envelope>,
+}
+
+impl ReliableBroadcastManager {
+    /// Eager Reliable Broadcast serving `parent`, under `<parent>.erb`
+    pub fn eager(parent: &str, tx: Sender<Envelope>) -> Self {
+        ReliableBroadcastManager {
+            abstraction_id: format!("{}.erb", parent),
+            lazy: false,
+            delivered: HashSet::new(),
+            from: HashMap::new(),
+            crashed: HashSet::new(),
+            tx,
+        }
+    }
+
+    /// Lazy Reliable Broadcast serving `parent`, under `<parent>.lrb`; it needs the perfect failure detector running
+    pub fn lazy(parent: &str, tx: Sender<Envelope>) -> Self {
+        ReliableBroadcastManager {
+            abstraction_id: format!("{}.lrb", parent),
+            lazy: true,
+            ..Self::eager(parent, tx)
+        }
+    }
+
+    pub fn abstraction_id(&self) -> &str {
+        &self.abstraction_id
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        match message.r#type() {
+            Type::RbBroadcast => self.broadcast(message, client_state),
+            Type::PlDeliver | Type::BebDeliver => {
+                let (relay, inner) = BroadcastManager::unwrap_delivery(message).unwrap();
+                match inner.rb_internal_data {
+                    Some(data) => self.handle_data(*data, relay, client_state),
+                    None => warn!("{} got an unknown message type: {:?}", self.abstraction_id, inner.r#type()),
+                }
+            },
+            Type::PlSend => PerfectLinkManager::handle_pl_send(message, &client_state.system_id, client_state.own_port, &client_state.link_keys),
+            _ => warn!("{} got an unknown message type: {:?}", self.abstraction_id, message.r#type()),
+        }
+    }
+
+    /// Called when the failure detector reports that `process` crashed
+    pub fn handle_crash(&mut self, process: &ProcessId, client_state: &ClientState) {
+        if !self.lazy || !self.crashed.insert(process.port) {
+            return;
+        }
+        for data in self.from.remove(&process.port).unwrap_or_default() {
+            debug!("{} relays message {} of {}-{}", self.abstraction_id, data.message_id, process.owner, process.index);
+            self.relay(data, client_state);
+        }
+    }
+
+    fn broadcast(&self, message: Envelope, client_state: &ClientState) {
+        let Some(source) = client_state.own_process().cloned() else {
+            warn!("{} cannot broadcast outside of a system", self.abstraction_id);
+            return;
+        };
+        let inner = *message.rb_broadcast.unwrap().message.unwrap();
+
+        let data = protobuf::RbInternalData {
+            message_id: inner.message_uuid.clone(),
+            source: Option::from(source),
+            message: NetworkService::wrap_envelope_contents(inner),
+        };
+        self.relay(data, client_state);
+    }
+
+    fn handle_data(&mut self, data: protobuf::RbInternalData, relay: ProcessId, client_state: &ClientState) {
+        if !self.delivered.insert(data.message_id.clone()) {
+            return;
+        }
+        if let (Some(message), Some(source)) = (&data.message, &data.source) {
+            deliver_to_parent(&self.abstraction_id, (**message).clone(), source.clone(), &self.tx);
+        }
+
+        if !self.lazy {
+            self.relay(data, client_state);
+        } else if self.crashed.contains(&relay.port) {
+            // Whoever relayed it may have crashed before reaching everyone
+            self.relay(data, client_state);
+        } else {
+            self.from.entry(relay.port).or_default().push(data);
+        }
+    }
+
+    fn relay(&self, data: protobuf::RbInternalData, client_state: &ClientState) {
+        let mut wrapper = Envelope::with_shipping_label(Type::RbInternalData);
+        wrapper.rb_internal_data = NetworkService::wrap_envelope_contents(data);
+        wrapper.from_abstraction_id = self.abstraction_id.clone();
+        wrapper.to_abstraction_id = self.abstraction_id.clone();
+        BroadcastManager::do_beb_broadcast(wrapper, &self.tx, client_state);
+    }
+}
+
+/// Abstraction an upcall goes to, e.g. `app` for `app.erb`, or for `app.hc[a.b]`, whose instance name has a dot
+pub(crate) fn parent_abstraction_id(abstraction_id: &str) -> &str {
+    let mut depth = 0;
+    for (position, character) in abstraction_id.char_indices().rev() {
+        match character {
+            ']' => depth += 1,
+            '[' => depth -= 1,
+            '.' if depth == 0 => return &abstraction_id[..position],
+            _ => {},
+        }
+    }
+    abstraction_id
+}
+
+/// Ask the broadcast abstraction at `abstraction_id` to broadcast `message`
+pub(crate) fn request_broadcast(abstraction_id: &str, message: Envelope, tx: &Sender<Envelope>) {
+    let rb_broadcast = protobuf::RbBroadcast {
+        message: NetworkService::wrap_envelope_contents(message),
+    };
+
+    let mut wrapper = Envelope::with_shipping_label(Type::RbBroadcast);
+    wrapper.rb_broadcast = NetworkService::wrap_envelope_contents(rb_broadcast);
+    wrapper.from_abstraction_id = parent_abstraction_id(abstraction_id).to_string();
+    wrapper.to_abstraction_id = abstraction_id.to_string();
+    tx.send(wrapper).unwrap();
+}
+
+/// Hand `message`, broadcast by `sender`, up from the broadcast abstraction at `abstraction_id`
+pub(crate) fn deliver_to_parent(abstraction_id: &str, message: Envelope, sender: ProcessId, tx: &Sender<Envelope>) {
+    let rb_deliver = protobuf::RbDeliver {
+        message: NetworkService::wrap_envelope_contents(message),
+        sender: Option::from(sender),
+    };
+
+    let mut wrapper = Envelope::with_shipping_label(Type::RbDeliver);
+    wrapper.rb_deliver = NetworkService::wrap_envelope_contents(rb_deliver);
+    wrapper.from_abstraction_id = abstraction_id.to_string();
+    wrapper.to_abstraction_id = parent_abstraction_id(abstraction_id).to_string();
+    tx.send(wrapper).unwrap();
+}
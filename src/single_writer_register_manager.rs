@@ -0,0 +1,342 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc::Sender;
+use crate::{debug, protobuf, warn, Envelope};
+use crate::broadcast_manager::BroadcastManager;
+use crate::client::ClientState;
+use crate::event_context::EventContext;
+use crate::perfect_link_manager::PerfectLinkManager;
+use crate::protobuf::message::Type;
+use crate::register_manager::{register_name, reject_write, return_read, return_write, Contents, Operation, OperationKind, Receipt, RegisterSummary};
+use crate::regular_register_manager::check_designated_writer;
+use crate::tag::Tag;
+use crate::value_codec::{I32Codec, ValueCodec};
+
+/// Handles all messages addressed to `app.onar` and its subroutes: (1,N) atomic registers,
+/// Read-Impose Write-Majority. Like the regular ones, they are written by the process of lowest rank
+/// and speak the NNAR messages.
+pub struct SingleWriterRegisterManager<C: ValueCodec = I32Codec> {
+    registers: HashMap<String, SingleWriterRegister<C>>,
+    tx: Sender<Envelope>,
+}
+
+struct SingleWriterRegister<C: ValueCodec> {
+    tag: Tag,
+    value: Contents<C>,
+    /// Tag of the last write started here, when this process is the writer
+    write_tag: Tag,
+    read_id: i32,
+    /// Operations started by this process, keyed by their read id
+    operations: HashMap<i32, Operation<C>>,
+    queued_operations: VecDeque<OperationKind<C>>,
+
+    my_name: String,
+
+    tx: Sender<Envelope>,
+}
+
+impl<C: ValueCodec> SingleWriterRegisterManager<C> {
+    pub fn new(tx: Sender<Envelope>) -> Self {
+        SingleWriterRegisterManager {
+            registers: HashMap::new(),
+            tx,
+        }
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: ClientState) {
+        let destination = message.to_abstraction_id.clone();
+        let Some(name) = register_name(&destination) else {
+            warn!("Message not addressed to a (1,N) atomic register arrived in SingleWriterRegisterManager");
+            return;
+        };
+
+        let context = EventContext::local(&message);
+        self.registers.entry(name.to_string())
+            .or_insert_with(|| SingleWriterRegister::new(self.tx.clone(), name))
+            .handle_message(message, context, client_state);
+    }
+
+    pub fn summaries(&self) -> Vec<RegisterSummary> {
+        let mut summaries = self.registers.values()
+            .map(|register| register.summary())
+            .collect::<Vec<_>>();
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        summaries
+    }
+
+    /// Abandon every read or write still in flight or queued, returning how many there were
+    pub fn fail_pending_operations(&mut self) -> usize {
+        self.registers.values_mut()
+            .map(|register| register.fail_pending_operations())
+            .sum()
+    }
+}
+
+impl<C: ValueCodec> SingleWriterRegister<C> {
+    fn new(tx: Sender<Envelope>, name: &str) -> Self {
+        SingleWriterRegister {
+            tag: Tag::default(),
+            value: None,
+            write_tag: Tag::default(),
+            read_id: 0,
+            operations: HashMap::new(),
+            queued_operations: VecDeque::new(),
+            my_name: name.to_string(),
+            tx,
+        }
+    }
+
+    fn abstraction_id(&self) -> String {
+        format!("app.onar[{}]", self.my_name)
+    }
+
+    fn handle_message(&mut self, message: Envelope, context: EventContext, client_state: ClientState) {
+        match message.r#type() {
+            Type::NnarRead => self.submit_operation(OperationKind::Read, client_state),
+            Type::NnarWrite => self.handle_write(message, client_state),
+            Type::NnarInternalRead => self.handle_internal_read(message, context, client_state),
+            Type::NnarInternalValue => self.handle_internal_value(message, context, client_state),
+            Type::NnarInternalWrite => self.handle_internal_write(message, context, client_state),
+            Type::NnarInternalAck => self.handle_internal_ack(message, context, client_state),
+
+            Type::PlDeliver | Type::BebDeliver => {
+                let (sender, inner) = BroadcastManager::unwrap_delivery(message).unwrap();
+                let context = EventContext::delivered(sender, &inner);
+                self.handle_message(inner, context, client_state);
+            },
+            Type::PlSend => PerfectLinkManager::handle_pl_send(message, &client_state.system_id, client_state.own_port, &client_state.link_keys),
+
+            _ => warn!("(1,N) atomic register '{}' got an unknown message type: {:?}", self.my_name, message.r#type()),
+        }
+    }
+
+    fn handle_write(&mut self, message: Envelope, client_state: ClientState) {
+        if let Err(reason) = check_designated_writer(&client_state) {
+            reject_write(&self.my_name, reason, &client_state, &self.tx);
+            return;
+        }
+
+        match C::from_wire(&message.nnar_write.unwrap().value.unwrap_or_default()) {
+            Ok(Some(value)) => self.submit_operation(OperationKind::Write(value), client_state),
+            Ok(None) => reject_write(&self.my_name, "the undefined value cannot be written".to_string(), &client_state, &self.tx),
+            Err(e) => reject_write(&self.my_name, format!("that value cannot be written: {}", e), &client_state, &self.tx),
+        }
+    }
+
+    fn submit_operation(&mut self, kind: OperationKind<C>, client_state: ClientState) {
+        if self.operations.is_empty() {
+            self.start_operation(kind, client_state);
+        } else {
+            debug!("(1,N) atomic register '{}' queued an operation behind the one in flight", self.my_name);
+            self.queued_operations.push_back(kind);
+        }
+    }
+
+    fn start_operation(&mut self, kind: OperationKind<C>, client_state: ClientState) {
+        self.read_id += 1;
+        let mut operation = Operation::new(kind, client_state.nodes.len());
+
+        let message = match &operation.kind {
+            OperationKind::Read => {
+                let mut wrapper = Envelope::with_shipping_label(Type::NnarInternalRead);
+                wrapper.nnar_internal_read = Option::from(protobuf::NnarInternalRead { read_id: self.read_id });
+                wrapper
+            },
+            OperationKind::Write(value) => {
+                // The writer knows the latest tag, so writes skip the query phase
+                let imposed = self.write_tag.next(client_state.rank)
+                    .and_then(|tag| Ok((tag, self.impose_message(self.read_id, tag, Some(value))?)));
+                let (tag, message) = match imposed {
+                    Ok(imposed) => imposed,
+                    Err(reason) => {
+                        reject_write(&self.my_name, reason, &client_state, &self.tx);
+                        self.start_queued_operation(client_state);
+                        return;
+                    }
+                };
+                self.write_tag = tag;
+                operation.imposed_value = Some(Some(value.clone()));
+                message
+            },
+        };
+        self.operations.insert(self.read_id, operation);
+        self.broadcast(message, &client_state);
+    }
+
+    fn impose_message(&self, read_id: i32, tag: Tag, value: Option<&C::Value>) -> Result<Envelope, String> {
+        let (timestamp, writer_rank) = tag.to_wire()?;
+        let payload = protobuf::NnarInternalWrite {
+            read_id,
+            timestamp,
+            writer_rank,
+            value: Option::from(C::to_wire(value)),
+        };
+
+        let mut wrapper = Envelope::with_shipping_label(Type::NnarInternalWrite);
+        wrapper.nnar_internal_write = Option::from(payload);
+        Ok(wrapper)
+    }
+
+    fn handle_internal_read(&self, message: Envelope, context: EventContext, client_state: ClientState) {
+        let Some(reader) = context.sender else {
+            warn!("(1,N) atomic register '{}' got a Read without knowing who to answer", self.my_name);
+            return;
+        };
+
+        let (timestamp, writer_rank) = match self.tag.to_wire() {
+            Ok(pair) => pair,
+            Err(e) => {
+                warn!("(1,N) atomic register '{}' cannot answer a Read: {}", self.my_name, e);
+                return;
+            }
+        };
+
+        let value = protobuf::NnarInternalValue {
+            read_id: message.nnar_internal_read.unwrap().read_id,
+            timestamp,
+            writer_rank,
+            value: Option::from(C::to_wire(self.value.as_ref())),
+        };
+
+        let mut wrapper = Envelope::with_shipping_label(Type::NnarInternalValue);
+        wrapper.nnar_internal_value = Option::from(value);
+        wrapper.to_abstraction_id = self.abstraction_id();
+        PerfectLinkManager::send_to(wrapper, reader, &self.abstraction_id(), &client_state);
+    }
+
+    fn handle_internal_value(&mut self, message: Envelope, context: EventContext, client_state: ClientState) {
+        let value = message.nnar_internal_value.unwrap();
+        let Some(replica) = context.sender else {
+            warn!("(1,N) atomic register '{}' got a Value without knowing who sent it", self.my_name);
+            return;
+        };
+        let read_id = value.read_id;
+        let Some(operation) = self.operations.get_mut(&read_id) else {
+            debug!("(1,N) atomic register '{}' ignored a Value for read id {}", self.my_name, read_id);
+            return;
+        };
+        if operation.imposed_value.is_some() {
+            return;
+        }
+
+        match Receipt::from_wire(value) {
+            Ok(receipt) => { operation.read_receipts.insert(replica, receipt); },
+            Err(e) => {
+                warn!("(1,N) atomic register '{}' ignored a Value it cannot decode: {}", self.my_name, e);
+                return;
+            }
+        }
+        if !operation.read_receipts.is_reached() {
+            return;
+        }
+
+        // Impose the highest value on a majority before returning it, so that no later read returns an older one
+        let highest = Receipt::highest(operation.read_receipts.replies())
+            .expect("A quorum holds at least one receipt");
+        let (tag, value) = (highest.tag, highest.value.clone());
+        operation.imposed_value = Some(value.clone());
+
+        match self.impose_message(read_id, tag, value.as_ref()) {
+            Ok(message) => self.broadcast(message, &client_state),
+            Err(e) => {
+                warn!("(1,N) atomic register '{}' abandoned read id {}: {}", self.my_name, read_id, e);
+                self.operations.remove(&read_id);
+                self.start_queued_operation(client_state);
+            },
+        }
+    }
+
+    fn handle_internal_write(&mut self, message: Envelope, context: EventContext, client_state: ClientState) {
+        let write = message.nnar_internal_write.unwrap();
+        let Some(sender) = context.sender else {
+            warn!("(1,N) atomic register '{}' got a Write without knowing who to acknowledge", self.my_name);
+            return;
+        };
+        let decoded = Tag::from_wire(write.timestamp, write.writer_rank)
+            .and_then(|tag| Ok((tag, C::from_wire(&write.value.unwrap_or_default())?)));
+        let (tag, value) = match decoded {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                warn!("(1,N) atomic register '{}' refused a Write it cannot decode: {}", self.my_name, e);
+                return;
+            }
+        };
+
+        // Readers impose values too, so a Write may come from any process
+        if tag > self.tag {
+            self.tag = tag;
+            self.value = value;
+        }
+
+        let ack = protobuf::NnarInternalAck {
+            read_id: write.read_id,
+        };
+        let mut wrapper = Envelope::with_shipping_label(Type::NnarInternalAck);
+        wrapper.nnar_internal_ack = Option::from(ack);
+        wrapper.to_abstraction_id = self.abstraction_id();
+        PerfectLinkManager::send_to(wrapper, sender, &self.abstraction_id(), &client_state);
+    }
+
+    fn handle_internal_ack(&mut self, message: Envelope, context: EventContext, client_state: ClientState) {
+        let read_id = message.nnar_internal_ack.unwrap().read_id;
+        let Some(replica) = context.sender else {
+            warn!("(1,N) atomic register '{}' got an Ack without knowing who sent it", self.my_name);
+            return;
+        };
+        let Some(operation) = self.operations.get_mut(&read_id) else {
+            debug!("(1,N) atomic register '{}' ignored an Ack for read id {}", self.my_name, read_id);
+            return;
+        };
+        let Some(imposed_value) = operation.imposed_value.clone() else {
+            warn!("(1,N) atomic register '{}' got an Ack for read id {} before imposing a value", self.my_name, read_id);
+            return;
+        };
+
+        operation.acks.insert(replica, ());
+        if !operation.acks.is_reached() {
+            return;
+        }
+
+        let operation = self.operations.remove(&read_id).unwrap();
+        match operation.kind {
+            OperationKind::Read => return_read(&self.my_name, C::to_wire(imposed_value.as_ref()), &client_state, &self.tx),
+            OperationKind::Write(_) => return_write(&self.my_name, &client_state, &self.tx),
+        }
+
+        self.start_queued_operation(client_state);
+    }
+
+    fn start_queued_operation(&mut self, client_state: ClientState) {
+        if let Some(next) = self.queued_operations.pop_front() {
+            self.start_operation(next, client_state);
+        }
+    }
+
+    fn summary(&self) -> RegisterSummary {
+        // One operation is in flight at a time
+        let operations = self.operations.iter()
+            .map(|(read_id, operation)| operation.summary(*read_id))
+            .chain(self.queued_operations.iter().map(|kind| kind.queued_summary()))
+            .collect();
+
+        RegisterSummary {
+            name: self.my_name.clone(),
+            abstraction_id: self.abstraction_id(),
+            tag: self.tag,
+            value: C::to_wire(self.value.as_ref()),
+            operations,
+        }
+    }
+
+    fn fail_pending_operations(&mut self) -> usize {
+        let count = self.operations.len() + self.queued_operations.len();
+        self.operations.clear();
+        self.queued_operations.clear();
+        count
+    }
+
+    fn broadcast(&self, mut message: Envelope, client_state: &ClientState) {
+        message.from_abstraction_id = self.abstraction_id();
+        message.to_abstraction_id = self.abstraction_id();
+        BroadcastManager::do_beb_broadcast(message, &self.tx, client_state);
+    }
+}
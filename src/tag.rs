@@ -0,0 +1,42 @@
+use std::fmt;
+
+/// Version of a register value: the timestamp of the write that produced it, ties being broken by
+/// the rank of its writer. Tags compare by timestamp first, then by rank.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tag {
+    pub ts: u64,
+    pub rank: u32,
+}
+
+impl Tag {
+    pub fn new(ts: u64, rank: u32) -> Self {
+        Tag { ts, rank }
+    }
+
+    /// Tag of a write by the process of rank `writer_rank` that supersedes this one
+    pub fn next(self, writer_rank: i32) -> Result<Self, String> {
+        let ts = self.ts.checked_add(1).ok_or("the timestamp would overflow")?;
+        let rank = u32::try_from(writer_rank).map_err(|_| format!("invalid writer rank {}", writer_rank))?;
+        Ok(Tag { ts, rank })
+    }
+
+    /// Tag carried by a message as a (timestamp, writer rank) pair, neither of which can be negative
+    pub fn from_wire(timestamp: i32, writer_rank: i32) -> Result<Self, String> {
+        let ts = u64::try_from(timestamp).map_err(|_| format!("invalid timestamp {}", timestamp))?;
+        let rank = u32::try_from(writer_rank).map_err(|_| format!("invalid writer rank {}", writer_rank))?;
+        Ok(Tag { ts, rank })
+    }
+
+    /// The (timestamp, writer rank) pair messages carry, unless the tag outgrew their fields
+    pub fn to_wire(self) -> Result<(i32, i32), String> {
+        let timestamp = i32::try_from(self.ts).map_err(|_| format!("timestamp {} does not fit in a message", self.ts))?;
+        let writer_rank = i32::try_from(self.rank).map_err(|_| format!("writer rank {} does not fit in a message", self.rank))?;
+        Ok((timestamp, writer_rank))
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(timestamp {}, writer rank {})", self.ts, self.rank)
+    }
+}
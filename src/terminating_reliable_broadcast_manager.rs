@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+use crate::{debug, protobuf, warn, Envelope};
+use crate::client::ClientState;
+use crate::hierarchical_consensus_manager::{request_propose, HierarchicalConsensusManager};
+use crate::protobuf::message::Type;
+use crate::protobuf::ProcessId;
+use crate::reliable_broadcast_manager::{deliver_to_parent, request_broadcast, ReliableBroadcastManager};
+
+pub const ABSTRACTION_ID: &str = "app.trb";
+
+/// Terminating reliable broadcast, Consensus-Based, one instance per topic and designated sender under
+/// `app.trb[topic@port]`, `port` being the sender's: every correct process delivers the value of the sender, or
+/// every one of them delivers that it failed. Since every message of an instance names its sender, a process
+/// knows whom to wait for as soon as it hears of the instance, whether or not it got the AppTrbBroadcast.
+/// The sender's value goes over an eager reliable broadcast; each process proposes what it delivers from it, or
+/// a failed value once the failure detector reports the sender crashed, and delivers what the consensus decides.
+/// It relies on the perfect failure detector running.
+pub struct TerminatingReliableBroadcastManager {
+    /// Keyed by `topic@port`
+    instances: HashMap<String, Instance>,
+    /// Processes detected as crashed, for the instances started later
+    crashed: Vec<ProcessId>,
+    tx: Sender<Envelope>,
+}
+
+struct Instance {
+    topic: String,
+    abstraction_id: String,
+    /// Designated sender
+    sender: ProcessId,
+    /// Whether the AppTrbBroadcast of the instance arrived
+    started: bool,
+    rb: ReliableBroadcastManager,
+    consensus: HierarchicalConsensusManager,
+    proposed: bool,
+    delivered: bool,
+    tx: Sender<Envelope>,
+}
+
+impl TerminatingReliableBroadcastManager {
+    pub fn new(tx: Sender<Envelope>) -> Self {
+        TerminatingReliableBroadcastManager {
+            instances: HashMap::new(),
+            crashed: Vec::new(),
+            tx,
+        }
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        if message.r#type() == Type::AppTrbBroadcast {
+            let trb_broadcast = message.app_trb_broadcast.unwrap();
+            let Some(sender) = trb_broadcast.sender else {
+                warn!("{} got a broadcast on '{}' without a designated sender", ABSTRACTION_ID, trb_broadcast.topic);
+                return;
+            };
+            self.instance(&trb_broadcast.topic, sender).start(trb_broadcast.value, client_state);
+            return;
+        }
+
+        let Some((topic, sender_port)) = instance_name(&message.to_abstraction_id) else {
+            warn!("{} got a {:?} for {}", ABSTRACTION_ID, message.r#type(), message.to_abstraction_id);
+            return;
+        };
+        let Some(sender) = client_state.nodes.iter().find(|node| node.port == sender_port).cloned() else {
+            warn!("{} got a {:?} for {}, whose sender is not in the system",
+                  ABSTRACTION_ID, message.r#type(), message.to_abstraction_id);
+            return;
+        };
+        self.instance(topic, sender).handle_message(message, client_state);
+    }
+
+    /// Called when the failure detector reports that `process` crashed
+    pub fn handle_crash(&mut self, process: &ProcessId, client_state: &ClientState) {
+        self.crashed.push(process.clone());
+        for instance in self.instances.values_mut() {
+            instance.handle_crash(process, client_state);
+        }
+    }
+
+    fn instance(&mut self, topic: &str, sender: ProcessId) -> &mut Instance {
+        let crashed = &self.crashed;
+        let tx = &self.tx;
+        self.instances.entry(format!("{}@{}", topic, sender.port))
+            .or_insert_with(|| {
+                let sender_crashed = crashed.iter().any(|process| process.port == sender.port);
+                let mut instance = Instance::new(topic, sender, crashed, tx.clone());
+                // The sender crashed already, so its value may never come
+                if sender_crashed {
+                    instance.propose_failed();
+                }
+                instance
+            })
+    }
+}
+
+impl Instance {
+    fn new(topic: &str, sender: ProcessId, crashed: &[ProcessId], tx: Sender<Envelope>) -> Self {
+        let abstraction_id = format!("{}[{}@{}]", ABSTRACTION_ID, topic, sender.port);
+        Instance {
+            topic: topic.to_string(),
+            rb: ReliableBroadcastManager::eager(&abstraction_id, tx.clone()),
+            consensus: HierarchicalConsensusManager::new(format!("{}.hc", abstraction_id), crashed, tx.clone()),
+            abstraction_id,
+            sender,
+            started: false,
+            proposed: false,
+            delivered: false,
+            tx,
+        }
+    }
+
+    /// Handle the AppTrbBroadcast of the instance, in which the designated sender broadcasts `value`
+    fn start(&mut self, value: Option<protobuf::Value>, client_state: &ClientState) {
+        if self.started {
+            warn!("{} was already started", self.abstraction_id);
+            return;
+        }
+        self.started = true;
+
+        if self.sender.port == client_state.own_port as i32 {
+            let trb_deliver = protobuf::AppTrbDeliver {
+                topic: self.topic.clone(),
+                sender: Option::from(self.sender.clone()),
+                value,
+                failed: false,
+            };
+            request_broadcast(self.rb.abstraction_id(), app_trb_deliver(trb_deliver), &self.tx);
+        }
+    }
+
+    fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        if message.to_abstraction_id.starts_with(self.rb.abstraction_id()) {
+            self.rb.handle_message(message, client_state);
+            return;
+        }
+        if message.to_abstraction_id != self.abstraction_id {
+            self.consensus.handle_message(message, client_state);
+            return;
+        }
+
+        match message.r#type() {
+            Type::RbDeliver => {
+                let rb_deliver = message.rb_deliver.unwrap();
+                match (rb_deliver.message, rb_deliver.sender) {
+                    (Some(inner), Some(source)) => self.handle_delivery(*inner, source),
+                    _ => warn!("{} got a delivery it cannot read", self.abstraction_id),
+                }
+            },
+            Type::HcDecide => {
+                let value = message.hc_decide.unwrap().value.map(|value| *value).unwrap_or_default();
+                self.deliver(value);
+            },
+            _ => warn!("{} got an unknown message type: {:?}", self.abstraction_id, message.r#type()),
+        }
+    }
+
+    fn handle_crash(&mut self, process: &ProcessId, client_state: &ClientState) {
+        self.consensus.handle_crash(process, client_state);
+        if self.sender.port == process.port {
+            self.propose_failed();
+        }
+    }
+
+    /// Propose the value the designated sender broadcast
+    fn handle_delivery(&mut self, message: Envelope, source: ProcessId) {
+        if self.sender.port != source.port || message.app_trb_deliver.is_none() {
+            warn!("{} ignored a {:?} from {}-{}, which is not its sender",
+                  self.abstraction_id, message.r#type(), source.owner, source.index);
+            return;
+        }
+        self.propose(message);
+    }
+
+    fn propose_failed(&mut self) {
+        let trb_deliver = protobuf::AppTrbDeliver {
+            topic: self.topic.clone(),
+            sender: Option::from(self.sender.clone()),
+            value: None,
+            failed: true,
+        };
+        self.propose(app_trb_deliver(trb_deliver));
+    }
+
+    fn propose(&mut self, value: Envelope) {
+        if self.proposed {
+            return;
+        }
+        self.proposed = true;
+        request_propose(&format!("{}.hc", self.abstraction_id), value, &self.tx);
+    }
+
+    fn deliver(&mut self, value: Envelope) {
+        if self.delivered {
+            return;
+        }
+        self.delivered = true;
+
+        let sender = value.app_trb_deliver.as_ref().and_then(|trb_deliver| trb_deliver.sender.clone());
+        debug!("{} delivers {:?}", self.abstraction_id, value.app_trb_deliver);
+        deliver_to_parent(&self.abstraction_id, value, sender.unwrap_or_default(), &self.tx);
+    }
+}
+
+fn app_trb_deliver(trb_deliver: protobuf::AppTrbDeliver) -> Envelope {
+    let mut wrapper = Envelope::with_shipping_label(Type::AppTrbDeliver);
+    wrapper.app_trb_deliver = Option::from(trb_deliver);
+    wrapper.to_abstraction_id = "app".to_string();
+    wrapper
+}
+
+/// Topic and sender port of the instance an abstraction id such as `app.trb[t@5004].hc.beb.pl` belongs to
+fn instance_name(abstraction_id: &str) -> Option<(&str, i32)> {
+    let rest = abstraction_id.strip_prefix(ABSTRACTION_ID)?.strip_prefix('[')?;
+    let (name, _) = rest.split_once(']')?;
+    let (topic, sender_port) = name.rsplit_once('@')?;
+    Some((topic, sender_port.parse().ok()?))
+}
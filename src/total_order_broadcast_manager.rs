@@ -0,0 +1,168 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::mpsc::Sender;
+use crate::{debug, protobuf, warn, Envelope};
+use crate::client::ClientState;
+use crate::hierarchical_consensus_manager::{request_propose, HierarchicalConsensusManager};
+use crate::network_service::NetworkService;
+use crate::protobuf::message::Type;
+use crate::protobuf::ProcessId;
+use crate::reliable_broadcast_manager::{deliver_to_parent, request_broadcast, ReliableBroadcastManager};
+
+pub const ABSTRACTION_ID: &str = "app.tob";
+
+/// Total order broadcast, Consensus-Based: every correct process delivers the same messages in the
+/// same order. Messages run over an eager reliable broadcast and wait in `unordered`; rounds of
+/// consensus, one instance per round under `app.tob.hc[round]`, agree on the next batch to deliver.
+/// The consensus relies on the perfect failure detector running.
+pub struct TotalOrderBroadcastManager {
+    rb: ReliableBroadcastManager,
+    /// Messages delivered by the reliable broadcast but not ordered yet, in the order they arrived
+    unordered: Vec<protobuf::RbInternalData>,
+    /// Ids of the messages delivered so far
+    delivered: HashSet<String>,
+    /// Round whose decision is delivered next, starting at 1
+    round: i64,
+    /// Whether this process proposed in the current round
+    wait: bool,
+    /// Consensus instances of the current and later rounds, keyed by round
+    consensus: BTreeMap<i64, HierarchicalConsensusManager>,
+    /// Batches decided ahead of the current round, keyed by round
+    decisions: BTreeMap<i64, Envelope>,
+    /// Processes detected as crashed, for the consensus instances started later
+    crashed: Vec<ProcessId>,
+    tx: Sender<Envelope>,
+}
+
+impl TotalOrderBroadcastManager {
+    pub fn new(tx: Sender<Envelope>) -> Self {
+        TotalOrderBroadcastManager {
+            rb: ReliableBroadcastManager::eager(ABSTRACTION_ID, tx.clone()),
+            unordered: Vec::new(),
+            delivered: HashSet::new(),
+            round: 1,
+            wait: false,
+            consensus: BTreeMap::new(),
+            decisions: BTreeMap::new(),
+            crashed: Vec::new(),
+            tx,
+        }
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        if message.to_abstraction_id.starts_with(self.rb.abstraction_id()) {
+            self.rb.handle_message(message, client_state);
+            return;
+        }
+        if let Some(round) = consensus_round(&message.to_abstraction_id) {
+            if round < self.round {
+                debug!("{} ignored a {:?} for round {}, which is over", ABSTRACTION_ID, message.r#type(), round);
+                return;
+            }
+            let crashed = &self.crashed;
+            let tx = &self.tx;
+            self.consensus.entry(round)
+                .or_insert_with(|| HierarchicalConsensusManager::new(consensus_abstraction_id(round), crashed, tx.clone()))
+                .handle_message(message, client_state);
+            return;
+        }
+
+        match message.r#type() {
+            Type::RbBroadcast => {
+                let inner = *message.rb_broadcast.unwrap().message.unwrap();
+                request_broadcast(self.rb.abstraction_id(), inner, &self.tx);
+            },
+            Type::RbDeliver => {
+                let rb_deliver = message.rb_deliver.unwrap();
+                match (rb_deliver.message, rb_deliver.sender) {
+                    (Some(inner), Some(sender)) => self.handle_delivery(*inner, sender),
+                    _ => warn!("{} got a delivery it cannot read", ABSTRACTION_ID),
+                }
+            },
+            Type::HcDecide => {
+                let Some(round) = consensus_round(&message.from_abstraction_id) else {
+                    warn!("{} got a decision from {}", ABSTRACTION_ID, message.from_abstraction_id);
+                    return;
+                };
+                let value = message.hc_decide.unwrap().value.map(|value| *value).unwrap_or_default();
+                self.handle_decision(round, value);
+            },
+            _ => warn!("{} got an unknown message type: {:?}", ABSTRACTION_ID, message.r#type()),
+        }
+    }
+
+    /// Called when the failure detector reports that `process` crashed
+    pub fn handle_crash(&mut self, process: &ProcessId, client_state: &ClientState) {
+        self.crashed.push(process.clone());
+        for consensus in self.consensus.values_mut() {
+            consensus.handle_crash(process, client_state);
+        }
+    }
+
+    fn handle_delivery(&mut self, message: Envelope, sender: ProcessId) {
+        let message_id = message.message_uuid.clone();
+        if self.delivered.contains(&message_id) || self.unordered.iter().any(|data| data.message_id == message_id) {
+            return;
+        }
+
+        let data = protobuf::RbInternalData {
+            message_id,
+            source: Option::from(sender),
+            message: NetworkService::wrap_envelope_contents(message),
+        };
+        self.unordered.push(data);
+        self.propose();
+    }
+
+    fn handle_decision(&mut self, round: i64, value: Envelope) {
+        if round < self.round {
+            return;
+        }
+        self.decisions.insert(round, value);
+
+        while let Some(value) = self.decisions.remove(&self.round) {
+            let batch = value.tob_batch.map(|batch| batch.messages).unwrap_or_default();
+            debug!("{} delivers round {} with {} message(s)", ABSTRACTION_ID, self.round, batch.len());
+
+            for data in batch {
+                if !self.delivered.insert(data.message_id.clone()) {
+                    continue;
+                }
+                self.unordered.retain(|pending| pending.message_id != data.message_id);
+                if let (Some(message), Some(source)) = (data.message, data.source) {
+                    deliver_to_parent(ABSTRACTION_ID, *message, source, &self.tx);
+                }
+            }
+
+            self.consensus.remove(&self.round);
+            self.round += 1;
+            self.wait = false;
+        }
+        self.propose();
+    }
+
+    /// Propose the messages not ordered yet in the current round, unless this process already did
+    fn propose(&mut self) {
+        if self.wait || self.unordered.is_empty() {
+            return;
+        }
+        self.wait = true;
+
+        let batch = protobuf::TobBatch {
+            messages: self.unordered.clone(),
+        };
+        let mut wrapper = Envelope::with_shipping_label(Type::TobBatch);
+        wrapper.tob_batch = Option::from(batch);
+        request_propose(&consensus_abstraction_id(self.round), wrapper, &self.tx);
+    }
+}
+
+fn consensus_abstraction_id(round: i64) -> String {
+    format!("{}.hc[{}]", ABSTRACTION_ID, round)
+}
+
+/// Round of the consensus instance an abstraction id such as `app.tob.hc[3].beb.pl` belongs to
+fn consensus_round(abstraction_id: &str) -> Option<i64> {
+    let rest = abstraction_id.strip_prefix(ABSTRACTION_ID)?.strip_prefix(".hc[")?;
+    let (round, _) = rest.split_once(']')?;
+    round.parse().ok()
+}
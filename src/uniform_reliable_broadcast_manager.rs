@@ -0,0 +1,155 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::Sender;
+use crate::{debug, protobuf, warn, Envelope};
+use crate::broadcast_manager::BroadcastManager;
+use crate::client::ClientState;
+use crate::network_service::NetworkService;
+use crate::perfect_link_manager::PerfectLinkManager;
+use crate::protobuf::message::Type;
+use crate::protobuf::ProcessId;
+use crate::quorum::Quorum;
+use crate::reliable_broadcast_manager::deliver_to_parent;
+
+pub const ALL_ACK_ABSTRACTION_ID: &str = "app.aurb";
+pub const MAJORITY_ACK_ABSTRACTION_ID: &str = "app.murb";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Algorithm {
+    /// Waits for every process not detected as crashed; relies on the perfect failure detector
+    AllAck,
+    /// Waits for a majority; assumes a majority of the processes is correct
+    MajorityAck,
+}
+
+/// Uniform reliable broadcast: a message delivered by any process, even one that crashes right after,
+/// is delivered by every correct process. A message is delivered once enough processes relayed it.
+/// It speaks the RB messages.
+pub struct UniformReliableBroadcastManager {
+    abstraction_id: &'static str,
+    algorithm: Algorithm,
+    /// Messages relayed but not delivered yet, keyed by message id
+    pending: HashMap<String, protobuf::RbInternalData>,
+    /// Processes that relayed each pending message, keyed by message id
+    acks: HashMap<String, Quorum<()>>,
+    delivered: HashSet<String>,
+    /// Ports of the processes detected as crashed
+    crashed: HashSet<i32>,
+    tx: Sender<Envelope>,
+}
+
+impl UniformReliableBroadcastManager {
+    /// All-Ack Uniform Reliable Broadcast, under `app.aurb`; it needs the perfect failure detector running
+    pub fn all_ack(tx: Sender<Envelope>) -> Self {
+        UniformReliableBroadcastManager {
+            abstraction_id: ALL_ACK_ABSTRACTION_ID,
+            algorithm: Algorithm::AllAck,
+            pending: HashMap::new(),
+            acks: HashMap::new(),
+            delivered: HashSet::new(),
+            crashed: HashSet::new(),
+            tx,
+        }
+    }
+
+    /// Majority-Ack Uniform Reliable Broadcast, under `app.murb`
+    pub fn majority_ack(tx: Sender<Envelope>) -> Self {
+        UniformReliableBroadcastManager {
+            abstraction_id: MAJORITY_ACK_ABSTRACTION_ID,
+            algorithm: Algorithm::MajorityAck,
+            ..Self::all_ack(tx)
+        }
+    }
+
+    pub fn handle_message(&mut self, message: Envelope, client_state: &ClientState) {
+        match message.r#type() {
+            Type::RbBroadcast => self.broadcast(message, client_state),
+            Type::PlDeliver | Type::BebDeliver => {
+                let (relay, inner) = BroadcastManager::unwrap_delivery(message).unwrap();
+                match inner.rb_internal_data {
+                    Some(data) => self.handle_data(*data, relay, client_state),
+                    None => warn!("{} got an unknown message type: {:?}", self.abstraction_id, inner.r#type()),
+                }
+            },
+            Type::PlSend => PerfectLinkManager::handle_pl_send(message, &client_state.system_id, client_state.own_port, &client_state.link_keys),
+            _ => warn!("{} got an unknown message type: {:?}", self.abstraction_id, message.r#type()),
+        }
+    }
+
+    /// Called when the failure detector reports that `process` crashed
+    pub fn handle_crash(&mut self, process: &ProcessId, client_state: &ClientState) {
+        if self.algorithm != Algorithm::AllAck || !self.crashed.insert(process.port) {
+            return;
+        }
+        let message_ids = self.pending.keys().cloned().collect::<Vec<_>>();
+        for message_id in message_ids {
+            self.deliver_if_acknowledged(&message_id, client_state);
+        }
+    }
+
+    fn broadcast(&mut self, message: Envelope, client_state: &ClientState) {
+        let Some(source) = client_state.own_process().cloned() else {
+            warn!("{} cannot broadcast outside of a system", self.abstraction_id);
+            return;
+        };
+        let inner = *message.rb_broadcast.unwrap().message.unwrap();
+
+        let data = protobuf::RbInternalData {
+            message_id: inner.message_uuid.clone(),
+            source: Option::from(source),
+            message: NetworkService::wrap_envelope_contents(inner),
+        };
+        self.add_pending(data, client_state);
+    }
+
+    fn handle_data(&mut self, data: protobuf::RbInternalData, relay: ProcessId, client_state: &ClientState) {
+        let message_id = data.message_id.clone();
+        if self.delivered.contains(&message_id) {
+            return;
+        }
+        if !self.pending.contains_key(&message_id) {
+            self.add_pending(data, client_state);
+        }
+
+        if !self.acks.get_mut(&message_id).unwrap().insert(relay, ()) {
+            debug!("{} ignored a second relay of message {}", self.abstraction_id, message_id);
+            return;
+        }
+        self.deliver_if_acknowledged(&message_id, client_state);
+    }
+
+    /// Remember `data` and relay it, which counts as this process acknowledging it
+    fn add_pending(&mut self, data: protobuf::RbInternalData, client_state: &ClientState) {
+        self.acks.insert(data.message_id.clone(), Quorum::majority(client_state.nodes.len()));
+        self.pending.insert(data.message_id.clone(), data.clone());
+
+        let mut wrapper = Envelope::with_shipping_label(Type::RbInternalData);
+        wrapper.rb_internal_data = NetworkService::wrap_envelope_contents(data);
+        wrapper.from_abstraction_id = self.abstraction_id.to_string();
+        wrapper.to_abstraction_id = self.abstraction_id.to_string();
+        BroadcastManager::do_beb_broadcast(wrapper, &self.tx, client_state);
+    }
+
+    fn deliver_if_acknowledged(&mut self, message_id: &str, client_state: &ClientState) {
+        let Some(acks) = self.acks.get(message_id) else { return };
+        let acknowledged = match self.algorithm {
+            Algorithm::AllAck => {
+                let acked = acks.senders().map(|sender| sender.port).collect::<HashSet<_>>();
+                client_state.nodes.iter()
+                    .filter(|node| !self.crashed.contains(&node.port))
+                    .all(|node| acked.contains(&node.port))
+            },
+            Algorithm::MajorityAck => acks.is_reached(),
+        };
+        if !acknowledged {
+            return;
+        }
+
+        // Once delivered, later relays of the message are ignored, so its entries can go
+        self.acks.remove(message_id);
+        let data = self.pending.remove(message_id).unwrap();
+        self.delivered.insert(message_id.to_string());
+        if let (Some(message), Some(source)) = (data.message, data.source) {
+            deliver_to_parent(self.abstraction_id, *message, source, &self.tx);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "value-payload")]
+use std::marker::PhantomData;
+use crate::protobuf;
+
+/// How the values a register holds are carried inside `protobuf::Value`.
+/// On the Rust side, the undefined value of the textbook is `None`.
+pub trait ValueCodec {
+    type Value: Clone + fmt::Debug;
+
+    fn encode(value: &Self::Value) -> protobuf::Value;
+    fn decode(value: &protobuf::Value) -> Result<Self::Value, String>;
+
+    fn to_wire(value: Option<&Self::Value>) -> protobuf::Value {
+        value.map(Self::encode).unwrap_or_default()
+    }
+
+    fn from_wire(value: &protobuf::Value) -> Result<Option<Self::Value>, String> {
+        if !value.defined {
+            return Ok(None);
+        }
+        Self::decode(value).map(Some)
+    }
+}
+
+/// Codec picked for a register by name; without the value-payload feature, every register holds `i32` values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RegisterCodec {
+    #[default]
+    I32,
+    #[cfg(feature = "value-payload")]
+    Bytes,
+    #[cfg(feature = "value-payload")]
+    String,
+}
+
+impl FromStr for RegisterCodec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "i32" => Ok(RegisterCodec::I32),
+            #[cfg(feature = "value-payload")]
+            "bytes" => Ok(RegisterCodec::Bytes),
+            #[cfg(feature = "value-payload")]
+            "string" => Ok(RegisterCodec::String),
+            #[cfg(feature = "value-payload")]
+            _ => Err(format!("Unknown register codec '{}', expected i32, bytes or string", s)),
+            #[cfg(not(feature = "value-payload"))]
+            _ => Err(format!("Unknown register codec '{}', expected i32; bytes and string need the value-payload feature", s)),
+        }
+    }
+}
+
+/// Plain `i32` values, exactly as the reference hub sends them
+pub struct I32Codec;
+
+impl ValueCodec for I32Codec {
+    type Value = i32;
+
+    // The update only has an effect when the value-payload feature adds the payload, which stays empty
+    #[cfg_attr(not(feature = "value-payload"), allow(clippy::needless_update))]
+    fn encode(value: &i32) -> protobuf::Value {
+        protobuf::Value {
+            defined: true,
+            v: *value,
+            ..Default::default()
+        }
+    }
+
+    fn decode(value: &protobuf::Value) -> Result<i32, String> {
+        #[cfg(feature = "value-payload")]
+        if !value.payload.is_empty() {
+            return Err(format!("Expected an i32, got a {} byte payload", value.payload.len()));
+        }
+        Ok(value.v)
+    }
+}
+
+/// Raw bytes, carried in the payload field
+#[cfg(feature = "value-payload")]
+pub struct BytesCodec;
+
+#[cfg(feature = "value-payload")]
+impl ValueCodec for BytesCodec {
+    type Value = Vec<u8>;
+
+    fn encode(value: &Vec<u8>) -> protobuf::Value {
+        protobuf::Value {
+            defined: true,
+            payload: value.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn decode(value: &protobuf::Value) -> Result<Vec<u8>, String> {
+        Ok(value.payload.clone())
+    }
+}
+
+/// UTF-8 strings, carried in the payload field
+#[cfg(feature = "value-payload")]
+pub struct StringCodec;
+
+#[cfg(feature = "value-payload")]
+impl ValueCodec for StringCodec {
+    type Value = String;
+
+    fn encode(value: &String) -> protobuf::Value {
+        BytesCodec::encode(&value.clone().into_bytes())
+    }
+
+    fn decode(value: &protobuf::Value) -> Result<String, String> {
+        String::from_utf8(value.payload.clone()).map_err(|e| format!("Payload is not UTF-8: {}", e))
+    }
+}
+
+/// Small structs described as protobuf messages, encoded into the payload field
+#[cfg(feature = "value-payload")]
+pub struct MessageCodec<M> {
+    message: PhantomData<M>,
+}
+
+#[cfg(feature = "value-payload")]
+impl<M: prost::Message + Default + Clone> ValueCodec for MessageCodec<M> {
+    type Value = M;
+
+    fn encode(value: &M) -> protobuf::Value {
+        BytesCodec::encode(&value.encode_to_vec())
+    }
+
+    fn decode(value: &protobuf::Value) -> Result<M, String> {
+        M::decode(value.payload.as_slice()).map_err(|e| format!("Payload is not a valid message: {}", e))
+    }
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::Duration;
+use dp_algo::authenticated_link::LinkKeys;
+use dp_algo::broadcast_manager::BroadcastGuarantee;
+use dp_algo::hub::{BroadcastStamp, HubEvent, HubHandle};
+use dp_algo::protobuf::message::Type;
+use dp_algo::protobuf::ProcessId;
+use dp_algo::value_codec::{I32Codec, ValueCodec};
+use dp_algo::{causal_broadcast_manager, fifo_broadcast_manager, protobuf, Envelope, NetworkService, NodeHandle};
+use common::{address, free_ports, send, shut_down, wait_until};
+
+fn stamps(hub: &HubHandle) -> Vec<BroadcastStamp> {
+    hub.events().into_iter()
+        .filter_map(|event| match event {
+            HubEvent::Delivered { stamp, .. } => stamp,
+            _ => None,
+        })
+        .collect()
+}
+
+/// Have two processes broadcast values back to back, and check that they reach everyone in an order the hub accepts
+fn broadcast_causally(beb_loopback: bool) {
+    let (hub, node) = common::start("abc", 3, |config| {
+        config.broadcast_guarantee = BroadcastGuarantee::Causal;
+        config.beb_loopback = beb_loopback;
+    });
+
+    for value in 1..=5 {
+        hub.broadcast("abc-1", value).unwrap();
+        hub.broadcast("abc-2", 10 + value).unwrap();
+    }
+    wait_until("every value to be delivered everywhere", || stamps(&hub).len() == 30);
+
+    let stamps = stamps(&hub);
+    assert!(stamps.iter().all(|stamp| stamp.vector_clock.len() == 3), "{:?}", stamps);
+    assert_eq!(stamps.iter().filter(|stamp| stamp.sender == "abc-1").map(|stamp| stamp.sequence_number).max(), Some(5));
+    assert_eq!(hub.check_deliveries(BroadcastGuarantee::Causal), Vec::<String>::new());
+
+    shut_down(hub, node);
+}
+
+#[test]
+fn causal_broadcasts_pass_the_hub_check() {
+    broadcast_causally(false);
+}
+
+/// Own copies going through the listener must not change what gets delivered
+#[test]
+fn causal_broadcasts_pass_the_hub_check_over_loopback() {
+    broadcast_causally(true);
+}
+
+/// Start three processes broadcasting over `guarantee`, and a fourth one that the test plays, whose port comes last
+fn start_with_a_player(guarantee: BroadcastGuarantee) -> (HubHandle, NodeHandle, ProcessId) {
+    let port = free_ports(1)[0];
+    let (hub, node) = common::start_on("abc", vec![address(0); 3], &[port], |config| {
+        config.broadcast_guarantee = guarantee;
+    });
+    let player = hub.system().1.into_iter().find(|member| member.port == port as i32).unwrap();
+    (hub, node, player)
+}
+
+/// Have `player` relay, as part of the eager reliable broadcast under `abstraction_id`, the message it
+/// broadcast there, only to the first process
+fn relay_to_the_first(node: &NodeHandle, player: &ProcessId, abstraction_id: &str, message: Envelope) {
+    let mut envelope = Envelope::with_shipping_label(Type::RbInternalData);
+    envelope.rb_internal_data = NetworkService::wrap_envelope_contents(protobuf::RbInternalData {
+        message_id: message.message_uuid.clone(),
+        source: Option::from(player.clone()),
+        message: NetworkService::wrap_envelope_contents(message),
+    });
+    envelope.from_abstraction_id = format!("{}.erb", abstraction_id);
+    envelope.to_abstraction_id = envelope.from_abstraction_id.clone();
+    send(envelope, node.own_addresses()[0], player.port as u16, &LinkKeys::default());
+}
+
+fn app_value(value: i32) -> Box<Envelope> {
+    let mut envelope = Envelope::with_shipping_label(Type::AppValue);
+    envelope.app_value = Option::from(protobuf::AppValue { value: Option::from(I32Codec::encode(&value)), ..Default::default() });
+    envelope.to_abstraction_id = "app".to_string();
+    Box::new(envelope)
+}
+
+/// Values each process delivered, in delivery order
+fn values(hub: &HubHandle) -> BTreeMap<String, Vec<i32>> {
+    let mut values = BTreeMap::<String, Vec<i32>>::new();
+    for event in hub.events() {
+        if let HubEvent::Delivered { process, value, .. } = event {
+            values.entry(process).or_default().push(value.v);
+        }
+    }
+    values
+}
+
+/// The second message of a sender arrives before its first one: every process holds it until the first arrives
+#[test]
+fn fifo_broadcast_holds_messages_that_arrive_ahead_of_their_turn() {
+    let (hub, node, player) = start_with_a_player(BroadcastGuarantee::Fifo);
+    let fifo_data = |sequence_number, value| {
+        let mut envelope = Envelope::with_shipping_label(Type::FrbInternalData);
+        envelope.frb_internal_data = NetworkService::wrap_envelope_contents(protobuf::FrbInternalData {
+            sequence_number,
+            message: Option::from(app_value(value)),
+        });
+        envelope
+    };
+
+    relay_to_the_first(&node, &player, fifo_broadcast_manager::ABSTRACTION_ID, fifo_data(2, 20));
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(values(&hub), BTreeMap::new());
+
+    relay_to_the_first(&node, &player, fifo_broadcast_manager::ABSTRACTION_ID, fifo_data(1, 10));
+    wait_until("both values to be delivered everywhere", || values(&hub).values().flatten().count() == 6);
+    assert_eq!(values(&hub), ["sys-1/abc-1", "sys-1/abc-2", "sys-1/abc-3"].into_iter()
+        .map(|process| (process.to_string(), vec![10, 20]))
+        .collect());
+
+    shut_down(hub, node);
+}
+
+/// Messages whose vector clock does not have one entry per process of the system are dropped, rather than
+/// compared entry by entry with a clock they do not match
+#[test]
+fn causal_broadcast_drops_vector_clocks_of_the_wrong_size() {
+    let (hub, node, player) = start_with_a_player(BroadcastGuarantee::Causal);
+    let causal_data = |vector_clock, value| {
+        let mut envelope = Envelope::with_shipping_label(Type::CrbInternalData);
+        envelope.crb_internal_data = NetworkService::wrap_envelope_contents(protobuf::CrbInternalData {
+            vector_clock,
+            message: Option::from(app_value(value)),
+        });
+        envelope
+    };
+
+    relay_to_the_first(&node, &player, causal_broadcast_manager::ABSTRACTION_ID, causal_data(vec![0; 3], 3));
+    relay_to_the_first(&node, &player, causal_broadcast_manager::ABSTRACTION_ID, causal_data(vec![0; 5], 5));
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(values(&hub), BTreeMap::new());
+
+    relay_to_the_first(&node, &player, causal_broadcast_manager::ABSTRACTION_ID, causal_data(vec![0; 4], 4));
+    wait_until("the value to be delivered everywhere", || values(&hub).len() == 3);
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(values(&hub), ["sys-1/abc-1", "sys-1/abc-2", "sys-1/abc-3"].into_iter()
+        .map(|process| (process.to_string(), vec![4]))
+        .collect());
+
+    shut_down(hub, node);
+}
+
+/// Every process broadcasts values of its own at once, and all of them deliver all the values in the same order
+#[test]
+fn total_order_broadcasts_are_delivered_in_the_same_order_everywhere() {
+    let (hub, node) = common::start("abc", 3, |config| config.broadcast_guarantee = BroadcastGuarantee::TotalOrder);
+
+    for value in 1..=5 {
+        for (index, process) in ["abc-1", "abc-2", "abc-3"].into_iter().enumerate() {
+            hub.broadcast(process, (index as i32 + 1) * 10 + value).unwrap();
+        }
+    }
+    wait_until("every value to be delivered everywhere", || values(&hub).values().flatten().count() == 45);
+
+    let values = values(&hub);
+    let orders = values.values().collect::<Vec<_>>();
+    assert_eq!(orders.len(), 3);
+    assert!(orders.iter().all(|order| order == &orders[0]), "{:?}", values);
+    assert_eq!(hub.check_deliveries(BroadcastGuarantee::TotalOrder), Vec::<String>::new());
+
+    shut_down(hub, node);
+}
@@ -0,0 +1,209 @@
+mod common;
+
+use std::io::Write;
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+use dp_algo::authenticated_link::{LinkKeys, ReplayWindow};
+use dp_algo::broadcast_manager::BroadcastGuarantee;
+use dp_algo::hub::{HubEvent, HubHandle};
+use dp_algo::protobuf::message::Type;
+use dp_algo::protobuf::ProcessId;
+use dp_algo::value_codec::{I32Codec, ValueCodec};
+use dp_algo::{protobuf, Envelope, NodeHandle, NetworkService};
+use common::{address, free_ports, send, shut_down, wait_until};
+
+/// A key for every two of the four processes listening on `ports`
+fn link_keys(ports: &[u16], secret: &str) -> LinkKeys {
+    let mut keys = LinkKeys::default();
+    for first in 0..ports.len() {
+        for second in first + 1..ports.len() {
+            keys.insert(ports[first], ports[second], format!("{}-{}-{}", secret, first + 1, second + 1));
+        }
+    }
+    keys
+}
+
+/// Start a hub and three correct processes, and register a fourth one that the test plays, making a
+/// system of N = 4 processes tolerating f = 1 fault. The link keys need the ports up front, which come back
+/// in index order, the fourth process's last.
+fn start(guarantee: BroadcastGuarantee) -> (HubHandle, NodeHandle, Vec<u16>) {
+    let ports = free_ports(4);
+    let addresses = ports[..3].iter().map(|port| address(*port)).collect();
+    let (hub, node) = common::start_on("byz", addresses, &ports[3..], |config| {
+        config.broadcast_guarantee = guarantee;
+        config.link_keys = link_keys(&ports, "secret");
+    });
+    (hub, node, ports)
+}
+
+fn app_value(value: i32) -> Envelope {
+    let mut envelope = Envelope::with_shipping_label(Type::AppValue);
+    envelope.app_value = Option::from(protobuf::AppValue { value: Option::from(I32Codec::encode(&value)), ..Default::default() });
+    envelope.to_abstraction_id = "app".to_string();
+    envelope
+}
+
+/// A SEND or ECHO from the fourth process, for the message it broadcasts as `message_id`
+fn byzantine_part(message_type: Type, abstraction_id: &str, ports: &[u16], message_id: &str, value: Envelope) -> Envelope {
+    let source = Option::from(ProcessId { host: "127.0.0.1".to_string(), port: ports[3] as i32, ..Default::default() });
+    let message = NetworkService::wrap_envelope_contents(value);
+    let mut envelope = Envelope::with_shipping_label(message_type);
+    match message_type {
+        Type::ByzInternalSend => envelope.byz_internal_send = NetworkService::wrap_envelope_contents(
+            protobuf::ByzInternalSend { message_id: message_id.to_string(), source, message }),
+        _ => envelope.byz_internal_echo = NetworkService::wrap_envelope_contents(
+            protobuf::ByzInternalEcho { message_id: message_id.to_string(), source, message }),
+    }
+    envelope.from_abstraction_id = abstraction_id.to_string();
+    envelope.to_abstraction_id = abstraction_id.to_string();
+    envelope
+}
+
+/// Values delivered so far, as (process, value)
+fn deliveries(hub: &HubHandle) -> Vec<(String, i32)> {
+    hub.events().into_iter()
+        .filter_map(|event| match event {
+            HubEvent::Delivered { process, value, .. } => Some((process, value.v)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn wait_for_deliveries(hub: &HubHandle, count: usize) -> Vec<(String, i32)> {
+    wait_until(&format!("{} deliveries", count), || deliveries(hub).len() >= count);
+    thread::sleep(Duration::from_millis(300));
+    deliveries(hub)
+}
+
+/// The correct processes deliver a correct process's value even though the fourth one stays silent
+fn deliver_despite_a_silent_process(guarantee: BroadcastGuarantee) {
+    let (hub, node, _) = start(guarantee);
+    hub.broadcast("byz-2", 42).unwrap();
+
+    let mut delivered = wait_for_deliveries(&hub, 3);
+    delivered.sort();
+    assert_eq!(delivered, vec![
+        ("sys-1/byz-1".to_string(), 42), ("sys-1/byz-2".to_string(), 42), ("sys-1/byz-3".to_string(), 42),
+    ]);
+
+    shut_down(hub, node);
+}
+
+#[test]
+fn consistent_broadcast_tolerates_a_silent_process() {
+    deliver_despite_a_silent_process(BroadcastGuarantee::ByzantineConsistent);
+}
+
+#[test]
+fn reliable_broadcast_tolerates_a_silent_process() {
+    deliver_despite_a_silent_process(BroadcastGuarantee::ByzantineReliable);
+}
+
+/// The fourth process sends and echoes 1 to the first process and 2 to the others, under the same message id.
+/// Only 2 gets enough echoes, so no correct process delivers 1; the first process may not deliver at all
+/// under consistent broadcast, while under reliable broadcast the others being ready brings it along.
+fn agree_despite_an_equivocating_source(guarantee: BroadcastGuarantee) -> Vec<(String, i32)> {
+    let (hub, node, ports) = start(guarantee);
+    let abstraction_id = guarantee.abstraction_id().unwrap();
+    let keys = link_keys(&ports, "secret");
+    let (one, two) = (app_value(1), app_value(2));
+
+    for (port, value) in [(ports[0], &one), (ports[1], &two), (ports[2], &two)] {
+        for message_type in [Type::ByzInternalSend, Type::ByzInternalEcho] {
+            let part = byzantine_part(message_type, abstraction_id, &ports, "forked", value.clone());
+            send(part, address(port), ports[3], &keys);
+        }
+    }
+
+    let mut delivered = wait_for_deliveries(&hub, if guarantee.is_total() { 3 } else { 2 });
+    delivered.sort();
+
+    shut_down(hub, node);
+    delivered
+}
+
+#[test]
+fn consistent_broadcast_agrees_despite_an_equivocating_source() {
+    assert_eq!(agree_despite_an_equivocating_source(BroadcastGuarantee::ByzantineConsistent), vec![
+        ("sys-1/byz-2".to_string(), 2), ("sys-1/byz-3".to_string(), 2),
+    ]);
+}
+
+#[test]
+fn reliable_broadcast_agrees_despite_an_equivocating_source() {
+    assert_eq!(agree_despite_an_equivocating_source(BroadcastGuarantee::ByzantineReliable), vec![
+        ("sys-1/byz-1".to_string(), 2), ("sys-1/byz-2".to_string(), 2), ("sys-1/byz-3".to_string(), 2),
+    ]);
+}
+
+/// Messages signed with keys the processes do not share are dropped, the same messages signed with the
+/// right keys go through
+#[test]
+fn authenticated_links_drop_forged_messages() {
+    let (hub, node, ports) = start(BroadcastGuarantee::ByzantineReliable);
+    let abstraction_id = BroadcastGuarantee::ByzantineReliable.abstraction_id().unwrap();
+
+    let value = app_value(7);
+    let send_to_all = |keys: &LinkKeys| for port in &ports[..3] {
+        let send_part = byzantine_part(Type::ByzInternalSend, abstraction_id, &ports, "signed", value.clone());
+        send(send_part, address(*port), ports[3], keys);
+    };
+    send_to_all(&link_keys(&ports, "guessed"));
+    send_to_all(&LinkKeys::default());
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(deliveries(&hub), vec![]);
+
+    send_to_all(&link_keys(&ports, "secret"));
+    let delivered = wait_for_deliveries(&hub, 3);
+    assert_eq!(delivered.len(), 3, "{:?}", delivered);
+    assert!(delivered.iter().all(|(_, value)| *value == 7), "{:?}", delivered);
+
+    shut_down(hub, node);
+}
+
+/// A peer that connects and stays silent, sends bytes that are no message, or announces an absurd length only
+/// loses its own connection: the processes keep delivering
+#[test]
+fn malformed_and_silent_connections_do_not_stop_a_process() {
+    let (hub, node, ports) = start(BroadcastGuarantee::ByzantineReliable);
+
+    let mut connections = Vec::new();
+    for port in &ports[..3] {
+        let silent = TcpStream::connect(address(*port)).unwrap();
+        let mut garbage = TcpStream::connect(address(*port)).unwrap();
+        garbage.write_all(&[0, 0, 0, 4, 0xff, 0xff, 0xff, 0xff]).unwrap();
+        let mut too_long = TcpStream::connect(address(*port)).unwrap();
+        too_long.write_all(&[0xff, 0xff, 0xff, 0xff]).unwrap();
+        connections.extend([silent, garbage, too_long]);
+    }
+    hub.broadcast("byz-2", 42).unwrap();
+
+    let mut delivered = wait_for_deliveries(&hub, 3);
+    delivered.sort();
+    assert_eq!(delivered, vec![
+        ("sys-1/byz-1".to_string(), 42), ("sys-1/byz-2".to_string(), 42), ("sys-1/byz-3".to_string(), 42),
+    ]);
+    drop(connections);
+
+    shut_down(hub, node);
+}
+
+#[test]
+fn replay_window_drops_repeated_and_stale_sequence_numbers() {
+    let mut window = ReplayWindow::default();
+    assert!(window.accept(1, 10));
+    assert!(!window.accept(1, 10));
+    // Messages may overtake each other, and other senders have windows of their own
+    assert!(window.accept(1, 9));
+    assert!(window.accept(2, 10));
+
+    for sequence_number in 11..11 + ReplayWindow::SIZE as u64 {
+        assert!(window.accept(1, sequence_number));
+    }
+    // 9 and 10 fell out of the window, so they can no longer be told apart from replays
+    assert!(!window.accept(1, 9));
+    assert!(!window.accept(1, 10));
+    assert!(!window.accept(1, 11 + ReplayWindow::SIZE as u64 - 1));
+    assert!(window.accept(1, 11 + ReplayWindow::SIZE as u64));
+}
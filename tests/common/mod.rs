@@ -0,0 +1,106 @@
+// Each test binary only uses some of the helpers
+#![allow(dead_code)]
+
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+use std::time::{Duration, Instant};
+use dp_algo::authenticated_link::LinkKeys;
+use dp_algo::hub::{Hub, HubHandle};
+use dp_algo::perfect_link_manager::PerfectLinkManager;
+use dp_algo::protobuf::message::Type;
+use dp_algo::protobuf::ProcessId;
+use dp_algo::{protobuf, Envelope, NetworkService, Node, NodeConfig, NodeHandle};
+
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn address(port: u16) -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], port))
+}
+
+/// Ports that were free a moment ago, for the few cases that must know a port before anything listens on it,
+/// like link keys or processes that never run. Everything else listens on port 0.
+pub fn free_ports(count: usize) -> Vec<u16> {
+    let listeners = (0..count)
+        .map(|_| TcpListener::bind(address(0)).unwrap())
+        .collect::<Vec<_>>();
+    listeners.iter().map(|listener| listener.local_addr().unwrap().port()).collect()
+}
+
+/// Start a hub and `processes` processes of `owner` on free ports, tweaked by `configure`, all in one system
+pub fn start(owner: &str, processes: usize, configure: impl FnOnce(&mut NodeConfig)) -> (HubHandle, NodeHandle) {
+    start_on(owner, vec![address(0); processes], &[], configure)
+}
+
+/// Start a hub and the processes of `owner` listening on `addresses`, tweaked by `configure`. The processes
+/// on `absent_ports` register as well, right after them, but never run: the failure detector reports them
+/// crashed unless a test plays them. Everyone ends up in one system.
+pub fn start_on(owner: &str, addresses: Vec<SocketAddr>, absent_ports: &[u16],
+                configure: impl FnOnce(&mut NodeConfig)) -> (HubHandle, NodeHandle) {
+    let hub = Hub::start(address(0));
+    let processes = addresses.len() + absent_ports.len();
+    let mut config = NodeConfig::new(hub.address(), addresses);
+    config.owner = owner.to_string();
+    configure(&mut config);
+    let running = config.own_addresses.len();
+    let node = Node::start(config);
+
+    for (offset, port) in absent_ports.iter().enumerate() {
+        register(&hub, owner, (running + offset + 1) as i32, *port);
+    }
+    wait_until("the processes to register", || hub.processes().len() == processes);
+    hub.create_system(&[owner]).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    (hub, node)
+}
+
+/// Register with `hub` the process `owner`-`index`, as if it listened on `own_port`
+pub fn register(hub: &HubHandle, owner: &str, index: i32, own_port: u16) {
+    let mut registration = Envelope::with_shipping_label(Type::ProcRegistration);
+    registration.proc_registration = Option::from(protobuf::ProcRegistration { owner: owner.to_string(), index });
+    registration.to_abstraction_id = "app".to_string();
+
+    let pl_send = protobuf::PlSend {
+        destination: Option::from(ProcessId { host: "127.0.0.1".to_string(), port: hub.address().port() as i32, ..Default::default() }),
+        message: NetworkService::wrap_envelope_contents(registration),
+    };
+    let mut wrapper = Envelope::with_shipping_label(Type::PlSend);
+    wrapper.pl_send = NetworkService::wrap_envelope_contents(pl_send);
+    NetworkService::send(&hub.address(), wrapper, own_port);
+}
+
+/// Send `message` over a perfect link from `own_port`, the way the process listening there would, for tests
+/// playing a process that does not run
+pub fn send(message: Envelope, destination: SocketAddr, own_port: u16, link_keys: &LinkKeys) {
+    let to_abstraction_id = message.to_abstraction_id.clone();
+    let pl_send = protobuf::PlSend {
+        destination: Option::from(ProcessId { host: destination.ip().to_string(), port: destination.port() as i32, ..Default::default() }),
+        message: NetworkService::wrap_envelope_contents(message),
+    };
+    let mut wrapper = Envelope::with_shipping_label(Type::PlSend);
+    wrapper.pl_send = NetworkService::wrap_envelope_contents(pl_send);
+    wrapper.to_abstraction_id = to_abstraction_id;
+    PerfectLinkManager::handle_pl_send(wrapper, "sys-1", own_port, link_keys);
+}
+
+/// Poll `found` until it finds something, failing the test after `TIMEOUT`
+pub fn wait_for<T>(what: &str, mut found: impl FnMut() -> Option<T>) -> T {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if let Some(value) = found() {
+            return value;
+        }
+        assert!(Instant::now() < deadline, "Timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+pub fn wait_until(what: &str, condition: impl Fn() -> bool) {
+    wait_for(what, || condition().then_some(()))
+}
+
+/// Stop the node and the hub, and check that the node stopped cleanly
+pub fn shut_down(hub: HubHandle, node: NodeHandle) {
+    let report = node.shutdown();
+    hub.shutdown();
+    assert!(report.is_clean(), "{:?}", report);
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+use dp_algo::hub::{HubEvent, HubHandle};
+use dp_algo::protobuf::message::Type;
+use dp_algo::value_codec::{I32Codec, ValueCodec};
+use dp_algo::{protobuf, Envelope, NodeHandle};
+use common::{shut_down, wait_until};
+
+/// Have the process with 1-based `index` propose `value` on `topic`, the way the hub would
+fn propose(node: &NodeHandle, index: usize, topic: &str, value: i32) {
+    let mut wrapper = Envelope::with_shipping_label(Type::AppPropose);
+    wrapper.app_propose = Option::from(protobuf::AppPropose { topic: topic.to_string(), value: Option::from(I32Codec::encode(&value)) });
+    wrapper.to_abstraction_id = "app".to_string();
+    node.queue(index).unwrap().send(wrapper).unwrap();
+}
+
+/// Values decided so far, as (process, value), in arrival order
+fn decisions(hub: &HubHandle) -> Vec<(String, i32)> {
+    hub.events().into_iter()
+        .filter_map(|event| match event {
+            HubEvent::Decided { process, value } => Some((process, value.v)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Every process proposes a value of its own, and all of them decide the same one: the proposal of the process
+/// of rank 1, which leads the first round
+#[test]
+fn processes_decide_the_same_proposed_value() {
+    let (hub, node) = common::start("hc", 3, |_| {});
+    for index in 1..=3 {
+        propose(&node, index, "t", index as i32 * 10);
+    }
+
+    wait_until("every process to decide", || decisions(&hub).len() == 3);
+    let mut decided = decisions(&hub);
+    decided.sort();
+    assert_eq!(decided, vec![
+        ("sys-1/hc-1".to_string(), 10), ("sys-1/hc-2".to_string(), 10), ("sys-1/hc-3".to_string(), 10),
+    ]);
+
+    shut_down(hub, node);
+}
+
+/// Each topic is an instance of its own, including topics with a dot in their name
+#[test]
+fn topics_are_independent_instances() {
+    let (hub, node) = common::start("hc", 3, |_| {});
+    for index in 1..=3 {
+        propose(&node, index, "a", index as i32);
+    }
+    wait_until("every process to decide on a", || decisions(&hub).len() == 3);
+
+    for index in 1..=3 {
+        propose(&node, index, "b.c", index as i32 + 10);
+    }
+    wait_until("every process to decide on b.c", || decisions(&hub).len() == 6);
+    let decided = decisions(&hub).into_iter().map(|(_, value)| value).collect::<Vec<_>>();
+    assert_eq!(decided, vec![1, 1, 1, 11, 11, 11]);
+
+    shut_down(hub, node);
+}
+
+/// The hub's consensus command gets exactly one decision back from every process, the same everywhere
+#[test]
+fn a_hub_proposal_gets_one_decision_per_process() {
+    let (hub, node) = common::start("hc", 3, |_| {});
+    hub.consensus("t").unwrap();
+
+    wait_until("every process to decide", || decisions(&hub).len() >= 3);
+    thread::sleep(Duration::from_millis(300));
+    let mut decided = decisions(&hub);
+    decided.sort();
+    assert_eq!(decided.iter().map(|(process, _)| process.as_str()).collect::<Vec<_>>(),
+               vec!["sys-1/hc-1", "sys-1/hc-2", "sys-1/hc-3"]);
+    assert!(decided.iter().all(|(_, value)| *value == decided[0].1), "{:?}", decided);
+
+    shut_down(hub, node);
+}
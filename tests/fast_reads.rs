@@ -0,0 +1,63 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+use dp_algo::hub::{HubEvent, HubHandle};
+use common::shut_down;
+
+/// Wait for the first event after the `seen` ones that `matches` accepts, returning what it extracted
+fn wait_for<T>(hub: &HubHandle, seen: &mut usize, matches: impl Fn(&HubEvent) -> Option<T>) -> T {
+    common::wait_for("an event", || {
+        let events = hub.events();
+        while *seen < events.len() {
+            *seen += 1;
+            if let Some(found) = matches(&events[*seen - 1]) {
+                return Some(found);
+            }
+        }
+        None
+    })
+}
+
+fn read(hub: &HubHandle, seen: &mut usize, process: &str) -> i32 {
+    hub.read("x", &[process]).unwrap();
+    let label = format!("{}/{}", hub.system().0, process);
+    wait_for(hub, seen, |event| match event {
+        HubEvent::ReadReturned { process, register, value } if *process == label && register == "x" => Some(value.v),
+        _ => None,
+    })
+}
+
+/// Reads that skip the write-back must still never return a value older than one already returned
+#[test]
+fn fast_reads_stay_atomic() {
+    let (hub, node) = common::start("abc", 3, |config| config.fast_reads = true);
+
+    let writer = format!("{}/abc-1", hub.system().0);
+    let mut seen = 0;
+    let mut write_seen = 0;
+    for value in 1..=20 {
+        // Reads race with the write of `value`, which the second reader may see after the first did
+        hub.write("x", value, &["abc-1"]).unwrap();
+        let first = read(&hub, &mut seen, "abc-2");
+        let second = read(&hub, &mut seen, "abc-3");
+        assert!(first >= value - 1, "abc-2 read {} after the write of {} returned", first, value - 1);
+        assert!(second >= first, "abc-3 read {} after abc-2 had read {}", second, first);
+
+        wait_for(&hub, &mut write_seen, |event| match event {
+            HubEvent::WriteReturned { process, register } if *process == writer && register == "x" => Some(()),
+            _ => None,
+        });
+        assert_eq!(read(&hub, &mut seen, "abc-2"), value);
+    }
+
+    // Processes publish their metrics right after handling the message that completed the read
+    thread::sleep(Duration::from_millis(200));
+    let metrics = node.processes().iter()
+        .map(|status| status.read_metrics)
+        .fold((0, 0), |(fast, slow), metrics| (fast + metrics.fast, slow + metrics.slow));
+    assert_eq!(metrics.0 + metrics.1, 60);
+    assert!(metrics.0 > 0, "No read took the fast path");
+
+    shut_down(hub, node);
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+use dp_algo::hub::{HubEvent, HubHandle};
+use dp_algo::probabilistic_broadcast_manager::{GossipMetrics, ABSTRACTION_ID};
+use dp_algo::protobuf::message::Type;
+use dp_algo::value_codec::{I32Codec, ValueCodec};
+use dp_algo::{protobuf, Envelope, NodeHandle};
+use common::{shut_down, wait_until};
+
+const PROCESSES: usize = 12;
+
+/// Start a hub and a node of `PROCESSES` processes, all in one system
+fn start(fanout: usize, rounds: i32) -> (HubHandle, NodeHandle) {
+    common::start("gsp", PROCESSES, |config| {
+        config.gossip_fanout = fanout;
+        config.gossip_rounds = rounds;
+    })
+}
+
+/// Have the first process gossip an AppValue holding `value`
+fn gossip(node: &NodeHandle, value: i32) {
+    let mut inner = Envelope::with_shipping_label(Type::AppValue);
+    inner.app_value = Option::from(protobuf::AppValue { value: Option::from(I32Codec::encode(&value)), ..Default::default() });
+    inner.to_abstraction_id = "app".to_string();
+
+    let rb_broadcast = protobuf::RbBroadcast { message: Option::from(Box::new(inner)) };
+    let mut wrapper = Envelope::with_shipping_label(Type::RbBroadcast);
+    wrapper.rb_broadcast = Option::from(Box::new(rb_broadcast));
+    wrapper.to_abstraction_id = ABSTRACTION_ID.to_string();
+    node.queue(1).unwrap().send(wrapper).unwrap();
+}
+
+fn deliveries(hub: &HubHandle) -> usize {
+    hub.events().iter()
+        .filter(|event| matches!(event, HubEvent::Delivered { .. }))
+        .count()
+}
+
+fn total_metrics(node: &NodeHandle) -> GossipMetrics {
+    node.processes().iter()
+        .map(|status| status.gossip_metrics)
+        .fold(GossipMetrics::default(), |total, metrics| GossipMetrics {
+            broadcast: total.broadcast + metrics.broadcast,
+            sent: total.sent + metrics.sent,
+            delivered: total.delivered + metrics.delivered,
+            duplicates: total.duplicates + metrics.duplicates,
+        })
+}
+
+/// With a fanout covering the whole system, a single hop reaches every process and nobody relays
+#[test]
+fn full_fanout_reaches_everyone_in_one_round() {
+    let (hub, node) = start(PROCESSES - 1, 1);
+    gossip(&node, 7);
+
+    wait_until("every process to deliver", || deliveries(&hub) == PROCESSES);
+    thread::sleep(Duration::from_millis(200));
+
+    let metrics = total_metrics(&node);
+    assert_eq!(metrics.broadcast, 1);
+    assert_eq!(metrics.delivered, PROCESSES);
+    assert_eq!(metrics.sent, PROCESSES - 1);
+    assert_eq!(metrics.duplicates, 0);
+
+    shut_down(hub, node);
+}
+
+/// A small fanout delivers each message at most once per process, and only the processes that
+/// delivered it relay it, each to `fanout` others
+#[test]
+fn small_fanout_bounds_the_relays() {
+    let fanout = 3;
+    let (hub, node) = start(fanout, 3);
+    gossip(&node, 9);
+    thread::sleep(Duration::from_millis(1000));
+
+    let metrics = total_metrics(&node);
+    assert_eq!(metrics.broadcast, 1);
+    assert_eq!(metrics.delivered, deliveries(&hub));
+    assert!(metrics.delivered <= PROCESSES);
+    assert!(metrics.sent <= fanout * metrics.delivered, "{:?}", metrics);
+    assert_eq!(metrics.delivered + metrics.duplicates, metrics.sent + 1, "{:?}", metrics);
+
+    shut_down(hub, node);
+}
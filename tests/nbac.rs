@@ -0,0 +1,71 @@
+#![cfg(feature = "nbac")]
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+use dp_algo::hub::{HubEvent, HubHandle};
+use dp_algo::NodeHandle;
+use common::{free_ports, shut_down, wait_until};
+
+/// Start a hub and three processes; with `absent`, also register a fourth process that is not running,
+/// as if it crashed right after joining
+fn start(absent: bool) -> (HubHandle, NodeHandle) {
+    let absent_ports = if absent { free_ports(1) } else { vec![] };
+    common::start_on("tx", vec![common::address(0); 3], &absent_ports, |_| {})
+}
+
+/// Decisions taken on `transaction`, as (process, commit), sorted by process
+fn decisions(hub: &HubHandle, transaction: &str) -> Vec<(String, bool)> {
+    let mut decisions = hub.events().into_iter()
+        .filter_map(|event| match event {
+            HubEvent::NbacDecided { process, transaction: decided, commit } if decided == transaction => Some((process, commit)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    decisions.sort();
+    decisions
+}
+
+fn wait_for_decisions(hub: &HubHandle, transaction: &str) -> Vec<(String, bool)> {
+    wait_until(&format!("every process to decide {}", transaction), || decisions(hub, transaction).len() >= 3);
+    thread::sleep(Duration::from_millis(200));
+    decisions(hub, transaction)
+}
+
+fn all_decide(commit: bool) -> Vec<(String, bool)> {
+    (1..=3).map(|index| (format!("sys-1/tx-{}", index), commit)).collect()
+}
+
+#[test]
+fn commits_when_everyone_votes_to_commit() {
+    let (hub, node) = start(false);
+    hub.nbac("t1", &[]).unwrap();
+
+    assert_eq!(wait_for_decisions(&hub, "t1"), all_decide(true));
+
+    shut_down(hub, node);
+}
+
+#[test]
+fn aborts_when_one_process_votes_to_abort() {
+    let (hub, node) = start(false);
+    hub.nbac("t1", &["tx-3"]).unwrap();
+    hub.nbac("t2", &[]).unwrap();
+
+    assert_eq!(wait_for_decisions(&hub, "t1"), all_decide(false));
+    assert_eq!(wait_for_decisions(&hub, "t2"), all_decide(true));
+
+    shut_down(hub, node);
+}
+
+/// The fourth process is not running, so it never votes and the failure detector reports it crashed
+#[test]
+fn aborts_when_a_process_crashed() {
+    let (hub, node) = start(true);
+    hub.nbac("t1", &[]).unwrap();
+
+    assert_eq!(wait_for_decisions(&hub, "t1"), all_decide(false));
+
+    shut_down(hub, node);
+}
@@ -0,0 +1,50 @@
+use dp_algo::protobuf::ProcessId;
+use dp_algo::quorum::Quorum;
+
+fn process(port: i32) -> ProcessId {
+    ProcessId { host: "127.0.0.1".to_string(), port, ..Default::default() }
+}
+
+/// Whether `replies` distinct processes out of `system_size` make a majority
+fn reached_with(system_size: usize, replies: i32) -> bool {
+    let mut quorum = Quorum::majority(system_size);
+    for port in 1..=replies {
+        quorum.insert(process(port), ());
+    }
+    quorum.is_reached()
+}
+
+#[test]
+fn majority_takes_more_than_half() {
+    for (system_size, threshold) in [(1, 1), (2, 2), (3, 2), (4, 3)] {
+        assert_eq!(Quorum::<()>::majority(system_size).threshold(), threshold, "N = {}", system_size);
+        assert!(!reached_with(system_size, threshold as i32 - 1), "N = {}", system_size);
+        assert!(reached_with(system_size, threshold as i32), "N = {}", system_size);
+    }
+}
+
+#[test]
+fn an_empty_quorum_is_not_reached() {
+    let quorum = Quorum::<()>::majority(1);
+    assert!(quorum.is_empty());
+    assert!(!quorum.is_reached());
+}
+
+#[test]
+fn repeated_senders_count_once() {
+    let mut quorum = Quorum::majority(3);
+    assert!(quorum.insert(process(1), "first"));
+    assert!(!quorum.insert(process(1), "second"));
+    assert!(!quorum.insert(process(1), "third"));
+
+    assert_eq!(quorum.len(), 1);
+    assert!(!quorum.is_reached());
+    // The first reply is the one kept
+    assert_eq!(quorum.replies().collect::<Vec<_>>(), vec![&"first"]);
+
+    assert!(quorum.insert(process(2), "other"));
+    assert!(quorum.is_reached());
+    let mut senders = quorum.senders().map(|sender| sender.port).collect::<Vec<_>>();
+    senders.sort();
+    assert_eq!(senders, vec![1, 2]);
+}
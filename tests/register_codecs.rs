@@ -0,0 +1,84 @@
+#![cfg(feature = "value-payload")]
+
+mod common;
+
+use std::collections::HashMap;
+use dp_algo::hub::{HubEvent, HubHandle};
+use dp_algo::protobuf::message::Type;
+use dp_algo::register_manager::RegisterKind;
+use dp_algo::value_codec::{BytesCodec, RegisterCodec, StringCodec, ValueCodec};
+use dp_algo::{protobuf, Envelope, NodeHandle};
+use common::shut_down;
+
+/// Start three processes whose register `r` is a `kind` register holding values of `codec`
+fn start(kind: RegisterKind, codec: RegisterCodec) -> (HubHandle, NodeHandle) {
+    common::start("cod", 3, |config| {
+        config.register_kinds = HashMap::from([("r".to_string(), kind)]);
+        config.register_codecs = HashMap::from([("r".to_string(), codec)]);
+    })
+}
+
+/// Index of the process of rank 1, which may write any register
+fn writer(node: &NodeHandle) -> usize {
+    node.processes().iter()
+        .min_by_key(|process| process.state.rank)
+        .map(|process| process.state.own_process().unwrap().index as usize)
+        .unwrap()
+}
+
+fn send(node: &NodeHandle, index: usize, mut wrapper: Envelope) {
+    wrapper.to_abstraction_id = "app".to_string();
+    node.queue(index).unwrap().send(wrapper).unwrap();
+}
+
+/// Have the writer write `value` to `r`, then have every process read `r` back, returning what they read
+fn write_then_read(kind: RegisterKind, codec: RegisterCodec, value: protobuf::Value) -> Vec<protobuf::Value> {
+    let (hub, node) = start(kind, codec);
+
+    let mut write = Envelope::with_shipping_label(Type::AppWrite);
+    write.app_write = Option::from(protobuf::AppWrite { register: "r".to_string(), value: Option::from(value) });
+    send(&node, writer(&node), write);
+    common::wait_until("the write to return", || hub.events().iter()
+        .any(|event| matches!(event, HubEvent::WriteReturned { register, .. } if register == "r")));
+
+    for index in 1..=3 {
+        let mut read = Envelope::with_shipping_label(Type::AppRead);
+        read.app_read = Option::from(protobuf::AppRead { register: "r".to_string() });
+        send(&node, index, read);
+    }
+    let read = common::wait_for("every process to read", || {
+        let values = hub.events().into_iter()
+            .filter_map(|event| match event {
+                HubEvent::ReadReturned { register, value, .. } if register == "r" => Some(value),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        (values.len() == 3).then_some(values)
+    });
+
+    shut_down(hub, node);
+    read
+}
+
+#[test]
+fn string_registers_read_back_what_was_written() {
+    let value = "héllo, world".to_string();
+    for kind in [RegisterKind::Nnar, RegisterKind::MajorityVoting, RegisterKind::SingleWriterAtomic] {
+        let read = write_then_read(kind, RegisterCodec::String, StringCodec::encode(&value));
+        for returned in read {
+            assert_eq!(StringCodec::from_wire(&returned), Ok(Some(value.clone())), "{:?}", kind);
+        }
+    }
+}
+
+#[test]
+fn bytes_registers_read_back_what_was_written() {
+    let value = vec![0, 159, 146, 150, 255];
+    for kind in [RegisterKind::Nnar, RegisterKind::MajorityVoting, RegisterKind::SingleWriterAtomic] {
+        let read = write_then_read(kind, RegisterCodec::Bytes, BytesCodec::encode(&value));
+        for returned in read {
+            assert_eq!(BytesCodec::from_wire(&returned), Ok(Some(value.clone())), "{:?}", kind);
+        }
+    }
+}
+
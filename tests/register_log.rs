@@ -0,0 +1,105 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use dp_algo::protobuf;
+use dp_algo::register_log::RegisterLog;
+use dp_algo::tag::Tag;
+use dp_algo::value_codec::{I32Codec, ValueCodec};
+use uuid::Uuid;
+
+/// A log file in a directory of its own, which nothing else writes to
+fn log_path() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dp-algo-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join("abc-1.wal")
+}
+
+fn value(v: i32) -> protobuf::Value {
+    I32Codec::encode(&v)
+}
+
+/// What a process logged before it stopped is what it finds when it opens the log again
+#[test]
+fn a_restarted_process_recovers_its_replicas_and_read_ids() {
+    let path = log_path();
+    {
+        let mut log = RegisterLog::open(&path).unwrap();
+        assert!(log.recovered("sys-1", "x").is_none());
+        log.log_read_id("sys-1", "x", 1).unwrap();
+        log.log_write("sys-1", "x", Tag::new(1, 2), &value(10)).unwrap();
+        log.log_read_id("sys-1", "x", 2).unwrap();
+        log.log_write("sys-1", "x", Tag::new(3, 1), &value(30)).unwrap();
+        log.log_write("sys-1", "y", Tag::new(1, 3), &value(5)).unwrap();
+        log.log_write("sys-2", "x", Tag::new(7, 1), &value(70)).unwrap();
+    }
+
+    let log = RegisterLog::open(&path).unwrap();
+    let x = log.recovered("sys-1", "x").unwrap();
+    assert_eq!((x.tag, &x.value, x.read_id), (Tag::new(3, 1), &value(30), 2));
+    let y = log.recovered("sys-1", "y").unwrap();
+    assert_eq!((y.tag, &y.value, y.read_id), (Tag::new(1, 3), &value(5), 0));
+    assert_eq!(log.recovered("sys-2", "x").unwrap().tag, Tag::new(7, 1));
+    assert!(log.recovered("sys-2", "y").is_none());
+}
+
+/// Names holding the characters records are split on come back whole, and do not mix with other names
+#[test]
+fn names_with_spaces_and_line_breaks_survive_a_restart() {
+    let path = log_path();
+    let names = ["my register", "50%", "two\nlines", "%20"];
+    {
+        let mut log = RegisterLog::open(&path).unwrap();
+        for (index, name) in names.iter().enumerate() {
+            log.log_write("sys 1", name, Tag::new(index as u64 + 1, 1), &value(index as i32)).unwrap();
+            log.log_read_id("sys 1", name, index as i32 + 1).unwrap();
+        }
+    }
+
+    let log = RegisterLog::open(&path).unwrap();
+    for (index, name) in names.iter().enumerate() {
+        let register = log.recovered("sys 1", name).unwrap_or_else(|| panic!("'{}' was not recovered", name));
+        assert_eq!(register.tag, Tag::new(index as u64 + 1, 1), "{}", name);
+        assert_eq!(register.value, value(index as i32), "{}", name);
+        assert_eq!(register.read_id, index as i32 + 1, "{}", name);
+    }
+    assert!(log.recovered("sys", "1").is_none());
+    assert!(log.recovered("sys 1", " ").is_none());
+}
+
+/// A crash in the middle of an append only loses the record being written
+#[test]
+fn a_partial_last_record_is_skipped() {
+    let path = log_path();
+    {
+        let mut log = RegisterLog::open(&path).unwrap();
+        log.log_write("sys-1", "x", Tag::new(1, 1), &value(1)).unwrap();
+    }
+    write!(OpenOptions::new().append(true).open(&path).unwrap(), "W sys-1 x 2 1 08").unwrap();
+
+    let log = RegisterLog::open(&path).unwrap();
+    let x = log.recovered("sys-1", "x").unwrap();
+    assert_eq!((x.tag, &x.value), (Tag::new(1, 1), &value(1)));
+}
+
+/// Reopening the log keeps one write and one read id per register, whatever the earlier runs appended
+#[test]
+fn reopening_compacts_the_log() {
+    let path = log_path();
+    {
+        let mut log = RegisterLog::open(&path).unwrap();
+        for ts in 1..=50 {
+            log.log_read_id("sys-1", "x", ts as i32).unwrap();
+            log.log_write("sys-1", "x", Tag::new(ts, 1), &value(ts as i32)).unwrap();
+            log.log_write("sys-1", "y", Tag::new(ts, 2), &value(-(ts as i32))).unwrap();
+        }
+    }
+
+    for _ in 0..2 {
+        let log = RegisterLog::open(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+        let x = log.recovered("sys-1", "x").unwrap();
+        assert_eq!((x.tag, &x.value, x.read_id), (Tag::new(50, 1), &value(50), 50));
+        let y = log.recovered("sys-1", "y").unwrap();
+        assert_eq!((y.tag, &y.value, y.read_id), (Tag::new(50, 2), &value(-50), 0));
+    }
+}
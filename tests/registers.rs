@@ -0,0 +1,87 @@
+mod common;
+
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+use dp_algo::hub::{HubEvent, HubHandle};
+use dp_algo::protobuf::message::Type;
+use dp_algo::register_manager::RegisterKind;
+use dp_algo::{protobuf, Envelope, NodeHandle};
+use common::shut_down;
+
+fn start(kind: RegisterKind) -> (HubHandle, NodeHandle) {
+    common::start("reg", 3, |config| config.register_kinds = HashMap::from([("x".to_string(), kind)]))
+}
+
+/// Have the designated writer of (1,N) registers, which any process is for (N,N) ones, write `value` to x
+fn write(node: &NodeHandle, value: Option<protobuf::Value>) {
+    let writer = node.processes().iter()
+        .min_by_key(|process| process.state.rank)
+        .map(|process| process.state.own_process().unwrap().index as usize)
+        .unwrap();
+
+    let mut wrapper = Envelope::with_shipping_label(Type::AppWrite);
+    wrapper.app_write = Option::from(protobuf::AppWrite { register: "x".to_string(), value });
+    wrapper.to_abstraction_id = "app".to_string();
+    node.queue(writer).unwrap().send(wrapper).unwrap();
+}
+
+#[cfg(feature = "write-rejections")]
+fn wait_for_rejection(hub: &HubHandle) -> String {
+    common::wait_for("the write to be rejected", || hub.events().into_iter().find_map(|event| match event {
+        HubEvent::WriteRejected { register, reason, .. } if register == "x" => Some(reason),
+        _ => None,
+    }))
+}
+
+/// Writing the undefined value is answered with a rejection instead of leaving the hub waiting
+#[cfg(feature = "write-rejections")]
+fn reject_undefined_writes(kind: RegisterKind) {
+    let (hub, node) = start(kind);
+    write(&node, None);
+    write(&node, Some(protobuf::Value::default()));
+
+    assert!(wait_for_rejection(&hub).contains("undefined"));
+    thread::sleep(Duration::from_millis(200));
+    let rejections = hub.events().into_iter()
+        .filter(|event| matches!(event, HubEvent::WriteRejected { .. }))
+        .count();
+    assert_eq!(rejections, 2);
+
+    shut_down(hub, node);
+}
+
+/// Without AppWriteRejected, writing the undefined value is answered with the AppWriteReturn the reference
+/// hub expects, and the register keeps what it held
+#[cfg(not(feature = "write-rejections"))]
+fn reject_undefined_writes(kind: RegisterKind) {
+    let (hub, node) = start(kind);
+    write(&node, None);
+    write(&node, Some(protobuf::Value::default()));
+
+    let returned = |hub: &HubHandle| hub.events().into_iter()
+        .filter(|event| matches!(event, HubEvent::WriteReturned { register, .. } if register == "x"))
+        .count();
+    common::wait_until("both writes to return", || returned(&hub) >= 2);
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(returned(&hub), 2);
+    assert!(node.processes().iter().all(|process| process.registers.iter().all(|register| !register.value.defined)));
+
+    shut_down(hub, node);
+}
+
+#[test]
+fn atomic_registers_reject_undefined_writes() {
+    reject_undefined_writes(RegisterKind::Nnar);
+}
+
+#[test]
+fn regular_registers_reject_undefined_writes() {
+    reject_undefined_writes(RegisterKind::ReadOneWriteAll);
+    reject_undefined_writes(RegisterKind::MajorityVoting);
+}
+
+#[test]
+fn single_writer_atomic_registers_reject_undefined_writes() {
+    reject_undefined_writes(RegisterKind::SingleWriterAtomic);
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+use dp_algo::authenticated_link::LinkKeys;
+use dp_algo::broadcast_manager::BroadcastGuarantee;
+use dp_algo::hub::{HubEvent, HubHandle};
+use dp_algo::protobuf::message::Type;
+use dp_algo::protobuf::ProcessId;
+use dp_algo::reliable_broadcast_manager::{EAGER_ABSTRACTION_ID, LAZY_ABSTRACTION_ID};
+use dp_algo::uniform_reliable_broadcast_manager::{ALL_ACK_ABSTRACTION_ID, MAJORITY_ACK_ABSTRACTION_ID};
+use dp_algo::value_codec::{I32Codec, ValueCodec};
+use dp_algo::{protobuf, Envelope, NetworkService};
+use common::{address, free_ports, send, shut_down, wait_until};
+
+/// The RbInternalData a source on `source_port` relays when it broadcasts `value` under `abstraction_id`
+fn rb_data(abstraction_id: &str, source_port: u16, value: i32) -> Envelope {
+    let mut app_value = Envelope::with_shipping_label(Type::AppValue);
+    app_value.app_value = Option::from(protobuf::AppValue { value: Option::from(I32Codec::encode(&value)), ..Default::default() });
+    app_value.to_abstraction_id = "app".to_string();
+
+    let mut envelope = Envelope::with_shipping_label(Type::RbInternalData);
+    envelope.rb_internal_data = NetworkService::wrap_envelope_contents(protobuf::RbInternalData {
+        message_id: app_value.message_uuid.clone(),
+        source: Option::from(ProcessId { host: "127.0.0.1".to_string(), port: source_port as i32, ..Default::default() }),
+        message: NetworkService::wrap_envelope_contents(app_value),
+    });
+    envelope.from_abstraction_id = abstraction_id.to_string();
+    envelope.to_abstraction_id = abstraction_id.to_string();
+    envelope
+}
+
+/// Values delivered so far, as (process, value), sorted by process
+fn deliveries(hub: &HubHandle) -> Vec<(String, i32)> {
+    let mut delivered = hub.events().into_iter()
+        .filter_map(|event| match event {
+            HubEvent::Delivered { process, value, .. } => Some((process, value.v)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    delivered.sort();
+    delivered
+}
+
+/// A fourth process, which the test plays and which never runs otherwise, crashes after its message reached
+/// only the first process. Every correct process delivers it all the same, exactly once; under uniform
+/// broadcast, none of them does before enough of the others relayed it.
+fn deliver_from_a_crashed_source(abstraction_id: &str) {
+    let ports = free_ports(1);
+    let (hub, node) = common::start_on("rb", vec![address(0); 3], &ports, |_| {});
+
+    send(rb_data(abstraction_id, ports[0], 42), node.own_addresses()[0], ports[0], &LinkKeys::default());
+
+    wait_until("every correct process to deliver", || deliveries(&hub).len() >= 3);
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(deliveries(&hub), vec![
+        ("sys-1/rb-1".to_string(), 42), ("sys-1/rb-2".to_string(), 42), ("sys-1/rb-3".to_string(), 42),
+    ]);
+
+    shut_down(hub, node);
+}
+
+/// The first process relays the message as soon as it delivers it
+#[test]
+fn eager_broadcast_delivers_the_message_of_a_crashed_source() {
+    deliver_from_a_crashed_source(EAGER_ABSTRACTION_ID);
+}
+
+/// The first process only relays the message once its failure detector reports the source crashed
+#[test]
+fn lazy_broadcast_delivers_the_message_of_a_crashed_source() {
+    deliver_from_a_crashed_source(LAZY_ABSTRACTION_ID);
+}
+
+/// The first process acknowledges the message, and waits for the others' acknowledgements and the source's crash
+#[test]
+fn all_ack_broadcast_delivers_the_message_of_a_crashed_source() {
+    deliver_from_a_crashed_source(ALL_ACK_ABSTRACTION_ID);
+}
+
+/// The three correct processes are a majority of the four
+#[test]
+fn majority_ack_broadcast_delivers_the_message_of_a_crashed_source() {
+    deliver_from_a_crashed_source(MAJORITY_ACK_ABSTRACTION_ID);
+}
+
+/// Have every process broadcast values over `guarantee`, and check that all of them deliver all the values
+fn broadcast_uniformly(guarantee: BroadcastGuarantee) {
+    let (hub, node) = common::start("urb", 3, |config| config.broadcast_guarantee = guarantee);
+
+    for value in 1..=3 {
+        for process in ["urb-1", "urb-2", "urb-3"] {
+            hub.broadcast(process, value).unwrap();
+        }
+    }
+    wait_until("every value to be delivered everywhere", || deliveries(&hub).len() == 27);
+    assert_eq!(hub.check_deliveries(guarantee), Vec::<String>::new());
+
+    shut_down(hub, node);
+}
+
+#[test]
+fn all_ack_broadcasts_pass_the_hub_check() {
+    broadcast_uniformly(BroadcastGuarantee::UniformAllAck);
+}
+
+#[test]
+fn majority_ack_broadcasts_pass_the_hub_check() {
+    broadcast_uniformly(BroadcastGuarantee::UniformMajorityAck);
+}
+
+/// Two processes out of five broadcast a value while the other three never run. All-Ack delivers it once
+/// the three are detected as crashed; Majority-Ack cannot tell that two processes that have the value are
+/// not about to crash as well, so it delivers nothing rather than risk that only a crashed process did.
+fn deliver_without_a_correct_majority(guarantee: BroadcastGuarantee) -> Vec<(String, i32)> {
+    let (hub, node) = common::start_on("urb", vec![address(0); 2], &free_ports(3), |config| {
+        config.broadcast_guarantee = guarantee;
+    });
+
+    hub.broadcast("urb-1", 42).unwrap();
+    if guarantee == BroadcastGuarantee::UniformAllAck {
+        wait_until("both processes to deliver", || deliveries(&hub).len() >= 2);
+    }
+    thread::sleep(Duration::from_millis(500));
+    let delivered = deliveries(&hub);
+
+    shut_down(hub, node);
+    delivered
+}
+
+#[test]
+fn all_ack_broadcast_delivers_without_a_correct_majority() {
+    assert_eq!(deliver_without_a_correct_majority(BroadcastGuarantee::UniformAllAck), vec![
+        ("sys-1/urb-1".to_string(), 42), ("sys-1/urb-2".to_string(), 42),
+    ]);
+}
+
+#[test]
+fn majority_ack_broadcast_waits_for_a_majority() {
+    assert_eq!(deliver_without_a_correct_majority(BroadcastGuarantee::UniformMajorityAck), vec![]);
+}
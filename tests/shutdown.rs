@@ -0,0 +1,32 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+use common::{free_ports, shut_down};
+
+/// A write that can never gather its majority is abandoned at shutdown, and the report says so
+#[test]
+fn abandoned_operations_make_the_shutdown_unclean() {
+    // Two more processes join and are never heard from again, leaving the running one without a majority
+    let (hub, node) = common::start_on("down", vec![common::address(0)], &free_ports(2), |_| {});
+
+    hub.write("x", 1, &["down-1"]).unwrap();
+    thread::sleep(Duration::from_millis(300));
+
+    let report = node.shutdown();
+    hub.shutdown();
+    assert_eq!(report.failed_operations, 1);
+    assert_eq!(report.panicked_threads, 0);
+    assert!(!report.is_clean());
+}
+
+/// Shutting down right after a burst of broadcasts, whose relays go to the node's own processes, stays clean
+#[test]
+fn draining_a_backlog_is_a_clean_shutdown() {
+    let (hub, node) = common::start("drain", 3, |_| {});
+
+    for value in 0..20 {
+        hub.broadcast("drain-1", value).unwrap();
+    }
+    shut_down(hub, node);
+}
@@ -0,0 +1,52 @@
+use dp_algo::tag::Tag;
+
+#[test]
+fn next_increments_the_timestamp_and_takes_the_writer_rank() {
+    assert_eq!(Tag::new(4, 2).next(7), Ok(Tag::new(5, 7)));
+    assert_eq!(Tag::default().next(0), Ok(Tag::new(1, 0)));
+}
+
+#[test]
+fn next_refuses_to_overflow_the_timestamp() {
+    assert!(Tag::new(u64::MAX - 1, 1).next(1).is_ok());
+    assert!(Tag::new(u64::MAX, 1).next(1).is_err());
+}
+
+#[test]
+fn next_refuses_negative_writer_ranks() {
+    assert!(Tag::new(1, 1).next(-1).is_err());
+}
+
+#[test]
+fn from_wire_refuses_negative_values() {
+    assert_eq!(Tag::from_wire(3, 2), Ok(Tag::new(3, 2)));
+    assert_eq!(Tag::from_wire(0, 0), Ok(Tag::default()));
+    assert!(Tag::from_wire(-1, 2).is_err());
+    assert!(Tag::from_wire(3, -1).is_err());
+    assert!(Tag::from_wire(i32::MIN, i32::MIN).is_err());
+}
+
+#[test]
+fn to_wire_refuses_tags_that_outgrew_the_message_fields() {
+    assert_eq!(Tag::new(i32::MAX as u64, 3).to_wire(), Ok((i32::MAX, 3)));
+    assert!(Tag::new(i32::MAX as u64 + 1, 3).to_wire().is_err());
+    assert_eq!(Tag::new(3, i32::MAX as u32).to_wire(), Ok((3, i32::MAX)));
+    assert!(Tag::new(3, i32::MAX as u32 + 1).to_wire().is_err());
+}
+
+#[test]
+fn the_wire_round_trip_keeps_the_tag() {
+    for tag in [Tag::default(), Tag::new(1, 2), Tag::new(i32::MAX as u64, i32::MAX as u32)] {
+        let (timestamp, writer_rank) = tag.to_wire().unwrap();
+        assert_eq!(Tag::from_wire(timestamp, writer_rank), Ok(tag));
+    }
+}
+
+/// Timestamps decide first, and only equal timestamps fall back to the writer rank
+#[test]
+fn tags_order_by_timestamp_then_rank() {
+    assert!(Tag::new(2, 1) > Tag::new(1, 9));
+    assert!(Tag::new(2, 3) > Tag::new(2, 1));
+    assert_eq!(Tag::new(2, 3).cmp(&Tag::new(2, 3)), std::cmp::Ordering::Equal);
+    assert_eq!([Tag::new(2, 1), Tag::new(1, 9), Tag::new(2, 3)].into_iter().max(), Some(Tag::new(2, 3)));
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+use dp_algo::hub::{HubEvent, HubHandle};
+use dp_algo::protobuf::message::Type;
+use dp_algo::value_codec::{I32Codec, ValueCodec};
+use dp_algo::{protobuf, Envelope, NodeHandle};
+use common::{free_ports, shut_down, wait_until};
+
+/// Start a hub and three processes; with `absent`, also register a fourth process that is not running,
+/// as if it crashed right after joining
+fn start(absent: bool) -> (HubHandle, NodeHandle) {
+    let absent_ports = if absent { free_ports(1) } else { vec![] };
+    common::start_on("trb", vec![common::address(0); 3], &absent_ports, |_| {})
+}
+
+/// What each process delivered on `topic`, as (process, sender, value), sorted by process
+fn deliveries(hub: &HubHandle, topic: &str) -> Vec<(String, String, Option<i32>)> {
+    let mut deliveries = hub.events().into_iter()
+        .filter_map(|event| match event {
+            HubEvent::TrbDelivered { process, topic: delivered_topic, sender, value } if delivered_topic == topic =>
+                Some((process, sender, value.map(|value| value.v))),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    deliveries.sort();
+    deliveries
+}
+
+fn wait_for_deliveries(hub: &HubHandle, topic: &str, count: usize) -> Vec<(String, String, Option<i32>)> {
+    wait_until(&format!("{} deliveries on {}", count, topic), || deliveries(hub, topic).len() >= count);
+    thread::sleep(Duration::from_millis(200));
+    deliveries(hub, topic)
+}
+
+#[test]
+fn the_value_of_a_correct_sender_is_delivered_everywhere() {
+    let (hub, node) = start(false);
+    hub.trb("t", "trb-2", 17).unwrap();
+
+    assert_eq!(wait_for_deliveries(&hub, "t", 3), vec![
+        ("sys-1/trb-1".to_string(), "trb-2".to_string(), Some(17)),
+        ("sys-1/trb-2".to_string(), "trb-2".to_string(), Some(17)),
+        ("sys-1/trb-3".to_string(), "trb-2".to_string(), Some(17)),
+    ]);
+
+    shut_down(hub, node);
+}
+
+/// The designated sender is not running, so the failure detector reports it and everyone delivers that it failed
+#[test]
+fn a_crashed_sender_is_delivered_as_failed() {
+    let (hub, node) = start(true);
+    hub.trb("t", "trb-4", 17).unwrap();
+
+    assert_eq!(wait_for_deliveries(&hub, "t", 3), vec![
+        ("sys-1/trb-1".to_string(), "trb-4".to_string(), None),
+        ("sys-1/trb-2".to_string(), "trb-4".to_string(), None),
+        ("sys-1/trb-3".to_string(), "trb-4".to_string(), None),
+    ]);
+
+    shut_down(hub, node);
+}
+
+/// The designated sender crashed before broadcasting, and only the first process was asked to take part: the
+/// others hear of the instance from its consensus, which tells them whom it waits for, and deliver that it failed
+#[test]
+fn processes_that_were_not_told_deliver_that_a_crashed_sender_failed() {
+    let (hub, node) = start(true);
+    let sender = hub.system().1.into_iter().find(|member| member.index == 4).unwrap();
+
+    let mut wrapper = Envelope::with_shipping_label(Type::AppTrbBroadcast);
+    wrapper.app_trb_broadcast = Option::from(protobuf::AppTrbBroadcast {
+        topic: "t".to_string(),
+        sender: Option::from(sender),
+        value: Option::from(I32Codec::encode(&17)),
+    });
+    wrapper.to_abstraction_id = "app".to_string();
+    node.queue(1).unwrap().send(wrapper).unwrap();
+
+    assert_eq!(wait_for_deliveries(&hub, "t", 3), vec![
+        ("sys-1/trb-1".to_string(), "trb-4".to_string(), None),
+        ("sys-1/trb-2".to_string(), "trb-4".to_string(), None),
+        ("sys-1/trb-3".to_string(), "trb-4".to_string(), None),
+    ]);
+
+    shut_down(hub, node);
+}
+
+/// Instances on different topics, with different senders, do not get in each other's way
+#[test]
+fn topics_are_independent_instances() {
+    let (hub, node) = start(false);
+    hub.trb("a", "trb-1", 1).unwrap();
+    hub.trb("b", "trb-3", 3).unwrap();
+
+    let values = |deliveries: Vec<(String, String, Option<i32>)>| deliveries.into_iter()
+        .map(|(_, sender, value)| (sender, value))
+        .collect::<Vec<_>>();
+    assert_eq!(values(wait_for_deliveries(&hub, "a", 3)), vec![("trb-1".to_string(), Some(1)); 3]);
+    assert_eq!(values(wait_for_deliveries(&hub, "b", 3)), vec![("trb-3".to_string(), Some(3)); 3]);
+
+    shut_down(hub, node);
+}